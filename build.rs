@@ -6,4 +6,16 @@ fn main() {
         let git_hash = String::from_utf8(output.stdout).unwrap();
         println!("cargo:rustc-env=GIT_HASH={}", git_hash);
     }
+    if let Some(version) = locked_version("reis") {
+        println!("cargo:rustc-env=REIS_VERSION={}", version);
+    }
+}
+
+/// The version of the package `name` that Cargo.lock resolved.
+fn locked_version(name: &str) -> Option<String> {
+    let lock = std::fs::read_to_string("Cargo.lock").ok()?;
+    let mut lines = lock.lines();
+    lines.find(|line| *line == format!("name = \"{name}\""))?;
+    let version = lines.next()?.strip_prefix("version = \"")?;
+    Some(version.trim_end_matches('"').to_string())
 }
@@ -0,0 +1,346 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use serde::{Deserialize, Serialize};
+
+/// Settings for the EIS receiver injecting remote desktop input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EisConfig {
+    /// Reuse the surface found under the pointer for motion that stays close to the last lookup
+    pub surface_cache: bool,
+    /// Distance in logical pixels the pointer may travel before the cached surface is recomputed
+    pub surface_cache_threshold: f64,
+    /// Turn displays back on and reset the idle timer when a remote session connects
+    pub wake_displays_on_connect: bool,
+    /// How absolute pointer and touch coordinates are snapped to the pixel grid
+    pub coordinate_rounding: CoordinateRounding,
+    /// What absolute pointer and touch coordinates are relative to
+    pub absolute_origin: AbsoluteOrigin,
+    /// What happens when several remote sessions drive the pointer at once
+    pub pointer_arbitration: PointerArbitration,
+    /// Idle time in milliseconds after which an exclusively held pointer is released
+    pub pointer_release_timeout_ms: u64,
+    /// Lift remote touches that saw no motion or up for this many milliseconds; 0 disables
+    pub touch_timeout_ms: u64,
+    /// Disconnect connections that send no request for this many milliseconds; 0 disables
+    pub idle_timeout_ms: u64,
+    /// Keep the system from idling while a session injected input within this many milliseconds; 0 disables
+    pub idle_inhibit_window_ms: u64,
+    /// Device capabilities remote clients are not given, even if they ask for them
+    pub denied_capabilities: Vec<EisCapability>,
+    /// Ignore presses of keys already held and releases of keys not held, for lossy links
+    pub dedup_key_events: bool,
+    /// Maximum number of remote sessions connected at the same time
+    pub max_connections: usize,
+    /// Milliseconds that must pass between accepting two connections; 0 disables
+    pub min_connection_interval_ms: u64,
+    /// What happens to connections arriving within `min_connection_interval_ms` of the last
+    pub connection_pacing: ConnectionPacing,
+    /// Area relative pointer motion is confined to
+    pub pointer_clamp: PointerClamp,
+    /// Furthest a session may move the pointer with one absolute motion, in logical pixels; 0 disables
+    pub max_absolute_jump: f64,
+    /// What happens to absolute motion exceeding `max_absolute_jump`
+    pub absolute_jump_policy: JumpPolicy,
+    /// Input events a session may inject per second before excess events are dropped; 0 disables
+    pub max_events_per_second: u32,
+    /// Order in which the input events of one EIS frame are applied
+    pub frame_ordering: FrameOrdering,
+    /// How strongly the spacing of client frame timestamps is regularized, from 0 (off) to 0.99
+    pub timestamp_smoothing: f64,
+    /// Milliseconds pointer motion may wait for the end of its frame; 0 injects it at the end of each loop iteration
+    pub motion_coalesce_ms: u64,
+    /// Which pointer motion decides the position when a frame has both relative and absolute motion
+    pub motion_priority: MotionPriority,
+    /// Events of a canonically ordered frame applied per loop iteration, the rest waits; 0 is unlimited
+    pub max_events_per_frame: usize,
+    /// Multiplier applied to relative pointer motion
+    pub relative_motion_scale: f64,
+    /// How much faster relative motion gets with the speed of the client's device; 0 disables
+    pub relative_motion_acceleration: f64,
+    /// Highest touch ID a session may use, between 1 and 4096
+    pub max_touch_id: u32,
+    /// How much each scroll of a quick succession adds to the scroll distance; 0 disables
+    pub scroll_acceleration: f64,
+    /// Time in milliseconds after which a streak of scrolls has lost half its acceleration
+    pub scroll_acceleration_decay_ms: u32,
+    /// What happens to pointer and touch input over an overlay that doesn't accept input there
+    pub rejected_input: RejectedInput,
+    /// Input events a session may inject within `event_budget_window_s`; 0 disables
+    pub event_budget: u64,
+    /// Length of the rolling window `event_budget` applies to, in seconds
+    pub event_budget_window_s: u64,
+    /// What happens to a session exceeding `event_budget`
+    pub event_budget_policy: BudgetPolicy,
+    /// Seat receiving input that neither a bound seat nor a position routes
+    pub seat_fallback: SeatFallback,
+    /// Input the ContextMenu D-Bus method injects
+    pub context_menu: ContextMenuInput,
+    /// Whether a touch-down raises and focuses the touched window
+    pub touch_focus: TouchFocus,
+    /// Key combinations the paste and other editing D-Bus methods press
+    pub editing_shortcuts: EditingShortcuts,
+    /// Key combination the CloseFocusedWindow D-Bus method presses
+    pub close_shortcut: Shortcut,
+    /// Characters of a client's name shown in the log line announcing its connection
+    pub client_name_log_len: usize,
+    /// Keyboards, pointers and touch devices each that one session may bind; 0 disables
+    pub max_devices_per_kind: u32,
+    /// Users besides the portal's that may hand over EIS sockets, trusting every process they run to inject input; empty allows none
+    pub allowed_socket_uids: Vec<u32>,
+}
+
+/// Rounding applied to absolute coordinates before they are injected.
+///
+/// Defaults to [`CoordinateRounding::None`], passing fractional positions through unchanged.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoordinateRounding {
+    #[default]
+    None,
+    Floor,
+    Round,
+    Ceil,
+}
+
+impl Default for EisConfig {
+    fn default() -> Self {
+        Self {
+            surface_cache: false,
+            surface_cache_threshold: 2.0,
+            wake_displays_on_connect: false,
+            coordinate_rounding: CoordinateRounding::None,
+            absolute_origin: AbsoluteOrigin::PerOutput,
+            pointer_arbitration: PointerArbitration::Shared,
+            pointer_release_timeout_ms: 1000,
+            touch_timeout_ms: 0,
+            idle_timeout_ms: 300_000,
+            idle_inhibit_window_ms: 0,
+            denied_capabilities: Vec::new(),
+            dedup_key_events: false,
+            max_connections: 8,
+            min_connection_interval_ms: 0,
+            connection_pacing: ConnectionPacing::Refuse,
+            pointer_clamp: PointerClamp::PerOutput,
+            max_absolute_jump: 0.0,
+            absolute_jump_policy: JumpPolicy::Clamp,
+            max_events_per_second: 10000,
+            frame_ordering: FrameOrdering::Arrival,
+            timestamp_smoothing: 0.0,
+            motion_coalesce_ms: 0,
+            motion_priority: MotionPriority::InOrder,
+            max_events_per_frame: 512,
+            relative_motion_scale: 1.0,
+            relative_motion_acceleration: 0.0,
+            max_touch_id: 256,
+            scroll_acceleration: 0.0,
+            scroll_acceleration_decay_ms: 200,
+            rejected_input: RejectedInput::FallThrough,
+            event_budget: 0,
+            event_budget_window_s: 3600,
+            event_budget_policy: BudgetPolicy::Throttle,
+            seat_fallback: SeatFallback::LastActive,
+            context_menu: ContextMenuInput::MenuKey,
+            touch_focus: TouchFocus::Keep,
+            editing_shortcuts: EditingShortcuts::default(),
+            close_shortcut: Shortcut {
+                modifiers: vec![KEY_LEFTALT],
+                key: KEY_F4,
+            },
+            client_name_log_len: 128,
+            max_devices_per_kind: 4,
+            allowed_socket_uids: Vec::new(),
+        }
+    }
+}
+
+/// Policy for sessions competing over the same seat's pointer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PointerArbitration {
+    /// Every session moves the pointer, the last event wins
+    #[default]
+    Shared,
+    /// The first session to move the pointer holds it until it disconnects or goes idle
+    Exclusive,
+}
+
+/// How relative pointer motion is kept within the output layout.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PointerClamp {
+    /// The pointer stays on the output it is on unless a motion lands on another one
+    #[default]
+    PerOutput,
+    /// The pointer moves within the bounding box of all outputs, crossing gaps between them
+    Union,
+}
+
+/// Coordinate space of absolute positions sent by EIS clients.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AbsoluteOrigin {
+    /// The top-left of the session's target output, or of the global space without one
+    #[default]
+    PerOutput,
+    /// The top-left of the bounding box of all outputs, for captures of the whole desktop
+    VirtualDesktop,
+}
+
+/// Handling of connections arriving faster than the minimum connection interval.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionPacing {
+    /// Refuse the connection
+    #[default]
+    Refuse,
+    /// Accept the connection once the interval has passed
+    Queue,
+}
+
+/// Handling of absolute motion further than the configured maximum jump.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JumpPolicy {
+    /// Move the maximum distance towards the target
+    #[default]
+    Clamp,
+    /// Drop the motion
+    Reject,
+}
+
+/// Handling of input at a position covered by a layer surface that doesn't accept input there,
+/// such as an input-disabled overlay.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectedInput {
+    /// Deliver the input to the surface below, as for local input
+    #[default]
+    FallThrough,
+    /// Drop the input
+    Drop,
+}
+
+/// Handling of sessions that used up their event budget.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BudgetPolicy {
+    /// Drop input until older events leave the budget window
+    #[default]
+    Throttle,
+    /// Disconnect the session
+    Disconnect,
+}
+
+/// Seat for input that isn't routed to a seat otherwise.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SeatFallback {
+    /// The seat that last received input
+    #[default]
+    LastActive,
+    /// The seat with this name, dropping the input if there is none
+    Named(String),
+    /// Drop the input
+    Refuse,
+}
+
+/// Input opening a context menu on behalf of a session.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContextMenuInput {
+    /// Press the Menu key on the session's keyboard
+    #[default]
+    MenuKey,
+    /// Click the right button at the current pointer location
+    RightClick,
+}
+
+/// Keyboard focus handling when a session touches a window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TouchFocus {
+    /// Leave keyboard focus where it is
+    #[default]
+    Keep,
+    /// Raise and focus the touched window, as a click does
+    Raise,
+}
+
+/// A key combination as evdev key codes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shortcut {
+    /// Keys held down around `key`, pressed in order and released in reverse
+    pub modifiers: Vec<u32>,
+    pub key: u32,
+}
+
+impl Shortcut {
+    fn ctrl(key: u32) -> Self {
+        Self {
+            modifiers: vec![KEY_LEFTCTRL],
+            key,
+        }
+    }
+}
+
+const KEY_LEFTCTRL: u32 = 29;
+const KEY_LEFTSHIFT: u32 = 42;
+const KEY_LEFTALT: u32 = 56;
+const KEY_F4: u32 = 62;
+
+/// Shortcuts of the semantic editing commands, for layouts or apps that don't use the usual ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditingShortcuts {
+    /// Ctrl+A by default
+    pub select_all: Shortcut,
+    /// Ctrl+C by default
+    pub copy: Shortcut,
+    /// Ctrl+X by default
+    pub cut: Shortcut,
+    /// Ctrl+V by default
+    pub paste: Shortcut,
+    /// Ctrl+Z by default
+    pub undo: Shortcut,
+    /// Ctrl+Shift+Z by default
+    pub redo: Shortcut,
+}
+
+impl Default for EditingShortcuts {
+    fn default() -> Self {
+        Self {
+            select_all: Shortcut::ctrl(30),
+            copy: Shortcut::ctrl(46),
+            cut: Shortcut::ctrl(45),
+            paste: Shortcut::ctrl(47),
+            undo: Shortcut::ctrl(44),
+            redo: Shortcut {
+                modifiers: vec![KEY_LEFTCTRL, KEY_LEFTSHIFT],
+                key: 44,
+            },
+        }
+    }
+}
+
+/// Ordering of the input events within an EIS frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrameOrdering {
+    /// Apply events as they arrive
+    #[default]
+    Arrival,
+    /// Hold events until the frame ends, then apply modifiers, keys, pointer motion, buttons, scroll and touch in that order
+    Canonical,
+}
+
+/// Handling of relative and absolute pointer motion arriving in the same EIS frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MotionPriority {
+    /// Apply both kinds in the order they arrived
+    #[default]
+    InOrder,
+    /// Apply the absolute position and drop the relative motion
+    Absolute,
+    /// Apply the relative motion and drop the absolute position
+    Relative,
+}
+
+/// Device capabilities an EIS client can ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EisCapability {
+    Keyboard,
+    Pointer,
+    PointerAbsolute,
+    Button,
+    Scroll,
+    Touch,
+}
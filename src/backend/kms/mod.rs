@@ -369,6 +369,9 @@ impl State {
             }
         }
 
+        // resume remote input
+        crate::input::eis::session_active_changed(self, true);
+
         // update state and schedule new render,
         // after processing the rest of the pending event loop events
         let dispatcher = dispatcher.clone();
@@ -417,6 +420,7 @@ impl State {
     }
 
     fn pause_session(&mut self) {
+        crate::input::eis::session_active_changed(self, false);
         let backend = self.backend.kms();
         backend.libinput.suspend();
         for device in backend.drm_devices.values_mut() {
@@ -35,6 +35,7 @@ use std::{
     io::Write,
     path::PathBuf,
     sync::{Arc, atomic::AtomicBool},
+    time::Duration,
 };
 use tracing::{error, warn};
 
@@ -47,6 +48,7 @@ pub use cosmic_comp_config::EdidProduct;
 use cosmic_comp_config::{
     AppearanceConfig, CosmicCompConfig, KeyboardConfig, TileBehavior, XkbConfig, XwaylandDescaling,
     XwaylandEavesdropping, ZoomConfig,
+    eis::EisConfig,
     input::{DeviceState as InputDeviceState, InputConfig, TouchpadOverride},
     output::comp::{
         OutputConfig, OutputInfo, OutputState, OutputsConfig, TransformDef, load_outputs,
@@ -825,7 +827,9 @@ fn config_changed(config: cosmic_config::Config, keys: Vec<String>, state: &mut
                         }
                     }
                 }
-                state.common.config.cosmic_conf.xkb_config = value;
+                let previous =
+                    std::mem::replace(&mut state.common.config.cosmic_conf.xkb_config, value);
+                crate::input::eis::xkb_config_changed(state, &previous);
             }
             "keyboard_config" => {
                 let value = get_config::<KeyboardConfig>(&config, "keyboard_config");
@@ -952,6 +956,21 @@ fn config_changed(config: cosmic_config::Config, keys: Vec<String>, state: &mut
                     }
                 }
             }
+            "eis_config" => {
+                let new = get_config::<EisConfig>(&config, "eis_config");
+                if new != state.common.config.cosmic_conf.eis_config {
+                    let max_connections = new.max_connections;
+                    let max_touch_id = new.max_touch_id;
+                    let idle_timeout = Duration::from_millis(new.idle_timeout_ms);
+                    state.common.config.cosmic_conf.eis_config = new;
+                    if let Some(eis_state) = state.common.eis_state.as_mut() {
+                        eis_state.invalidate_surface_cache();
+                        eis_state.set_max_connections(max_connections);
+                        eis_state.set_max_touch_id(max_touch_id);
+                        eis_state.set_idle_timeout(idle_timeout);
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -4,117 +4,1005 @@
 //! portal. The portal creates a UNIX socket pair and sends the server-side fd
 //! to the compositor via this interface.
 
-use calloop::channel;
+use calloop::{
+    channel,
+    timer::{TimeoutAction, Timer},
+};
+use futures_channel::oneshot;
 use futures_executor::ThreadPool;
-use std::os::unix::net::UnixStream;
+use std::{
+    collections::HashMap,
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::net::UnixStream,
+    },
+    time::{Duration, Instant},
+};
 use tracing::{error, info, warn};
-use zbus::message::Header;
+use zbus::{message::Header, names::UniqueName, object_server::SignalEmitter};
+
+use crate::input::eis::{
+    Calibration, Caller, CapturedEvent, ConnectionRejected, EditingCommand, EisCommand, EisError,
+    FocusInfo, KeymapInfo, SessionEvent, SessionId,
+};
+
+/// A socket for the compositor's calloop, the seat it is for, if any, and
+/// where to report whether it was accepted.
+type EisSocket = (
+    UnixStream,
+    Option<String>,
+    oneshot::Sender<Result<SessionId, ConnectionRejected>>,
+);
+
+/// `(id, client_name, label, capabilities, connected_since, injected)` of a
+/// session as listed by `ListSessionsWithCounts`.
+type SessionWithCounts = (
+    SessionId,
+    String,
+    String,
+    Vec<String>,
+    u64,
+    HashMap<String, u64>,
+);
 
 /// Channel sender for delivering EIS sockets to the compositor's calloop.
 #[derive(Clone)]
 pub struct EisSocketSender {
-    tx: channel::Sender<UnixStream>,
+    tx: channel::Sender<EisSocket>,
 }
 
 impl EisSocketSender {
-    pub fn new(tx: channel::Sender<UnixStream>) -> Self {
+    pub fn new(tx: channel::Sender<EisSocket>) -> Self {
         Self { tx }
     }
 }
 
-/// Allowed D-Bus well-known names that may call `AcceptEisSocket`.
+/// Allowed D-Bus well-known names that may call `AcceptEisSocket`. Other
+/// callers may only hand over sockets if the configured user allowlist admits
+/// them.
 const ALLOWED_CALLERS: &[&str] = &["org.freedesktop.impl.portal.desktop.cosmic"];
 
 /// D-Bus interface for the compositor to accept EIS socket fds.
 pub struct CosmicCompEis {
     sender: EisSocketSender,
+    commands: channel::Sender<EisCommand>,
 }
 
 impl CosmicCompEis {
-    pub fn new(sender: EisSocketSender) -> Self {
-        Self { sender }
+    pub fn new(sender: EisSocketSender, commands: channel::Sender<EisCommand>) -> Self {
+        Self { sender, commands }
+    }
+
+    /// Forward a command to the compositor's event loop and await its reply,
+    /// without holding up the executor meanwhile.
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> EisCommand,
+    ) -> zbus::fdo::Result<T> {
+        let (tx, rx) = oneshot::channel();
+        self.commands
+            .send(command(tx))
+            .map_err(|_| zbus::fdo::Error::Failed("Compositor EIS channel closed".to_string()))?;
+        // Canceled once the compositor drops the command unanswered
+        rx.await
+            .map_err(|_| zbus::fdo::Error::Failed("EIS input receiver is not running".to_string()))
+    }
+
+    /// Press an editing command's shortcut for a session.
+    async fn edit(&self, session: SessionId, command: EditingCommand) -> zbus::fdo::Result<bool> {
+        let pressed = self
+            .request(|sync| EisCommand::Edit {
+                session,
+                command,
+                sync,
+            })
+            .await?;
+        pressed.ok_or_else(|| unknown_session(session))
+    }
+
+    /// Like [`authorize`], but also admit callers of the users the socket
+    /// allowlist names.
+    async fn authorize_socket(
+        &self,
+        header: &Header<'_>,
+        connection: &zbus::Connection,
+        method: &str,
+    ) -> zbus::fdo::Result<UniqueName<'static>> {
+        let (sender, portal) = portal_sender(header, connection).await?;
+        if portal {
+            return Ok(sender);
+        }
+
+        let caller = caller_credentials(connection, &sender).await;
+        let allowed = self
+            .request(|sync| EisCommand::CallerAllowed {
+                caller: caller.clone(),
+                sync,
+            })
+            .await?;
+        if allowed {
+            info!(
+                sender = sender.as_str(),
+                uid = caller.uid,
+                method,
+                "Allowing EIS socket from allowlisted caller"
+            );
+            return Ok(sender);
+        }
+
+        warn!(
+            sender = sender.as_str(),
+            uid = caller.uid,
+            method,
+            "Rejected EIS socket from caller not on the allowlist"
+        );
+        Err(not_authorized())
+    }
+
+    /// Hand a portal's EIS socket to the compositor, optionally for one seat,
+    /// and await it being set up, which may take a while for a queued
+    /// connection.
+    async fn accept(
+        &self,
+        sender: &UniqueName<'_>,
+        fd: zbus::zvariant::OwnedFd,
+        seat: Option<String>,
+    ) -> zbus::fdo::Result<()> {
+        // Verify the fd is a UNIX stream socket (not a file, pipe, etc.)
+        let raw_fd = OwnedFd::from(fd);
+        if let Err(err) = check_unix_stream(&raw_fd) {
+            warn!(
+                sender = sender.as_str(),
+                "Rejected EIS socket: fd is not a SOCK_STREAM Unix socket"
+            );
+            return Err(err);
+        }
+
+        let stream = UnixStream::from(raw_fd);
+        let (tx, rx) = oneshot::channel();
+        self.sender
+            .tx
+            .send((stream, seat, tx))
+            .map_err(|_| zbus::fdo::Error::Failed("Compositor EIS channel closed".to_string()))?;
+
+        // Wait for the compositor to set up the connection, so refusals
+        // reach the portal instead of being dropped silently
+        match rx.await {
+            Ok(Ok(session)) => {
+                info!(
+                    sender = sender.as_str(),
+                    session, "Accepted EIS socket via D-Bus"
+                );
+                Ok(())
+            }
+            Ok(Err(err)) => Err(rejection_error(err)),
+            Err(_) => Err(zbus::fdo::Error::Failed(
+                "EIS input receiver is not running".to_string(),
+            )),
+        }
     }
 }
 
+/// Verify that the caller owns one of the `ALLOWED_CALLERS` well-known names,
+/// returning its unique name.
+async fn authorize(
+    header: &Header<'_>,
+    connection: &zbus::Connection,
+    method: &str,
+) -> zbus::fdo::Result<UniqueName<'static>> {
+    let (sender, portal) = portal_sender(header, connection).await?;
+    if portal {
+        return Ok(sender);
+    }
+
+    warn!(
+        sender = sender.as_str(),
+        method, "Rejected call from unauthorized D-Bus sender"
+    );
+    Err(not_authorized())
+}
+
+/// The caller's unique name and whether it owns one of the `ALLOWED_CALLERS`
+/// well-known names.
+async fn portal_sender(
+    header: &Header<'_>,
+    connection: &zbus::Connection,
+) -> zbus::fdo::Result<(UniqueName<'static>, bool)> {
+    // Verify caller identity: resolve sender's unique name to well-known names
+    let sender = header
+        .sender()
+        .ok_or_else(|| zbus::fdo::Error::AccessDenied("no sender in D-Bus message".into()))?;
+
+    let dbus_proxy = zbus::fdo::DBusProxy::new(connection)
+        .await
+        .map_err(|e| zbus::fdo::Error::Failed(format!("D-Bus proxy error: {e}")))?;
+
+    // Check if the sender owns any of the allowed well-known names
+    for allowed in ALLOWED_CALLERS {
+        let bus_name: zbus::names::BusName<'_> = (*allowed)
+            .try_into()
+            .map_err(|e| zbus::fdo::Error::Failed(format!("invalid bus name: {e}")))?;
+        if let Ok(owner) = dbus_proxy.get_name_owner(bus_name).await
+            && owner.as_str() == sender.as_str()
+        {
+            return Ok((sender.to_owned(), true));
+        }
+    }
+    Ok((sender.to_owned(), false))
+}
+
+fn not_authorized() -> zbus::fdo::Error {
+    zbus::fdo::Error::AccessDenied("caller is not an authorized portal process".into())
+}
+
+/// Look up the user behind `sender`, as the bus daemon vouches for it, which
+/// is left out if the bus can't tell.
+async fn caller_credentials(connection: &zbus::Connection, sender: &UniqueName<'_>) -> Caller {
+    let Ok(dbus_proxy) = zbus::fdo::DBusProxy::new(connection).await else {
+        return Caller::default();
+    };
+    let name = zbus::names::BusName::from(sender.to_owned());
+    let uid = dbus_proxy.get_connection_unix_user(name).await.ok();
+    Caller { uid }
+}
+
+fn rejection_error(err: ConnectionRejected) -> zbus::fdo::Error {
+    match err {
+        ConnectionRejected::LimitReached(_)
+        | ConnectionRejected::FdPressure
+        | ConnectionRejected::TooFast(_) => zbus::fdo::Error::LimitsExceeded(err.to_string()),
+        ConnectionRejected::UnknownSeat(_) => zbus::fdo::Error::InvalidArgs(err.to_string()),
+        _ => zbus::fdo::Error::Failed(err.to_string()),
+    }
+}
+
+fn unknown_session(session: SessionId) -> zbus::fdo::Error {
+    zbus::fdo::Error::InvalidArgs(format!("no EIS session with id {session}"))
+}
+
+fn found_or_unknown(found: bool, session: SessionId) -> zbus::fdo::Result<()> {
+    if found {
+        Ok(())
+    } else {
+        Err(unknown_session(session))
+    }
+}
+
+/// Build description reported by the `Version` property, e.g.
+/// `1.0.0 (git 0123456789; reis 0.6.0; features: systemd)`, with the reis
+/// release Cargo.lock resolved.
+fn version_string() -> String {
+    let git_hash = option_env!("GIT_HASH")
+        .and_then(|hash| hash.get(0..10))
+        .unwrap_or("unknown");
+    let reis = option_env!("REIS_VERSION").unwrap_or("unknown");
+    let features = [
+        ("systemd", cfg!(feature = "systemd")),
+        ("debug", cfg!(feature = "debug")),
+        ("profile-with-tracy", cfg!(feature = "profile-with-tracy")),
+        (
+            "profile-with-tracy-gpu",
+            cfg!(feature = "profile-with-tracy-gpu"),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect::<Vec<_>>();
+    format!(
+        "{} (git {git_hash}; reis {reis}; features: {})",
+        env!("CARGO_PKG_VERSION"),
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        },
+    )
+}
+
 #[zbus::interface(name = "com.system76.CosmicComp.RemoteDesktop")]
 impl CosmicCompEis {
+    /// Version and build details of the remote desktop receiver, for bug
+    /// reports. Starts with the compositor's semantic version.
+    #[zbus(property)]
+    async fn version(&self) -> String {
+        version_string()
+    }
+
+    /// Number of open EIS connections, as a lightweight gauge next to
+    /// `ListSessions`. Changes are signalled as sessions open and close.
+    #[zbus(property)]
+    async fn active_sessions(&self) -> zbus::fdo::Result<u32> {
+        self.request(|sync| EisCommand::ActiveSessions { sync })
+            .await
+    }
+
+    /// Emitted once an EIS client completed its handshake, e.g. to show an
+    /// indicator while remote input is active.
+    #[zbus(signal)]
+    async fn session_opened(
+        ctx: SignalEmitter<'_>,
+        id: SessionId,
+        client_name: &str,
+    ) -> zbus::Result<()>;
+
+    /// Emitted when a session announced by `SessionOpened` ended.
+    #[zbus(signal)]
+    async fn session_closed(ctx: SignalEmitter<'_>, id: SessionId) -> zbus::Result<()>;
+
     /// Accept an EIS socket fd from the RemoteDesktop portal.
     /// The portal sends the server-side of a UNIX socket pair; the compositor
     /// will run an EIS receiver on it to accept emulated input events.
     ///
     /// Only callers that own an allowed D-Bus well-known name (currently the
-    /// COSMIC portal) may invoke this method.
+    /// COSMIC portal), or whose user the configured socket allowlist admits,
+    /// may invoke this method; others get `AccessDenied`.
+    ///
+    /// Returns once the compositor has set up the connection; if it refuses
+    /// the socket (e.g. `LimitsExceeded` when too many sessions are open) the
+    /// error carries the reason.
     async fn accept_eis_socket(
         &self,
         #[zbus(header)] header: Header<'_>,
         #[zbus(connection)] connection: &zbus::Connection,
         fd: zbus::zvariant::OwnedFd,
     ) -> zbus::fdo::Result<()> {
-        // Verify caller identity: resolve sender's unique name to well-known names
-        let sender = header
-            .sender()
-            .ok_or_else(|| zbus::fdo::Error::AccessDenied("no sender in D-Bus message".into()))?;
+        let sender = self
+            .authorize_socket(&header, connection, "AcceptEisSocket")
+            .await?;
+        self.accept(&sender, fd, None).await
+    }
+
+    /// Accept an EIS socket fd from the RemoteDesktop portal for the
+    /// compositor seat `seat_name`, as on multi-seat terminals. The session is
+    /// pinned to that seat, which is the only one announced to the client.
+    ///
+    /// Fails with `InvalidArgs` if there is no such seat, and otherwise like
+    /// `AcceptEisSocket`.
+    async fn accept_eis_socket_for_seat(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        fd: zbus::zvariant::OwnedFd,
+        seat_name: String,
+    ) -> zbus::fdo::Result<()> {
+        let sender = self
+            .authorize_socket(&header, connection, "AcceptEisSocketForSeat")
+            .await?;
+        self.accept(&sender, fd, Some(seat_name)).await
+    }
+
+    /// Only inject a session's input while a window with `app_id` holds
+    /// keyboard focus; events are dropped otherwise. An empty `app_id`
+    /// removes the restriction.
+    async fn set_session_required_app_id(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+        app_id: String,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "SetSessionRequiredAppId").await?;
+        let app_id = (!app_id.is_empty()).then_some(app_id);
+        let found = self
+            .request(|sync| EisCommand::SetRequiredAppId {
+                session,
+                app_id,
+                sync,
+            })
+            .await?;
+        found_or_unknown(found, session)
+    }
+
+    /// Attach a human-readable label to a session for dashboards. Control
+    /// characters are stripped and the label is capped at 64 characters; an
+    /// empty label removes it.
+    async fn set_session_label(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+        label: String,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "SetSessionLabel").await?;
+        let found = self
+            .request(|sync| EisCommand::SetLabel {
+                session,
+                label: Some(label),
+                sync,
+            })
+            .await?;
+        found_or_unknown(found, session)
+    }
+
+    /// Delay a session's input by `delay_ms` milliseconds to simulate a laggy
+    /// link, keeping the order and spacing of its events. Zero turns the delay
+    /// off again.
+    async fn set_session_input_delay(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+        delay_ms: u32,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "SetSessionInputDelay").await?;
+        let delay = (delay_ms > 0).then(|| Duration::from_millis(delay_ms.into()));
+        let found = self
+            .request(|sync| EisCommand::SetInputDelay {
+                session,
+                delay,
+                sync,
+            })
+            .await?;
+        found_or_unknown(found, session)
+    }
+
+    /// Enter or leave drain mode. While draining, `AcceptEisSocket` refuses
+    /// new sessions but existing ones keep working, e.g. ahead of a planned
+    /// restart.
+    async fn set_drain_mode(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        enabled: bool,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "SetDrainMode").await?;
+        self.request(|sync| EisCommand::SetDrainMode { enabled, sync })
+            .await
+    }
 
-        let dbus_proxy = zbus::fdo::DBusProxy::new(connection)
+    /// Start or stop capturing: EIS input that passes validation is recorded
+    /// for `GetCapturedEvents` instead of being injected, so a client's event
+    /// stream can be checked without moving the real cursor. So are the keys,
+    /// clicks and motion of methods like `Paste` and `WarpPointer`, which then
+    /// report success. Starting a capture discards the previous one. Setting
+    /// `COSMIC_EIS_CAPTURE=1` captures from startup.
+    async fn set_capture(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        enabled: bool,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "SetCapture").await?;
+        self.request(|sync| EisCommand::SetCapture { enabled, sync })
             .await
-            .map_err(|e| zbus::fdo::Error::Failed(format!("D-Bus proxy error: {e}")))?;
-
-        // Check if the sender owns any of the allowed well-known names
-        let mut authorized = false;
-        for allowed in ALLOWED_CALLERS {
-            let bus_name: zbus::names::BusName<'_> = (*allowed)
-                .try_into()
-                .map_err(|e| zbus::fdo::Error::Failed(format!("invalid bus name: {e}")))?;
-            if let Ok(owner) = dbus_proxy.get_name_owner(bus_name).await
-                && owner.as_str() == sender.as_str()
-            {
-                authorized = true;
-                break;
-            }
-        }
+    }
 
-        if !authorized {
-            warn!(
-                sender = sender.as_str(),
-                "Rejected AcceptEisSocket call from unauthorized D-Bus sender"
-            );
-            return Err(zbus::fdo::Error::AccessDenied(
-                "caller is not an authorized portal process".into(),
-            ));
+    /// The most recent captured events, oldest first, as `(session, time,
+    /// event)` with the time in milliseconds of the compositor clock and the
+    /// event as e.g. `button 272 pressed` or `motion 1.5 -2`.
+    async fn get_captured_events(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> zbus::fdo::Result<Vec<CapturedEvent>> {
+        authorize(&header, connection, "GetCapturedEvents").await?;
+        self.request(|sync| EisCommand::CapturedEvents { sync })
+            .await
+    }
+
+    /// List live sessions as `(id, client_name, label, capabilities,
+    /// connected_since)`, with the granted capabilities by name and the
+    /// connection time in seconds since the UNIX epoch.
+    async fn list_sessions(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> zbus::fdo::Result<Vec<(SessionId, String, String, Vec<String>, u64)>> {
+        authorize(&header, connection, "ListSessions").await?;
+        let sessions = self
+            .request(|sync| EisCommand::ListSessions { sync })
+            .await?;
+        Ok(sessions
+            .into_iter()
+            .map(|info| {
+                (
+                    info.id,
+                    info.client_name,
+                    info.label,
+                    info.capabilities,
+                    info.connected_since,
+                )
+            })
+            .collect())
+    }
+
+    /// Like `ListSessions`, with each session's injected events by kind:
+    /// `key`, `button`, `motion`, `absolute_motion`, `scroll` and `touch`,
+    /// plus `key_without_focus` counting the keys no surface received and
+    /// `rejected_non_finite` the events dropped for NaN/Inf values.
+    async fn list_sessions_with_counts(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> zbus::fdo::Result<Vec<SessionWithCounts>> {
+        authorize(&header, connection, "ListSessionsWithCounts").await?;
+        let sessions = self
+            .request(|sync| EisCommand::ListSessions { sync })
+            .await?;
+        Ok(sessions
+            .into_iter()
+            .map(|info| {
+                (
+                    info.id,
+                    info.client_name,
+                    info.label,
+                    info.capabilities,
+                    info.connected_since,
+                    info.injected,
+                )
+            })
+            .collect())
+    }
+
+    /// Forcibly end a session, e.g. to revoke remote input. The client is
+    /// told it was disconnected and any keys it held are released.
+    async fn disconnect_session(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "DisconnectSession").await?;
+        let found = self
+            .request(|sync| EisCommand::DisconnectSession { session, sync })
+            .await?;
+        found_or_unknown(found, session)
+    }
+
+    /// Correct a session's absolute pointer and touch coordinates, e.g. for a
+    /// misaligned remote touchscreen: positions are multiplied by `scale`,
+    /// then offset. Offsets may not exceed the size of the output layout.
+    async fn set_session_calibration(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+        offset_x: f64,
+        offset_y: f64,
+        scale: f64,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "SetSessionCalibration").await?;
+        let calibration = Calibration {
+            offset_x,
+            offset_y,
+            scale,
+        };
+        match self
+            .request(|sync| EisCommand::SetCalibration {
+                session,
+                calibration,
+                sync,
+            })
+            .await?
+        {
+            Some(true) => Ok(()),
+            Some(false) => Err(zbus::fdo::Error::InvalidArgs(
+                "calibration must be finite, with a positive scale and offsets within the layout"
+                    .into(),
+            )),
+            None => Err(unknown_session(session)),
         }
+    }
 
-        // Verify the fd is a UNIX stream socket (not a file, pipe, etc.)
-        let raw_fd = std::os::fd::OwnedFd::from(fd);
+    /// Treat a session's absolute pointer and touch coordinates as relative to
+    /// the top-left of the named output, for clients streaming a single
+    /// monitor. Devices bound afterwards announce that output's size as their
+    /// region. An empty name restores global coordinates.
+    ///
+    /// Only outputs driven by a backend can be targeted: there is no headless
+    /// backend to create virtual outputs of a requested resolution for
+    /// sessions without a monitor, and an output nothing renders to couldn't
+    /// be streamed anyway.
+    async fn set_session_output(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+        output: String,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "SetSessionOutput").await?;
+        let output = (!output.is_empty()).then_some(output);
+        let found = self
+            .request(|sync| EisCommand::SetTargetOutput {
+                session,
+                output,
+                sync,
+            })
+            .await?;
+        found_or_unknown(found, session)
+    }
+
+    /// Move the pointer smoothly through the global `points` over
+    /// `duration_ms` on behalf of a session, e.g. for scripted demos. Every
+    /// point must lie on an output.
+    async fn move_pointer_path(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+        points: Vec<(f64, f64)>,
+        duration_ms: u32,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "MovePointerPath").await?;
+        let duration = Duration::from_millis(duration_ms.into());
+        match self
+            .request(|sync| EisCommand::MovePointerPath {
+                session,
+                points,
+                duration,
+                sync,
+            })
+            .await?
         {
-            use std::os::fd::AsRawFd;
-            let mut sock_type: libc::c_int = 0;
-            let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
-            let ret = unsafe {
-                libc::getsockopt(
-                    raw_fd.as_raw_fd(),
-                    libc::SOL_SOCKET,
-                    libc::SO_TYPE,
-                    std::ptr::addr_of_mut!(sock_type).cast(),
-                    std::ptr::addr_of_mut!(len),
-                )
-            };
-            if ret != 0 || sock_type != libc::SOCK_STREAM {
-                warn!(
-                    sender = sender.as_str(),
-                    "Rejected AcceptEisSocket: fd is not a SOCK_STREAM socket"
-                );
-                return Err(zbus::fdo::Error::InvalidArgs(
-                    "fd must be a SOCK_STREAM Unix socket".into(),
-                ));
-            }
+            Some(true) => Ok(()),
+            Some(false) => Err(zbus::fdo::Error::InvalidArgs(
+                "path must have at least one point and stay within the output layout".into(),
+            )),
+            None => Err(unknown_session(session)),
         }
+    }
 
-        let stream = UnixStream::from(raw_fd);
-        info!(sender = sender.as_str(), "Accepted EIS socket via D-Bus");
-        self.sender
-            .tx
-            .send(stream)
-            .map_err(|_| zbus::fdo::Error::Failed("Compositor EIS channel closed".to_string()))
+    /// Whether a session currently controls the pointer, i.e. it moved it
+    /// last and hasn't been idle for longer than the pointer release timeout.
+    async fn session_is_pointer_owner(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "SessionIsPointerOwner").await?;
+        let owner = self
+            .request(|sync| EisCommand::IsPointerOwner { session, sync })
+            .await?;
+        owner.ok_or_else(|| unknown_session(session))
+    }
+
+    /// Capabilities of a session's device as `(granted, denied)`, so clients
+    /// can tell which of the requested capabilities were refused by
+    /// configuration.
+    async fn session_capabilities(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<(Vec<String>, Vec<String>)> {
+        authorize(&header, connection, "SessionCapabilities").await?;
+        let capabilities = self
+            .request(|sync| EisCommand::Capabilities { session, sync })
+            .await?;
+        capabilities.ok_or_else(|| unknown_session(session))
+    }
+
+    /// When a session last sent an input event for each capability, in
+    /// milliseconds since the UNIX epoch. Capabilities it never used are
+    /// missing.
+    async fn session_capability_activity(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<HashMap<String, u64>> {
+        authorize(&header, connection, "SessionCapabilityActivity").await?;
+        let activity = self
+            .request(|sync| EisCommand::CapabilityActivity { session, sync })
+            .await?;
+        activity.ok_or_else(|| unknown_session(session))
+    }
+
+    /// Paste the clipboard into the focused surface by pressing the configured
+    /// shortcut, Ctrl+V by default, on behalf of a session. Returns false
+    /// without pressing anything if no surface has keyboard focus.
+    async fn paste(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "Paste").await?;
+        self.edit(session, EditingCommand::Paste).await
+    }
+
+    /// Move a session's pointer straight to a global position, e.g. after a
+    /// layout change, instead of emulating motion to get there. Positions off
+    /// the layout land on the nearest output. Returns false if the pointer
+    /// didn't move.
+    async fn warp_pointer(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+        x: f64,
+        y: f64,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "WarpPointer").await?;
+        let warped = self
+            .request(|sync| EisCommand::WarpPointer {
+                session,
+                x,
+                y,
+                sync,
+            })
+            .await?;
+        warped.ok_or_else(|| unknown_session(session))
+    }
+
+    /// Select everything in the focused surface by pressing the configured
+    /// shortcut, Ctrl+A by default. Returns false if nothing is focused.
+    async fn select_all(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "SelectAll").await?;
+        self.edit(session, EditingCommand::SelectAll).await
+    }
+
+    /// Copy the selection of the focused surface by pressing the configured
+    /// shortcut, Ctrl+C by default. Returns false if nothing is focused.
+    async fn copy(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "Copy").await?;
+        self.edit(session, EditingCommand::Copy).await
+    }
+
+    /// Cut the selection of the focused surface by pressing the configured
+    /// shortcut, Ctrl+X by default. Returns false if nothing is focused.
+    async fn cut(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "Cut").await?;
+        self.edit(session, EditingCommand::Cut).await
+    }
+
+    /// Undo in the focused surface by pressing the configured shortcut,
+    /// Ctrl+Z by default. Returns false if nothing is focused.
+    async fn undo(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "Undo").await?;
+        self.edit(session, EditingCommand::Undo).await
+    }
+
+    /// Redo in the focused surface by pressing the configured shortcut,
+    /// Ctrl+Shift+Z by default. Returns false if nothing is focused.
+    async fn redo(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "Redo").await?;
+        self.edit(session, EditingCommand::Redo).await
+    }
+
+    /// Open a context menu on behalf of a session, by pressing the Menu key or
+    /// right-clicking at the current pointer location as configured. Returns
+    /// false if the session's seat has no keyboard or pointer for it.
+    async fn context_menu(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "ContextMenu").await?;
+        let opened = self
+            .request(|sync| EisCommand::ContextMenu { session, sync })
+            .await?;
+        opened.ok_or_else(|| unknown_session(session))
+    }
+
+    /// Close the window focused on a session's seat by pressing the
+    /// configured close shortcut, Alt+F4 by default. With a non-empty
+    /// `app_id` the window is only closed if it belongs to that app. Returns
+    /// false if nothing, or another app's window, was focused.
+    async fn close_focused_window(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+        app_id: String,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "CloseFocusedWindow").await?;
+        let app_id = (!app_id.is_empty()).then_some(app_id);
+        let closed = self
+            .request(|sync| EisCommand::CloseFocusedWindow {
+                session,
+                app_id,
+                sync,
+            })
+            .await?;
+        closed.ok_or_else(|| unknown_session(session))
+    }
+
+    /// Events a session injected within the rolling budget window and the
+    /// configured budget, as `(consumed, budget)`. A budget of 0 means none
+    /// is enforced.
+    async fn session_event_budget(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<(u64, u64)> {
+        authorize(&header, connection, "SessionEventBudget").await?;
+        let budget = self
+            .request(|sync| EisCommand::EventBudget { session, sync })
+            .await?;
+        budget.ok_or_else(|| unknown_session(session))
+    }
+
+    /// Whether the last key a session injected had a focused surface to
+    /// receive it. False until the session sent a key.
+    async fn session_last_key_had_focus(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "SessionLastKeyHadFocus").await?;
+        let focused = self
+            .request(|sync| EisCommand::LastKeyHadFocus { session, sync })
+            .await?;
+        focused.ok_or_else(|| unknown_session(session))
+    }
+
+    /// Whether a session's connection is still registered as an event source
+    /// on the compositor's event loop, for diagnosing sessions that won't go
+    /// away.
+    async fn session_source_registered(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<bool> {
+        authorize(&header, connection, "SessionSourceRegistered").await?;
+        let registered = self
+            .request(|sync| EisCommand::SourceRegistered { session, sync })
+            .await?;
+        registered.ok_or_else(|| unknown_session(session))
+    }
+
+    /// The app_id and global geometry `(app_id, x, y, width, height)` of the
+    /// window focused on the seat a session drives. An empty app_id and zero
+    /// geometry mean nothing is focused.
+    async fn session_focus_info(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<FocusInfo> {
+        authorize(&header, connection, "SessionFocusInfo").await?;
+        let info = self
+            .request(|sync| EisCommand::FocusInfo { session, sync })
+            .await?;
+        info.ok_or_else(|| unknown_session(session))
+    }
+
+    /// Give a session's keyboard its own keymap, e.g. the layout of the remote
+    /// machine, from the `(rules, model, layout, variant, options)` names
+    /// `SessionKeymapInfo` reports. Empty rules and model are the
+    /// compositor's; an empty layout goes back to the compositor's keymap. A
+    /// keyboard already bound is sent the new keymap.
+    ///
+    /// Fails with `InvalidArgs` if the names don't compile to a keymap.
+    async fn set_session_keymap(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+        names: KeymapInfo,
+    ) -> zbus::fdo::Result<()> {
+        authorize(&header, connection, "SetSessionKeymap").await?;
+        let result = self
+            .request(|sync| EisCommand::SetKeymap {
+                session,
+                names,
+                sync,
+            })
+            .await?;
+        result
+            .ok_or_else(|| unknown_session(session))?
+            .map_err(|err| match err {
+                EisError::Keymap => zbus::fdo::Error::InvalidArgs(err.to_string()),
+                err => zbus::fdo::Error::Failed(err.to_string()),
+            })
+    }
+
+    /// The `(rules, model, layout, variant, options)` names of the keymap a
+    /// session's keyboard uses: its own if set with `SetSessionKeymap`,
+    /// otherwise the compositor's XKB config.
+    async fn session_keymap_info(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<KeymapInfo> {
+        authorize(&header, connection, "SessionKeymapInfo").await?;
+        let info = self
+            .request(|sync| EisCommand::KeymapInfo { session, sync })
+            .await?;
+        info.ok_or_else(|| unknown_session(session))
+    }
+
+    /// The XKB keymap text a session's keyboard was sent, as named by
+    /// `SessionKeymapInfo`, for translating characters to its keycodes.
+    async fn get_session_keymap(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<String> {
+        authorize(&header, connection, "GetSessionKeymap").await?;
+        let keymap = self
+            .request(|sync| EisCommand::Keymap { session, sync })
+            .await?;
+        keymap
+            .ok_or_else(|| unknown_session(session))?
+            .map_err(|err| zbus::fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Bytes queued on a session's socket as `(unread, unsent)`. Unread bytes
+    /// are requests the compositor has not processed yet, unsent bytes are
+    /// events the client has not read yet; high values indicate a slow client
+    /// or network.
+    async fn session_socket_occupancy(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+        session: SessionId,
+    ) -> zbus::fdo::Result<(u32, u32)> {
+        authorize(&header, connection, "SessionSocketOccupancy").await?;
+        let occupancy = self
+            .request(|sync| EisCommand::SocketOccupancy { session, sync })
+            .await?;
+        occupancy.ok_or_else(|| unknown_session(session))
+    }
+
+    /// The most recent errors that ended EIS connections, oldest first, as
+    /// `<RFC 3339 time> session <id>: <error>`. Helps diagnosing flapping
+    /// clients after the fact.
+    async fn recent_errors(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> zbus::fdo::Result<Vec<String>> {
+        authorize(&header, connection, "RecentErrors").await?;
+        self.request(|sync| EisCommand::RecentErrors { sync }).await
+    }
+
+    /// Counters of injected events per kind (`injected_<kind>`) and of events
+    /// rejected per reason (`rejected_<reason>`) since the receiver started,
+    /// plus the number of `active_connections`, e.g. for exporting to
+    /// Prometheus.
+    async fn get_metrics(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> zbus::fdo::Result<HashMap<String, u64>> {
+        authorize(&header, connection, "GetMetrics").await?;
+        self.request(|sync| EisCommand::Metrics { sync }).await
+    }
+
+    /// Hits, misses and hit rate of the surface-under cache used for injected
+    /// pointer motion (see `eis_config.surface_cache`).
+    async fn surface_cache_stats(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        #[zbus(connection)] connection: &zbus::Connection,
+    ) -> zbus::fdo::Result<(u64, u64, f64)> {
+        authorize(&header, connection, "SurfaceCacheStats").await?;
+        self.request(|sync| EisCommand::SurfaceCacheStats { sync })
+            .await
     }
 }
 
@@ -126,36 +1014,54 @@ impl CosmicCompEis {
 pub fn init(
     evlh: &calloop::LoopHandle<'static, crate::state::State>,
     executor: &ThreadPool,
-) -> anyhow::Result<()> {
-    let (socket_tx, socket_rx) = channel::channel::<UnixStream>();
+) -> Result<(), EisError> {
+    let (socket_tx, socket_rx) = channel::channel::<EisSocket>();
+    let (events_tx, events_rx) = std::sync::mpsc::channel::<SessionEvent>();
+    let command_events = events_tx.clone();
 
     // Register the socket receiver with calloop - when the portal sends
-    // an EIS fd, this will deliver it to the compositor
-    evlh.insert_source(socket_rx, |event, _, state| {
-        if let channel::Event::Msg(stream) = event {
-            // Initialize EIS state if needed, then add connection
-            if state.common.eis_state.is_none() {
-                match crate::input::eis::EisState::new(&state.common.event_loop_handle) {
-                    Ok(eis_state) => {
-                        state.common.eis_state = Some(eis_state);
-                    }
-                    Err(err) => {
-                        error!("Failed to initialize EIS state: {}", err);
-                        return;
-                    }
+    // an EIS fd, this will deliver it to the compositor. Dropping `reply`
+    // without an answer tells the caller the receiver isn't running.
+    evlh.insert_source(socket_rx, move |event, _, state| {
+        if let channel::Event::Msg(socket) = event
+            && ensure_eis_state(state, &events_tx).is_some()
+            && let Some(eis_state) = state.common.eis_state.as_mut()
+        {
+            let paced = eis_state
+                .pace_connection(&state.common.config.cosmic_conf.eis_config, Instant::now());
+            match paced {
+                Ok(None) => accept_socket(state, socket),
+                Ok(Some(slot)) => queue_socket(state, socket, slot),
+                Err(err) => {
+                    let _ = socket.2.send(Err(err));
                 }
             }
-            if let Some(eis_state) = &state.common.eis_state {
-                eis_state.add_connection(stream);
-            }
         }
     })
-    .map_err(|e| anyhow::anyhow!("Failed to insert EIS socket channel: {}", e.error))?;
+    .map_err(|_| EisError::EventSource("socket channel"))?;
+
+    // Session management requests from D-Bus; dropping a command without
+    // replying (e.g. if the EIS state failed to initialize) reports an error
+    // to the caller.
+    let (command_tx, command_rx) = channel::channel::<EisCommand>();
+    evlh.insert_source(command_rx, move |event, _, state| {
+        if let channel::Event::Msg(command) = event
+            && ensure_eis_state(state, &command_events).is_some()
+            && let Some(eis_state) = &mut state.common.eis_state
+        {
+            eis_state.handle_command(
+                &state.common.config.cosmic_conf.eis_config,
+                &state.common.shell.read(),
+                command,
+            );
+        }
+    })
+    .map_err(|_| EisError::EventSource("command channel"))?;
 
     // Spawn async D-Bus registration via the executor (same pattern as a11y)
     let sender = EisSocketSender::new(socket_tx);
     executor.spawn_ok(async move {
-        match register_dbus(sender).await {
+        match register_dbus(sender, command_tx, events_rx).await {
             Ok(()) => info!("EIS D-Bus interface registered"),
             Err(err) => error!("Failed to register EIS D-Bus interface: {}", err),
         }
@@ -164,9 +1070,83 @@ pub fn init(
     Ok(())
 }
 
-async fn register_dbus(sender: EisSocketSender) -> anyhow::Result<()> {
+/// Set up the connection of a socket and report the outcome.
+fn accept_socket(state: &mut crate::state::State, (stream, seat, reply): EisSocket) {
+    let seats = state
+        .common
+        .shell
+        .read()
+        .seats
+        .iter()
+        .map(|seat| seat.name().to_string())
+        .collect::<Vec<_>>();
+    let Some(eis_state) = state.common.eis_state.as_mut() else {
+        return;
+    };
+    let accepted = match seat {
+        Some(seat) => {
+            let seats = seats.iter().map(String::as_str).collect::<Vec<_>>();
+            eis_state.add_connection_for_seat(stream, &seat, &seats)
+        }
+        None => eis_state.add_connection(stream),
+    };
+    let _ = reply.send(accepted);
+}
+
+/// Accept a socket once the minimum connection interval allows it at `slot`.
+fn queue_socket(state: &mut crate::state::State, socket: EisSocket, slot: Instant) {
+    let mut socket = Some(socket);
+    let res = state.common.event_loop_handle.insert_source(
+        Timer::from_deadline(slot),
+        move |_, _, state| {
+            if let Some(socket) = socket.take() {
+                accept_socket(state, socket);
+            }
+            TimeoutAction::Drop
+        },
+    );
+    // Dropping the reply along with the callback reports the failure
+    if let Err(err) = res {
+        warn!("Failed to queue EIS connection: {}", err.error);
+    }
+}
+
+/// Initialize the EIS state on first use, reporting session lifecycle changes
+/// to `events`.
+fn ensure_eis_state<'a>(
+    state: &'a mut crate::state::State,
+    events: &std::sync::mpsc::Sender<SessionEvent>,
+) -> Option<&'a mut crate::input::eis::EisState> {
+    if state.common.eis_state.is_none() {
+        let eis_config = &state.common.config.cosmic_conf.eis_config;
+        let (max_connections, max_touch_id) = (eis_config.max_connections, eis_config.max_touch_id);
+        let idle_timeout = Duration::from_millis(eis_config.idle_timeout_ms);
+        match crate::input::eis::EisState::new(&state.common.event_loop_handle, max_connections) {
+            Ok(mut eis_state) => {
+                eis_state.set_max_touch_id(max_touch_id);
+                eis_state.set_idle_timeout(idle_timeout);
+                if crate::utils::env::bool_var("COSMIC_EIS_CAPTURE").unwrap_or(false) {
+                    eis_state.set_capture(true);
+                }
+                eis_state.set_session_events(events.clone());
+                state.common.eis_state = Some(eis_state);
+            }
+            Err(err) => {
+                error!("Failed to initialize EIS state: {}", err);
+                return None;
+            }
+        }
+    }
+    state.common.eis_state.as_mut()
+}
+
+async fn register_dbus(
+    sender: EisSocketSender,
+    commands: channel::Sender<EisCommand>,
+    events: std::sync::mpsc::Receiver<SessionEvent>,
+) -> Result<(), EisError> {
     let connection = zbus::Connection::session().await?;
-    let eis_interface = CosmicCompEis::new(sender);
+    let eis_interface = CosmicCompEis::new(sender, commands);
 
     connection
         .object_server()
@@ -177,7 +1157,104 @@ async fn register_dbus(sender: EisSocketSender) -> anyhow::Result<()> {
         .request_name("com.system76.CosmicComp.RemoteDesktop")
         .await?;
 
+    // Session events come from the calloop thread; forward them as signals
+    // from a thread of their own so waiting doesn't hold up the executor
+    let emitter = SignalEmitter::new(&connection, "/com/system76/CosmicComp")?.into_owned();
+    let interface = connection
+        .object_server()
+        .interface::<_, CosmicCompEis>("/com/system76/CosmicComp")
+        .await?;
+    std::thread::Builder::new()
+        .name("eis-session-signals".into())
+        .spawn(move || {
+            for event in events {
+                let res = futures_executor::block_on(async {
+                    match event {
+                        SessionEvent::Opened { id, client_name } => {
+                            CosmicCompEis::session_opened(emitter.clone(), id, &client_name).await
+                        }
+                        SessionEvent::Closed(id) => {
+                            CosmicCompEis::session_closed(emitter.clone(), id).await
+                        }
+                    }?;
+                    interface
+                        .get()
+                        .await
+                        .active_sessions_changed(&emitter)
+                        .await
+                });
+                if let Err(err) = res {
+                    warn!("Failed to emit EIS session signal: {err}");
+                }
+            }
+        })
+        .map_err(EisError::Thread)?;
+
     // Keep the connection alive
     std::future::pending::<()>().await;
     Ok(())
 }
+
+/// Check that `fd` is a `SOCK_STREAM` UNIX socket, the only kind an EIS
+/// receiver can run on.
+fn check_unix_stream(fd: &OwnedFd) -> zbus::fdo::Result<()> {
+    let option = |name| {
+        let mut value: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                name,
+                std::ptr::addr_of_mut!(value).cast(),
+                std::ptr::addr_of_mut!(len),
+            )
+        };
+        (ret == 0).then_some(value)
+    };
+    if option(libc::SO_DOMAIN) == Some(libc::AF_UNIX)
+        && option(libc::SO_TYPE) == Some(libc::SOCK_STREAM)
+    {
+        Ok(())
+    } else {
+        Err(zbus::fdo::Error::InvalidArgs(
+            "fd must be a SOCK_STREAM Unix socket".into(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_unix_stream, version_string};
+    use std::os::{fd::OwnedFd, unix::net::UnixStream};
+
+    #[test]
+    fn test_version_string() {
+        let version = version_string();
+        let semver = version.split_whitespace().next().unwrap();
+        let parts = semver
+            .split('.')
+            .map(|part| part.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(parts.len(), 3);
+        // the locked reis release rather than the requirement
+        let reis = version.split("reis ").nth(1).unwrap();
+        let reis = reis.split(';').next().unwrap();
+        assert_eq!(reis.split('.').count(), 3);
+        #[cfg(feature = "systemd")]
+        assert!(version.contains("features: systemd"));
+    }
+
+    #[test]
+    fn test_socket_fd_check() {
+        let (server, _client) = UnixStream::pair().unwrap();
+        assert!(check_unix_stream(&OwnedFd::from(server)).is_ok());
+
+        let (reader, _writer) = std::io::pipe().unwrap();
+        assert!(matches!(
+            check_unix_stream(&OwnedFd::from(reader)),
+            Err(zbus::fdo::Error::InvalidArgs(_))
+        ));
+    }
+}
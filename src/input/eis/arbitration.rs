@@ -0,0 +1,105 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Arbitration between EIS sessions driving the same pointer.
+
+use std::time::{Duration, Instant};
+
+use cosmic_comp_config::eis::PointerArbitration;
+
+use super::session::SessionId;
+
+/// Tracks which session last drove the shared pointer.
+#[derive(Debug, Default)]
+pub struct PointerArbiter {
+    owner: Option<(SessionId, Instant)>,
+}
+
+impl PointerArbiter {
+    /// Decide whether `session` may drive the pointer at `now`.
+    ///
+    /// Under [`PointerArbitration::Exclusive`] the pointer stays with its
+    /// owner until that session disconnects or is idle for `idle_timeout`.
+    /// Under [`PointerArbitration::Shared`] every session may move it and the
+    /// last one to do so is recorded as owner.
+    pub fn claim(
+        &mut self,
+        policy: PointerArbitration,
+        session: SessionId,
+        now: Instant,
+        idle_timeout: Duration,
+    ) -> bool {
+        if policy == PointerArbitration::Exclusive
+            && let Some((owner, last)) = self.owner
+            && owner != session
+            && now.saturating_duration_since(last) < idle_timeout
+        {
+            return false;
+        }
+        self.owner = Some((session, now));
+        true
+    }
+
+    pub fn owner(&self) -> Option<SessionId> {
+        self.owner.map(|(owner, _)| owner)
+    }
+
+    /// Whether `session` drove the pointer within the last `idle_timeout`.
+    pub fn holds(&self, session: SessionId, now: Instant, idle_timeout: Duration) -> bool {
+        self.owner.is_some_and(|(owner, last)| {
+            owner == session && now.saturating_duration_since(last) < idle_timeout
+        })
+    }
+
+    /// Give up the pointer if `session` holds it.
+    pub fn release(&mut self, session: SessionId) {
+        if self.owner() == Some(session) {
+            self.owner = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PointerArbiter;
+    use cosmic_comp_config::eis::PointerArbitration;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_exclusive_pointer() {
+        let timeout = Duration::from_millis(500);
+        let start = Instant::now();
+        let mut arbiter = PointerArbiter::default();
+        let at = |ms| start + Duration::from_millis(ms);
+        let claim = |arbiter: &mut PointerArbiter, session, ms| {
+            arbiter.claim(PointerArbitration::Exclusive, session, at(ms), timeout)
+        };
+
+        // the first session to move takes the pointer, the second is dropped
+        assert!(claim(&mut arbiter, 1, 0));
+        assert!(!claim(&mut arbiter, 2, 10));
+        assert!(claim(&mut arbiter, 1, 20));
+        assert_eq!(arbiter.owner(), Some(1));
+
+        assert!(arbiter.holds(1, at(20), timeout));
+        assert!(!arbiter.holds(2, at(20), timeout));
+
+        // once the holder goes idle the other session may take over
+        assert!(!arbiter.holds(1, at(600), timeout));
+        assert!(claim(&mut arbiter, 2, 600));
+        assert!(!claim(&mut arbiter, 1, 610));
+
+        // as it may after the holder releases the pointer
+        arbiter.release(2);
+        assert!(claim(&mut arbiter, 1, 620));
+    }
+
+    #[test]
+    fn test_shared_pointer() {
+        let mut arbiter = PointerArbiter::default();
+        let now = Instant::now();
+        let timeout = Duration::from_millis(500);
+        assert!(arbiter.claim(PointerArbitration::Shared, 1, now, timeout));
+        assert!(arbiter.claim(PointerArbitration::Shared, 2, now, timeout));
+        assert_eq!(arbiter.owner(), Some(2));
+    }
+}
@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Per-connection budget of injected events over a long rolling window.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Number of buckets the window is counted in. Events leave the window one
+/// bucket at a time, so it rolls in steps of a sixtieth of its length.
+const BUCKETS: u32 = 60;
+
+/// Events counted within the last `window`, for catching clients injecting
+/// slowly but steadily past what the per-second rate limit notices.
+#[derive(Debug, Default)]
+pub struct RollingBudget {
+    start: Option<Instant>,
+    /// `(bucket index, events)`, oldest first.
+    buckets: VecDeque<(u64, u64)>,
+}
+
+impl RollingBudget {
+    /// Count an event at `now` if fewer than `budget` events were counted
+    /// within `window`, returning whether it may pass.
+    pub fn take(&mut self, now: Instant, window: Duration, budget: u64) -> bool {
+        let bucket = self.expire(now, window);
+        if self.consumed() >= budget {
+            return false;
+        }
+        match self.buckets.back_mut() {
+            Some((index, events)) if *index == bucket => *events += 1,
+            _ => self.buckets.push_back((bucket, 1)),
+        }
+        true
+    }
+
+    /// Events counted within `window` of `now`.
+    pub fn consumed_at(&mut self, now: Instant, window: Duration) -> u64 {
+        self.expire(now, window);
+        self.consumed()
+    }
+
+    fn consumed(&self) -> u64 {
+        self.buckets.iter().map(|(_, events)| events).sum()
+    }
+
+    /// Drop buckets that left the window, returning the index of the bucket
+    /// `now` falls into.
+    fn expire(&mut self, now: Instant, window: Duration) -> u64 {
+        let start = *self.start.get_or_insert(now);
+        let width = (window / BUCKETS).max(Duration::from_millis(1));
+        let bucket = (now.saturating_duration_since(start).as_millis() / width.as_millis()) as u64;
+        while self
+            .buckets
+            .front()
+            .is_some_and(|(index, _)| index + u64::from(BUCKETS) <= bucket)
+        {
+            self.buckets.pop_front();
+        }
+        bucket
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RollingBudget;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_rolling_budget() {
+        let hour = Duration::from_secs(3600);
+        let start = Instant::now();
+        let mut budget = RollingBudget::default();
+
+        // spread out over half an hour, the budget still runs out
+        for minute in 0..10 {
+            assert!(budget.take(start + Duration::from_secs(minute * 180), hour, 10));
+        }
+        let later = start + Duration::from_secs(1800);
+        assert!(!budget.take(later, hour, 10));
+        assert_eq!(budget.consumed_at(later, hour), 10);
+
+        // an hour after the first events they no longer count
+        let next_hour = start + hour + Duration::from_secs(60);
+        assert_eq!(budget.consumed_at(next_hour, hour), 9);
+        assert!(budget.take(next_hour, hour, 10));
+        assert!(!budget.take(next_hour, hour, 10));
+    }
+}
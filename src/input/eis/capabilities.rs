@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Negotiation of the device capabilities EIS clients may use.
+
+use cosmic_comp_config::eis::EisCapability;
+use reis::{event::DeviceCapability, request::EisRequest};
+
+fn config_capability(capability: DeviceCapability) -> Option<EisCapability> {
+    match capability {
+        DeviceCapability::Keyboard => Some(EisCapability::Keyboard),
+        DeviceCapability::Pointer => Some(EisCapability::Pointer),
+        DeviceCapability::PointerAbsolute => Some(EisCapability::PointerAbsolute),
+        DeviceCapability::Button => Some(EisCapability::Button),
+        DeviceCapability::Scroll => Some(EisCapability::Scroll),
+        DeviceCapability::Touch => Some(EisCapability::Touch),
+        _ => None,
+    }
+}
+
+/// Split the `requested` capabilities into `(granted, denied)` according to
+/// the configured `denied` list.
+pub fn negotiate(
+    requested: impl IntoIterator<Item = DeviceCapability>,
+    denied: &[EisCapability],
+) -> (Vec<DeviceCapability>, Vec<DeviceCapability>) {
+    requested.into_iter().partition(|capability| {
+        config_capability(*capability).is_none_or(|capability| !denied.contains(&capability))
+    })
+}
+
+/// The capability a device must have to send `request`, or `None` for
+/// requests that don't inject input.
+pub fn required(request: &EisRequest) -> Option<DeviceCapability> {
+    match request {
+        EisRequest::KeyboardKey(_) => Some(DeviceCapability::Keyboard),
+        EisRequest::PointerMotion(_) => Some(DeviceCapability::Pointer),
+        EisRequest::PointerMotionAbsolute(_) => Some(DeviceCapability::PointerAbsolute),
+        EisRequest::Button(_) => Some(DeviceCapability::Button),
+        EisRequest::ScrollDelta(_)
+        | EisRequest::ScrollDiscrete(_)
+        | EisRequest::ScrollStop(_)
+        | EisRequest::ScrollCancel(_) => Some(DeviceCapability::Scroll),
+        EisRequest::TouchDown(_)
+        | EisRequest::TouchMotion(_)
+        | EisRequest::TouchUp(_)
+        | EisRequest::TouchCancel(_) => Some(DeviceCapability::Touch),
+        _ => None,
+    }
+}
+
+/// Whether a device with the `granted` capabilities may send an event
+/// needing `required`.
+pub fn allows(granted: &[DeviceCapability], required: Option<DeviceCapability>) -> bool {
+    required.is_none_or(|required| granted.contains(&required))
+}
+
+/// Kind of device a bind creates, as limited per session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Keyboard,
+    Pointer,
+    Touch,
+}
+
+impl DeviceKind {
+    fn of(granted: &[DeviceCapability]) -> impl Iterator<Item = DeviceKind> + '_ {
+        [
+            (DeviceKind::Keyboard, &[DeviceCapability::Keyboard][..]),
+            (
+                DeviceKind::Pointer,
+                &[DeviceCapability::Pointer, DeviceCapability::PointerAbsolute][..],
+            ),
+            (DeviceKind::Touch, &[DeviceCapability::Touch][..]),
+        ]
+        .into_iter()
+        .filter(|(_, capabilities)| capabilities.iter().any(|c| granted.contains(c)))
+        .map(|(kind, _)| kind)
+    }
+}
+
+/// Devices of each kind a session bound.
+#[derive(Debug, Default)]
+pub struct DeviceCounts {
+    counts: [u32; 3],
+}
+
+impl DeviceCounts {
+    /// Whether another device with the `granted` capabilities fits, or the
+    /// kind the session already has `max` devices of. A `max` of 0 allows
+    /// any number.
+    pub fn admit(&self, granted: &[DeviceCapability], max: u32) -> Result<(), DeviceKind> {
+        match DeviceKind::of(granted).find(|kind| max > 0 && self.counts[*kind as usize] >= max) {
+            Some(full) => Err(full),
+            None => Ok(()),
+        }
+    }
+
+    /// Count a device that was added with the `granted` capabilities.
+    pub fn add(&mut self, granted: &[DeviceCapability]) {
+        for kind in DeviceKind::of(granted) {
+            self.counts[kind as usize] += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceCounts, DeviceKind, allows, negotiate};
+    use cosmic_comp_config::eis::{EisCapability, EisConfig};
+    use reis::event::DeviceCapability;
+
+    #[test]
+    fn test_denied_capabilities() {
+        let requested = [DeviceCapability::Keyboard, DeviceCapability::Touch];
+        let (granted, denied) = negotiate(requested, &[EisCapability::Touch]);
+        assert_eq!(granted, vec![DeviceCapability::Keyboard]);
+        assert_eq!(denied, vec![DeviceCapability::Touch]);
+
+        let (granted, denied) = negotiate(requested, &[]);
+        assert_eq!(granted, requested);
+        assert!(denied.is_empty());
+    }
+
+    #[test]
+    fn test_reloaded_denial() {
+        let mut config = EisConfig::default();
+        let requested = [DeviceCapability::Pointer, DeviceCapability::Touch];
+        let (granted, _) = negotiate(requested, &config.denied_capabilities);
+        assert_eq!(granted, requested);
+
+        // touch turned off while the compositor runs
+        config.denied_capabilities = vec![EisCapability::Touch];
+        let (granted, denied) = negotiate(requested, &config.denied_capabilities);
+        assert_eq!(granted, vec![DeviceCapability::Pointer]);
+        assert_eq!(denied, vec![DeviceCapability::Touch]);
+    }
+
+    #[test]
+    fn test_bound_capabilities() {
+        // a pointer-only device can't send keys
+        let granted = [DeviceCapability::Pointer];
+        assert!(allows(&granted, Some(DeviceCapability::Pointer)));
+        assert!(!allows(&granted, Some(DeviceCapability::Keyboard)));
+        assert!(!allows(&granted, Some(DeviceCapability::Touch)));
+        // protocol requests need no capability
+        assert!(allows(&granted, None));
+        // nothing is injected before a device was bound
+        assert!(!allows(&[], Some(DeviceCapability::Pointer)));
+    }
+
+    #[test]
+    fn test_device_limit() {
+        let mut devices = DeviceCounts::default();
+        let keyboard = [DeviceCapability::Keyboard];
+        for _ in 0..4 {
+            assert_eq!(devices.admit(&keyboard, 4), Ok(()));
+            devices.add(&keyboard);
+        }
+        assert_eq!(devices.admit(&keyboard, 4), Err(DeviceKind::Keyboard));
+        // a device with a keyboard among other capabilities counts too
+        let combined = [DeviceCapability::Keyboard, DeviceCapability::Touch];
+        assert_eq!(devices.admit(&combined, 4), Err(DeviceKind::Keyboard));
+        // other kinds have room of their own
+        let pointer = [DeviceCapability::PointerAbsolute, DeviceCapability::Button];
+        assert_eq!(devices.admit(&pointer, 4), Ok(()));
+        // only added devices count, not binds that ended without one
+        assert_eq!(devices.admit(&pointer, 4), Ok(()));
+        // and no limit allows any number
+        assert_eq!(devices.admit(&keyboard, 0), Ok(()));
+    }
+}
@@ -0,0 +1,147 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Capture of a client's input stream in place of injecting it, so a portal
+//! client can be checked without moving the real cursor.
+
+use std::{collections::VecDeque, fmt::Display};
+
+use reis::{eis, request::EisRequest};
+
+use super::SessionId;
+
+/// Captured events kept at most; older ones are dropped.
+const MAX_CAPTURED_EVENTS: usize = 1024;
+
+/// `(session, time, event)` of a captured event, with its time in
+/// milliseconds of the compositor clock.
+pub type CapturedEvent = (SessionId, u32, String);
+
+/// Input events that passed validation but weren't injected, oldest first.
+#[derive(Debug, Default)]
+pub struct EventCapture {
+    enabled: bool,
+    events: VecDeque<CapturedEvent>,
+}
+
+impl EventCapture {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turn capturing on or off. Turning it on starts with an empty buffer.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.events.clear();
+        }
+        self.enabled = enabled;
+    }
+
+    /// Keep `event` of a session, dropping the oldest event once full.
+    pub fn push(&mut self, session: SessionId, time: u32, event: String) {
+        if self.events.len() == MAX_CAPTURED_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back((session, time, event));
+    }
+
+    pub fn events(&self) -> Vec<CapturedEvent> {
+        self.events.iter().cloned().collect()
+    }
+}
+
+/// A readable form of an input request, or `None` for requests that don't
+/// inject input.
+pub fn describe(request: &EisRequest) -> Option<String> {
+    let event = match request {
+        EisRequest::KeyboardKey(event) => {
+            key(event.key, event.state == eis::keyboard::KeyState::Press)
+        }
+        EisRequest::Button(event) => {
+            button(event.button, event.state == eis::button::ButtonState::Press)
+        }
+        EisRequest::PointerMotion(motion) => format!("motion {} {}", motion.dx, motion.dy),
+        EisRequest::PointerMotionAbsolute(motion) => {
+            absolute(motion.dx_absolute, motion.dy_absolute)
+        }
+        EisRequest::ScrollDelta(scroll) => format!("scroll {} {}", scroll.dx, scroll.dy),
+        EisRequest::ScrollDiscrete(scroll) => format!(
+            "scroll discrete {} {}",
+            scroll.discrete_dx, scroll.discrete_dy
+        ),
+        EisRequest::ScrollStop(stop) => format!("scroll stop {} {}", stop.x, stop.y),
+        EisRequest::ScrollCancel(cancel) => format!("scroll cancel {} {}", cancel.x, cancel.y),
+        EisRequest::TouchDown(touch) => {
+            format!("touch down {} {} {}", touch.touch_id, touch.x, touch.y)
+        }
+        EisRequest::TouchMotion(touch) => {
+            format!("touch motion {} {} {}", touch.touch_id, touch.x, touch.y)
+        }
+        EisRequest::TouchUp(touch) => format!("touch up {}", touch.touch_id),
+        EisRequest::TouchCancel(touch) => format!("touch cancel {}", touch.touch_id),
+        EisRequest::Frame(_) => "frame".to_string(),
+        _ => return None,
+    };
+    Some(event)
+}
+
+/// The event of a key press or release, as sent by a client or pressed by a
+/// D-Bus command.
+pub fn key(code: u32, pressed: bool) -> String {
+    format!("key {code} {}", state(pressed))
+}
+
+/// The events of a `(key, pressed)` sequence.
+pub fn keys(sequence: &[(u32, bool)]) -> Vec<String> {
+    sequence
+        .iter()
+        .map(|(code, pressed)| key(*code, *pressed))
+        .collect()
+}
+
+pub fn button(code: u32, pressed: bool) -> String {
+    format!("button {code} {}", state(pressed))
+}
+
+pub fn absolute(x: impl Display, y: impl Display) -> String {
+    format!("absolute {x} {y}")
+}
+
+fn state(pressed: bool) -> &'static str {
+    if pressed { "pressed" } else { "released" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventCapture, MAX_CAPTURED_EVENTS};
+
+    #[test]
+    fn test_event_capture() {
+        let mut capture = EventCapture::default();
+        assert!(!capture.is_enabled());
+
+        capture.set_enabled(true);
+        capture.push(2, 100, "button 272 pressed".into());
+        capture.push(2, 100, "frame".into());
+        assert_eq!(
+            capture.events(),
+            vec![
+                (2, 100, "button 272 pressed".to_string()),
+                (2, 100, "frame".to_string())
+            ]
+        );
+
+        // only the latest events are kept
+        for time in 0..MAX_CAPTURED_EVENTS as u32 + 3 {
+            capture.push(2, time, "frame".into());
+        }
+        let events = capture.events();
+        assert_eq!(events.len(), MAX_CAPTURED_EVENTS);
+        assert_eq!(events[0].1, 3);
+
+        // stopping keeps the events for reading, a new capture starts afresh
+        capture.set_enabled(false);
+        assert_eq!(capture.events().len(), MAX_CAPTURED_EVENTS);
+        capture.set_enabled(true);
+        assert!(capture.events().is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Bounded log of the errors that ended EIS connections.
+
+use std::{collections::VecDeque, time::SystemTime};
+
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use super::session::SessionId;
+
+/// Number of connection errors kept for diagnosis.
+const MAX_DEAD_LETTERS: usize = 32;
+
+/// The most recent connection errors, oldest first, so flapping clients can
+/// be diagnosed after the fact.
+#[derive(Debug, Default)]
+pub struct DeadLetters {
+    entries: VecDeque<String>,
+}
+
+impl DeadLetters {
+    /// Keep `error` of `session` at `at`, dropping the oldest entry once full.
+    pub fn push(&mut self, session: SessionId, at: SystemTime, error: &str) {
+        if self.entries.len() == MAX_DEAD_LETTERS {
+            self.entries.pop_front();
+        }
+        let timestamp = OffsetDateTime::from(at)
+            .format(&Rfc3339)
+            .unwrap_or_default();
+        self.entries
+            .push_back(format!("{timestamp} session {session}: {error}"));
+    }
+
+    pub fn recent(&self) -> Vec<String> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeadLetters, MAX_DEAD_LETTERS};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_dead_letters() {
+        let mut dead_letters = DeadLetters::default();
+        assert!(dead_letters.recent().is_empty());
+
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(86400);
+        dead_letters.push(3, at, "unexpected opcode");
+        assert_eq!(
+            dead_letters.recent(),
+            vec!["1970-01-02T00:00:00Z session 3: unexpected opcode"]
+        );
+
+        // only the latest errors are kept
+        for session in 0..MAX_DEAD_LETTERS as u64 + 5 {
+            dead_letters.push(session, at, "connection reset");
+        }
+        let recent = dead_letters.recent();
+        assert_eq!(recent.len(), MAX_DEAD_LETTERS);
+        assert!(recent[0].contains("session 5:"));
+    }
+}
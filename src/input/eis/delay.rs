@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Artificial input latency for testing apps under laggy remote input.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// FIFO of events held back until their injection deadline.
+///
+/// Every event is due `delay` after it was received, so order and the
+/// relative timing between events are kept.
+#[derive(Debug)]
+pub struct DelayQueue<T> {
+    queue: VecDeque<(Instant, T)>,
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> DelayQueue<T> {
+    /// Queue `item` received at `now`, returning its deadline.
+    pub fn push(&mut self, now: Instant, delay: Duration, item: T) -> Instant {
+        let due = now + delay;
+        self.queue.push_back((due, item));
+        due
+    }
+
+    /// Remove the events whose deadline has passed, oldest first.
+    pub fn pop_due(&mut self, now: Instant) -> Vec<T> {
+        let mut due = Vec::new();
+        while self
+            .queue
+            .front()
+            .is_some_and(|(deadline, _)| *deadline <= now)
+        {
+            due.extend(self.queue.pop_front().map(|(_, item)| item));
+        }
+        due
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.queue.front().map(|(deadline, _)| *deadline)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DelayQueue;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_delay_queue() {
+        let delay = Duration::from_millis(50);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut queue = DelayQueue::default();
+
+        assert_eq!(queue.push(at(0), delay, "a"), at(50));
+        queue.push(at(5), delay, "b");
+        queue.push(at(20), delay, "c");
+
+        // nothing is injected before its delay elapsed
+        assert!(queue.pop_due(at(49)).is_empty());
+        // events come out in order, keeping their spacing
+        assert_eq!(queue.pop_due(at(55)), vec!["a", "b"]);
+        assert_eq!(queue.next_deadline(), Some(at(70)));
+        assert_eq!(queue.pop_due(at(70)), vec!["c"]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Tracking of which of a client's devices are emulating.
+
+/// Devices between their start and stop of emulation. EI clients may only
+/// send events from these, so input from other devices is a handshake race
+/// or a misbehaving client.
+#[derive(Debug)]
+pub struct EmulatingDevices<D> {
+    devices: Vec<D>,
+}
+
+impl<D> Default for EmulatingDevices<D> {
+    fn default() -> Self {
+        Self {
+            devices: Vec::new(),
+        }
+    }
+}
+
+impl<D: PartialEq> EmulatingDevices<D> {
+    pub fn start(&mut self, device: D) {
+        if !self.devices.contains(&device) {
+            self.devices.push(device);
+        }
+    }
+
+    pub fn stop(&mut self, device: &D) {
+        self.devices.retain(|emulating| emulating != device);
+    }
+
+    pub fn is_emulating(&self, device: &D) -> bool {
+        self.devices.contains(device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmulatingDevices;
+
+    #[test]
+    fn test_emulating_devices() {
+        let mut emulating = EmulatingDevices::default();
+        // a button sent before start-emulating is ignored
+        assert!(!emulating.is_emulating(&1));
+
+        emulating.start(1);
+        emulating.start(1);
+        assert!(emulating.is_emulating(&1));
+        // other devices don't emulate along
+        assert!(!emulating.is_emulating(&2));
+
+        emulating.stop(&1);
+        assert!(!emulating.is_emulating(&1));
+    }
+}
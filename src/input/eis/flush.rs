@@ -0,0 +1,49 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Coalescing of a connection's flushes within one event loop wakeup.
+
+/// Whether a connection has unflushed protocol messages. Steps that announce
+/// something mark it instead of flushing, and a single flush at the end of
+/// the wakeup sends everything at once.
+#[derive(Debug, Default)]
+pub struct DeferredFlush {
+    pending: bool,
+}
+
+impl DeferredFlush {
+    /// Mark messages as waiting, returning whether a flush has to be
+    /// scheduled, which is only the case if none is pending yet.
+    pub fn defer(&mut self) -> bool {
+        !std::mem::replace(&mut self.pending, true)
+    }
+
+    /// Clear the pending flush, returning whether there was one to do.
+    pub fn take(&mut self) -> bool {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeferredFlush;
+
+    #[test]
+    fn test_deferred_flush() {
+        let mut flush = DeferredFlush::default();
+        // nothing announced, nothing to flush
+        assert!(!flush.take());
+
+        // a seat, a device and the modifiers announced in one wakeup schedule
+        // a single flush
+        let scheduled = (0..3).filter(|_| flush.defer()).count();
+        assert_eq!(scheduled, 1);
+
+        // which sends them all, leaving nothing for a second one
+        assert!(flush.take());
+        assert!(!flush.take());
+
+        // the next wakeup schedules its own
+        assert!(flush.defer());
+        assert!(flush.take());
+    }
+}
@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Deterministic ordering of the input events within an EIS frame.
+
+use std::collections::VecDeque;
+
+use reis::request::EisRequest;
+
+use super::keys::is_modifier;
+
+/// Kinds of input, in the order they are applied within a frame: modifiers
+/// go first so a click or key in the same frame sees them, the pointer moves
+/// before its buttons act at the new position, and touches come last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventClass {
+    Modifier,
+    Key,
+    Motion,
+    Button,
+    Scroll,
+    Touch,
+}
+
+/// The class of an input request, or `None` for requests that don't inject
+/// input and are never reordered.
+pub fn classify(request: &EisRequest) -> Option<EventClass> {
+    match request {
+        EisRequest::KeyboardKey(key) if is_modifier(key.key) => Some(EventClass::Modifier),
+        EisRequest::KeyboardKey(_) => Some(EventClass::Key),
+        EisRequest::PointerMotion(_) | EisRequest::PointerMotionAbsolute(_) => {
+            Some(EventClass::Motion)
+        }
+        EisRequest::Button(_) => Some(EventClass::Button),
+        EisRequest::ScrollDelta(_) | EisRequest::ScrollDiscrete(_) | EisRequest::ScrollStop(_) => {
+            Some(EventClass::Scroll)
+        }
+        EisRequest::TouchDown(_)
+        | EisRequest::TouchMotion(_)
+        | EisRequest::TouchUp(_)
+        | EisRequest::TouchCancel(_) => Some(EventClass::Touch),
+        _ => None,
+    }
+}
+
+/// Input events of the current frame, held back until it ends.
+#[derive(Debug)]
+pub struct FrameBuffer<T> {
+    events: Vec<(EventClass, T)>,
+    /// Ordered events of an ended frame that didn't fit into one batch.
+    overflow: VecDeque<T>,
+}
+
+impl<T> Default for FrameBuffer<T> {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            overflow: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> FrameBuffer<T> {
+    pub fn push(&mut self, class: EventClass, event: T) {
+        self.events.push((class, event));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.overflow.is_empty()
+    }
+
+    /// Take up to `max` events in application order, or all of them for a
+    /// `max` of 0. Events beyond the limit are handed out by the next calls,
+    /// before those of any later frame.
+    pub fn take_batch(&mut self, max: usize) -> Vec<T> {
+        if self.overflow.is_empty() {
+            self.overflow = self.take_ordered().into();
+        }
+        let count = if max == 0 {
+            self.overflow.len()
+        } else {
+            max.min(self.overflow.len())
+        };
+        self.overflow.drain(..count).collect()
+    }
+
+    /// Take the buffered events in application order. Events of the same
+    /// class keep the order the client sent them in.
+    pub fn take_ordered(&mut self) -> Vec<T> {
+        let mut events = std::mem::take(&mut self.events);
+        events.sort_by_key(|(class, _)| *class);
+        events.into_iter().map(|(_, event)| event).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventClass, FrameBuffer};
+
+    #[test]
+    fn test_frame_order() {
+        let mut frame = FrameBuffer::default();
+        frame.push(EventClass::Touch, "touch down");
+        frame.push(EventClass::Button, "button press");
+        frame.push(EventClass::Motion, "first motion");
+        frame.push(EventClass::Key, "key a");
+        frame.push(EventClass::Scroll, "scroll");
+        frame.push(EventClass::Modifier, "shift");
+        frame.push(EventClass::Motion, "second motion");
+
+        assert_eq!(
+            frame.take_ordered(),
+            vec![
+                "shift",
+                "key a",
+                "first motion",
+                "second motion",
+                "button press",
+                "scroll",
+                "touch down",
+            ]
+        );
+        assert!(frame.is_empty());
+    }
+
+    #[test]
+    fn test_frame_batches() {
+        let mut frame = FrameBuffer::default();
+        for event in 0..10 {
+            frame.push(EventClass::Motion, event);
+        }
+
+        // an oversized frame is split up without losing events
+        assert_eq!(frame.take_batch(4), vec![0, 1, 2, 3]);
+        assert!(!frame.is_empty());
+
+        // a later frame waits for the rest of the earlier one
+        frame.push(EventClass::Modifier, 10);
+        assert_eq!(frame.take_batch(4), vec![4, 5, 6, 7]);
+        // and comes whole once the earlier one is done
+        assert_eq!(frame.take_batch(4), vec![8, 9]);
+        assert_eq!(frame.take_batch(4), vec![10]);
+        assert!(frame.is_empty());
+
+        // without a limit everything is applied at once
+        frame.push(EventClass::Key, 11);
+        frame.push(EventClass::Key, 12);
+        assert_eq!(frame.take_batch(0), vec![11, 12]);
+    }
+}
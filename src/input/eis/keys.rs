@@ -0,0 +1,245 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Tracking of keys held down by an EIS session.
+
+use std::collections::HashSet;
+
+use cosmic_comp_config::eis::{EditingShortcuts, Shortcut};
+
+/// Evdev codes of the modifier and lock keys (linux/input-event-codes.h).
+const MODIFIER_KEYS: &[u32] = &[
+    29,  // KEY_LEFTCTRL
+    42,  // KEY_LEFTSHIFT
+    54,  // KEY_RIGHTSHIFT
+    56,  // KEY_LEFTALT
+    58,  // KEY_CAPSLOCK
+    97,  // KEY_RIGHTCTRL
+    100, // KEY_RIGHTALT
+    125, // KEY_LEFTMETA
+    126, // KEY_RIGHTMETA
+];
+
+pub fn is_modifier(key: u32) -> bool {
+    MODIFIER_KEYS.contains(&key)
+}
+
+/// A semantic editing action pressing its configured shortcut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditingCommand {
+    SelectAll,
+    Copy,
+    Cut,
+    Paste,
+    Undo,
+    Redo,
+}
+
+impl EditingCommand {
+    pub fn shortcut(self, shortcuts: &EditingShortcuts) -> &Shortcut {
+        match self {
+            EditingCommand::SelectAll => &shortcuts.select_all,
+            EditingCommand::Copy => &shortcuts.copy,
+            EditingCommand::Cut => &shortcuts.cut,
+            EditingCommand::Paste => &shortcuts.paste,
+            EditingCommand::Undo => &shortcuts.undo,
+            EditingCommand::Redo => &shortcuts.redo,
+        }
+    }
+}
+
+/// The `(key, pressed)` events of a shortcut, or none if no surface is
+/// focused to receive it.
+pub fn shortcut_sequence(shortcut: &Shortcut, focused: bool) -> Vec<(u32, bool)> {
+    if !focused {
+        return Vec::new();
+    }
+    let modifiers = &shortcut.modifiers;
+    modifiers
+        .iter()
+        .map(|key| (*key, true))
+        .chain([(shortcut.key, true), (shortcut.key, false)])
+        .chain(modifiers.iter().rev().map(|key| (*key, false)))
+        .collect()
+}
+
+/// Keys a session currently holds down.
+#[derive(Debug, Default)]
+pub struct HeldKeys {
+    keys: HashSet<u32>,
+}
+
+impl HeldKeys {
+    /// Record a key event, returning whether it should be injected.
+    ///
+    /// Repeated presses of a held key are the client's autorepeat: they pass
+    /// for regular keys but are dropped for modifiers, which never repeat.
+    /// With `dedup` every repeated press is dropped, as is the release of a
+    /// key that isn't held, to cope with links duplicating events.
+    pub fn update(&mut self, key: u32, pressed: bool, dedup: bool) -> bool {
+        if !pressed {
+            return self.keys.remove(&key) || !dedup;
+        }
+        self.keys.insert(key) || !(dedup || is_modifier(key))
+    }
+
+    pub fn contains(&self, key: u32) -> bool {
+        self.keys.contains(&key)
+    }
+
+    /// Forget all held keys, returning them in the order they should be
+    /// released: regular keys first, then modifiers.
+    pub fn take(&mut self) -> Vec<u32> {
+        let mut keys: Vec<u32> = self.keys.drain().collect();
+        keys.sort_by_key(|key| (is_modifier(*key), *key));
+        keys
+    }
+}
+
+/// Serialized XKB modifier state, as carried by the EIS modifiers event.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub depressed: u32,
+    pub latched: u32,
+    pub locked: u32,
+    pub group: u32,
+}
+
+/// The modifier state last sent to a session's keyboard.
+#[derive(Debug, Default)]
+pub struct ModifierFeedback {
+    last: Option<Modifiers>,
+}
+
+impl ModifierFeedback {
+    /// Record the compositor's current state, returning it if the client
+    /// hasn't seen it yet.
+    pub fn update(&mut self, current: Modifiers) -> Option<Modifiers> {
+        (self.last != Some(current)).then(|| {
+            self.last = Some(current);
+            current
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EditingCommand, HeldKeys, ModifierFeedback, Modifiers, shortcut_sequence};
+    use cosmic_comp_config::eis::EditingShortcuts;
+
+    const KEY_LEFTSHIFT: u32 = 42;
+    const KEY_A: u32 = 30;
+
+    #[test]
+    fn test_modifier_autorepeat() {
+        let mut keys = HeldKeys::default();
+
+        // holding shift doesn't repeat it, a letter key does
+        assert!(keys.update(KEY_LEFTSHIFT, true, false));
+        assert!(!keys.update(KEY_LEFTSHIFT, true, false));
+        assert!(keys.update(KEY_A, true, false));
+        assert!(keys.update(KEY_A, true, false));
+
+        // stuck keys are released before the modifiers
+        assert_eq!(keys.take(), vec![KEY_A, KEY_LEFTSHIFT]);
+        assert!(keys.take().is_empty());
+
+        // a released modifier can be pressed again
+        assert!(keys.update(KEY_LEFTSHIFT, true, false));
+        assert!(keys.update(KEY_LEFTSHIFT, false, false));
+        assert!(keys.update(KEY_LEFTSHIFT, true, false));
+    }
+
+    #[test]
+    fn test_dedup_keys() {
+        let mut keys = HeldKeys::default();
+        assert!(keys.update(KEY_A, true, true));
+        // a duplicated press is ignored
+        assert!(!keys.update(KEY_A, true, true));
+        assert!(keys.update(KEY_A, false, true));
+        // as is a duplicated release
+        assert!(!keys.update(KEY_A, false, true));
+
+        // without dedup stray releases still pass
+        assert!(keys.update(KEY_A, false, false));
+    }
+
+    #[test]
+    fn test_caps_lock_feedback() {
+        // the Lock modifier of the standard XKB keymaps
+        const LOCK_MASK: u32 = 1 << 1;
+        let mut feedback = ModifierFeedback::default();
+
+        // pressing Caps Lock reports it depressed and locked
+        let pressed = Modifiers {
+            depressed: LOCK_MASK,
+            locked: LOCK_MASK,
+            ..Default::default()
+        };
+        let sent = feedback.update(pressed).unwrap();
+        assert_eq!(sent.locked & LOCK_MASK, LOCK_MASK);
+
+        // releasing it keeps the lock
+        let released = Modifiers {
+            locked: LOCK_MASK,
+            ..Default::default()
+        };
+        assert_eq!(feedback.update(released), Some(released));
+
+        // an unchanged state isn't resent
+        assert_eq!(feedback.update(released), None);
+    }
+
+    #[test]
+    fn test_paste_sequence() {
+        const KEY_LEFTCTRL: u32 = 29;
+        const KEY_V: u32 = 47;
+        let shortcuts = EditingShortcuts::default();
+        let paste = EditingCommand::Paste.shortcut(&shortcuts);
+        assert_eq!(
+            shortcut_sequence(paste, true),
+            vec![
+                (KEY_LEFTCTRL, true),
+                (KEY_V, true),
+                (KEY_V, false),
+                (KEY_LEFTCTRL, false),
+            ]
+        );
+        // nothing to paste into
+        assert!(shortcut_sequence(paste, false).is_empty());
+    }
+
+    #[test]
+    fn test_editing_sequences() {
+        const KEY_LEFTCTRL: u32 = 29;
+        let shortcuts = EditingShortcuts::default();
+        let ctrl = |key| {
+            vec![
+                (KEY_LEFTCTRL, true),
+                (key, true),
+                (key, false),
+                (KEY_LEFTCTRL, false),
+            ]
+        };
+        for (command, key) in [
+            (EditingCommand::SelectAll, 30), // KEY_A
+            (EditingCommand::Copy, 46),      // KEY_C
+            (EditingCommand::Cut, 45),       // KEY_X
+            (EditingCommand::Undo, 44),      // KEY_Z
+        ] {
+            let sequence = shortcut_sequence(command.shortcut(&shortcuts), true);
+            assert_eq!(sequence, ctrl(key), "{command:?}");
+        }
+        // modifiers are released in reverse
+        assert_eq!(
+            shortcut_sequence(EditingCommand::Redo.shortcut(&shortcuts), true),
+            vec![
+                (KEY_LEFTCTRL, true),
+                (KEY_LEFTSHIFT, true),
+                (44, true),
+                (44, false),
+                (KEY_LEFTSHIFT, false),
+                (KEY_LEFTCTRL, false),
+            ]
+        );
+    }
+}
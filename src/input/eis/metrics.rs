@@ -0,0 +1,308 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Counters of injected and rejected EIS events, for monitoring.
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use reis::request::EisRequest;
+
+use super::throttle::LogThrottle;
+
+/// Minimum interval between log lines for one rejection reason.
+const REJECTION_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Kind of an injected event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Injected {
+    Key,
+    Button,
+    Motion,
+    AbsoluteMotion,
+    Scroll,
+    Touch,
+}
+
+impl Injected {
+    const ALL: [Injected; 6] = [
+        Injected::Key,
+        Injected::Button,
+        Injected::Motion,
+        Injected::AbsoluteMotion,
+        Injected::Scroll,
+        Injected::Touch,
+    ];
+
+    /// The kind of input `request` injects, if any.
+    pub fn of(request: &EisRequest) -> Option<Self> {
+        Some(match request {
+            EisRequest::KeyboardKey(_) => Injected::Key,
+            EisRequest::Button(_) => Injected::Button,
+            EisRequest::PointerMotion(_) => Injected::Motion,
+            EisRequest::PointerMotionAbsolute(_) => Injected::AbsoluteMotion,
+            EisRequest::ScrollDelta(_)
+            | EisRequest::ScrollDiscrete(_)
+            | EisRequest::ScrollStop(_) => Injected::Scroll,
+            EisRequest::TouchDown(_)
+            | EisRequest::TouchMotion(_)
+            | EisRequest::TouchUp(_)
+            | EisRequest::TouchCancel(_) => Injected::Touch,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Injected::Key => "key",
+            Injected::Button => "button",
+            Injected::Motion => "motion",
+            Injected::AbsoluteMotion => "absolute_motion",
+            Injected::Scroll => "scroll",
+            Injected::Touch => "touch",
+        }
+    }
+}
+
+/// Injected events of one session by kind.
+#[derive(Debug, Default, Clone)]
+pub struct InjectedCounts {
+    counts: [u64; Injected::ALL.len()],
+}
+
+impl InjectedCounts {
+    pub fn count(&mut self, kind: Injected) {
+        self.counts[kind as usize] += 1;
+    }
+
+    /// All counts by kind name, e.g. `key` or `absolute_motion`.
+    pub fn by_name(&self) -> HashMap<String, u64> {
+        Injected::ALL
+            .iter()
+            .map(|kind| (kind.name().to_string(), self.counts[*kind as usize]))
+            .collect()
+    }
+}
+
+/// Why an event was not injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    NonFinite,
+    OutOfRange,
+    RateLimited,
+    Budget,
+    Capability,
+    Unfocused,
+    Arbitration,
+    DuplicateKey,
+    Jump,
+    NotDown,
+    SurfaceRejected,
+    NoSeat,
+    DeviceLimit,
+    NotEmulating,
+    Paused,
+}
+
+impl Rejection {
+    const ALL: [Rejection; 15] = [
+        Rejection::NonFinite,
+        Rejection::OutOfRange,
+        Rejection::RateLimited,
+        Rejection::Budget,
+        Rejection::Capability,
+        Rejection::Unfocused,
+        Rejection::Arbitration,
+        Rejection::DuplicateKey,
+        Rejection::Jump,
+        Rejection::NotDown,
+        Rejection::SurfaceRejected,
+        Rejection::NoSeat,
+        Rejection::DeviceLimit,
+        Rejection::NotEmulating,
+        Rejection::Paused,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Rejection::NonFinite => "non_finite",
+            Rejection::OutOfRange => "out_of_range",
+            Rejection::RateLimited => "rate_limited",
+            Rejection::Budget => "budget",
+            Rejection::Capability => "capability",
+            Rejection::Unfocused => "unfocused",
+            Rejection::Arbitration => "arbitration",
+            Rejection::DuplicateKey => "duplicate_key",
+            Rejection::Jump => "jump",
+            Rejection::NotDown => "not_down",
+            Rejection::SurfaceRejected => "surface_rejected",
+            Rejection::NoSeat => "no_seat",
+            Rejection::DeviceLimit => "device_limit",
+            Rejection::NotEmulating => "not_emulating",
+            Rejection::Paused => "paused",
+        }
+    }
+}
+
+/// Why an EIS connection couldn't be set up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionFailure {
+    /// The process is out of, or close to running out of, file descriptors.
+    FdPressure,
+    /// Any other failure creating the EIS context.
+    Context,
+}
+
+impl ConnectionFailure {
+    const ALL: [ConnectionFailure; 2] = [ConnectionFailure::FdPressure, ConnectionFailure::Context];
+
+    /// The failure `err` of creating a context stands for.
+    pub fn of(err: &io::Error) -> Self {
+        match err.raw_os_error() {
+            Some(libc::EMFILE | libc::ENFILE) => ConnectionFailure::FdPressure,
+            _ => ConnectionFailure::Context,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ConnectionFailure::FdPressure => "fd_pressure",
+            ConnectionFailure::Context => "context",
+        }
+    }
+}
+
+/// Event counters across all sessions since the EIS state was created.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    injected: [AtomicU64; Injected::ALL.len()],
+    rejected: [AtomicU64; Rejection::ALL.len()],
+    failed_connections: [AtomicU64; ConnectionFailure::ALL.len()],
+}
+
+impl Metrics {
+    pub fn injected(&self, kind: Injected) {
+        self.injected[kind as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rejected(&self, reason: Rejection) {
+        self.rejected[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_failed(&self, failure: ConnectionFailure) {
+        self.failed_connections[failure as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// All counters by name, e.g. `injected_key`, `rejected_non_finite` or
+    /// `failed_connection_fd_pressure`, together with the number of
+    /// `active_connections`.
+    pub fn snapshot(&self, active_connections: u64) -> HashMap<String, u64> {
+        let injected = Injected::ALL.iter().map(|kind| {
+            let count = self.injected[*kind as usize].load(Ordering::Relaxed);
+            (format!("injected_{}", kind.name()), count)
+        });
+        let rejected = Rejection::ALL.iter().map(|reason| {
+            let count = self.rejected[*reason as usize].load(Ordering::Relaxed);
+            (format!("rejected_{}", reason.name()), count)
+        });
+        let failed = ConnectionFailure::ALL.iter().map(|failure| {
+            let count = self.failed_connections[*failure as usize].load(Ordering::Relaxed);
+            (format!("failed_connection_{}", failure.name()), count)
+        });
+        injected
+            .chain(rejected)
+            .chain(failed)
+            .chain([("active_connections".to_string(), active_connections)])
+            .collect()
+    }
+}
+
+/// Samples rejection logging across all sessions: the first rejection for a
+/// reason is logged, then at most one per second summarizing how many were
+/// left out in between.
+#[derive(Debug, Default)]
+pub struct RejectionLog {
+    throttles: [LogThrottle; Rejection::ALL.len()],
+}
+
+impl RejectionLog {
+    /// Returns `Some(suppressed)` if a rejection for `reason` at `now` should
+    /// be logged, with the number left out since the last line.
+    pub fn sample(&mut self, reason: Rejection, now: Instant) -> Option<u64> {
+        self.throttles[reason as usize].check(now, REJECTION_LOG_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConnectionFailure, Injected, Metrics, Rejection, RejectionLog};
+    use std::{
+        io,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_metrics() {
+        let metrics = Metrics::default();
+        for kind in [Injected::Key, Injected::Key, Injected::Touch] {
+            metrics.injected(kind);
+        }
+        metrics.rejected(Rejection::NonFinite);
+        metrics.rejected(Rejection::OutOfRange);
+        metrics.rejected(Rejection::OutOfRange);
+
+        let snapshot = metrics.snapshot(2);
+        assert_eq!(snapshot["injected_key"], 2);
+        assert_eq!(snapshot["injected_touch"], 1);
+        assert_eq!(snapshot["injected_scroll"], 0);
+        assert_eq!(snapshot["rejected_non_finite"], 1);
+        assert_eq!(snapshot["rejected_out_of_range"], 2);
+        assert_eq!(snapshot["active_connections"], 2);
+        // every counter is reported, even before it was hit
+        assert_eq!(snapshot.len(), 6 + 15 + 2 + 1);
+    }
+
+    #[test]
+    fn test_connection_failures() {
+        let metrics = Metrics::default();
+        for err in [
+            io::Error::from_raw_os_error(libc::EMFILE),
+            io::Error::from_raw_os_error(libc::ENFILE),
+            io::Error::from_raw_os_error(libc::EINVAL),
+        ] {
+            metrics.connection_failed(ConnectionFailure::of(&err));
+        }
+
+        let snapshot = metrics.snapshot(0);
+        assert_eq!(snapshot["failed_connection_fd_pressure"], 2);
+        assert_eq!(snapshot["failed_connection_context"], 1);
+        // failed connections aren't rejected events
+        assert!(
+            snapshot
+                .iter()
+                .filter(|(name, _)| name.starts_with("rejected_"))
+                .all(|(_, count)| *count == 0)
+        );
+    }
+
+    #[test]
+    fn test_rejection_log() {
+        let mut log = RejectionLog::default();
+        let start = Instant::now();
+        // a client sending 1000 bad keycodes within a second
+        let lines = (0..1000)
+            .filter_map(|i| log.sample(Rejection::OutOfRange, start + Duration::from_millis(i)))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![0]);
+
+        // other reasons are sampled on their own
+        assert_eq!(log.sample(Rejection::NonFinite, start), Some(0));
+
+        // the next line summarizes the rest
+        let later = start + Duration::from_secs(1);
+        assert_eq!(log.sample(Rejection::OutOfRange, later), Some(999));
+    }
+}
@@ -0,0 +1,5689 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! EIS (Emulated Input Server) receiver for remote desktop input injection.
+//!
+//! Accepts input events from EIS clients (e.g., xdg-desktop-portal-cosmic
+//! RemoteDesktop sessions) and injects them into the compositor's input stack
+//! using the same Smithay APIs as the normal input pipeline.
+//!
+//! Uses `reis::calloop::EisRequestSource` to process EIS protocol events
+//! directly on the compositor's calloop event loop (no background threads).
+
+use calloop::timer::{TimeoutAction, Timer};
+use cosmic_comp_config::{
+    XkbConfig,
+    eis::{
+        AbsoluteOrigin, BudgetPolicy, ConnectionPacing, ContextMenuInput, CoordinateRounding,
+        EisConfig, FrameOrdering, JumpPolicy, PointerClamp, RejectedInput, SeatFallback, Shortcut,
+        TouchFocus,
+    },
+};
+use futures_channel::oneshot;
+use reis::{calloop::EisRequestSourceEvent, eis, event::DeviceCapability, request::EisRequest};
+use smithay::{
+    backend::input::{KeyState, TouchSlot},
+    desktop::{WindowSurfaceType, layer_map_for_output},
+    input::{
+        Seat,
+        keyboard::{FilterResult, Keycode},
+        touch::{DownEvent, MotionEvent as TouchMotionEvent, UpEvent},
+    },
+    output::Output,
+    reexports::wayland_server::{Resource, protocol::wl_surface::WlSurface},
+    utils::{Point, Rectangle, SERIAL_COUNTER},
+    wayland::shell::wlr_layer::Layer,
+};
+use std::collections::HashMap;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::shell::focus::target::{KeyboardFocusTarget, PointerFocusTarget};
+use crate::shell::{SeatExt, Shell};
+use crate::state::State;
+use crate::utils::geometry::{Global, PointExt, PointGlobalExt, PointLocalExt, SizeExt};
+use crate::utils::prelude::OutputExt;
+
+mod arbitration;
+mod budget;
+mod capabilities;
+mod capture;
+mod dead_letter;
+mod delay;
+mod emulation;
+mod flush;
+mod frame;
+mod keys;
+mod metrics;
+mod motion;
+mod path;
+mod rate_limit;
+mod scroll;
+mod session;
+mod surface_cache;
+mod throttle;
+mod timestamp;
+mod touch;
+
+use arbitration::PointerArbiter;
+pub use capture::CapturedEvent;
+use capture::EventCapture;
+use dead_letter::DeadLetters;
+use frame::EventClass;
+pub use keys::EditingCommand;
+use keys::{ModifierFeedback, Modifiers};
+use metrics::{ConnectionFailure, Injected, Metrics, Rejection, RejectionLog};
+use motion::Motion;
+use path::{PATH_STEP, PointerPath};
+use rate_limit::ConnectionPacer;
+pub use session::{Calibration, EisSession, KeymapInfo, SessionId, SessionInfo};
+use session::{Release, sanitize_client_name, truncate_client_name};
+use surface_cache::SurfaceUnderCache;
+use touch::TouchSlots;
+
+/// Scroll distance of one wheel click (120 v120 units), matching the axis
+/// value libinput reports for wheels.
+const WHEEL_STEP_DISTANCE: f64 = 15.0;
+
+/// Maximum valid evdev keycode (KEY_MAX from linux/input-event-codes.h).
+const MAX_EVDEV_KEYCODE: u32 = 0x2FF;
+
+/// Default maximum touch ID (generous upper bound; real devices rarely exceed
+/// 20), and the range a configured maximum is kept within.
+const DEFAULT_MAX_TOUCH_ID: u32 = 256;
+const MAX_TOUCH_ID_RANGE: std::ops::RangeInclusive<u32> = 1..=4096;
+
+/// Minimum interval between repeated warnings about one session's rejected
+/// events.
+const REJECTION_WARN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// `(app_id, x, y, width, height)` of a focused window.
+pub type FocusInfo = (String, i32, i32, i32, i32);
+
+/// Credentials of a D-Bus caller handing over an EIS socket.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    pub uid: Option<u32>,
+}
+
+/// Requests sent from the D-Bus interface to the compositor's event loop.
+#[derive(Debug)]
+pub enum EisCommand {
+    /// Restrict a session's injection to a focused app_id, or lift the
+    /// restriction with `None`. Replies `false` if the session is unknown.
+    SetRequiredAppId {
+        session: SessionId,
+        app_id: Option<String>,
+        sync: oneshot::Sender<bool>,
+    },
+    /// Set (or clear with `None`) the operator label of a session. Replies
+    /// `false` if the session is unknown.
+    SetLabel {
+        session: SessionId,
+        label: Option<String>,
+        sync: oneshot::Sender<bool>,
+    },
+    /// Delay a session's input by a fixed latency, or inject it immediately
+    /// again for `None`. Replies whether the session exists.
+    SetInputDelay {
+        session: SessionId,
+        delay: Option<Duration>,
+        sync: oneshot::Sender<bool>,
+    },
+    /// Calibrate a session's absolute and touch coordinates. Replies `None`
+    /// if the session is unknown and `Some(false)` if the offset would move
+    /// input outside the layout.
+    SetCalibration {
+        session: SessionId,
+        calibration: Calibration,
+        sync: oneshot::Sender<Option<bool>>,
+    },
+    /// Interpret a session's absolute coordinates relative to the named
+    /// output, or as global coordinates again for `None`. Replies whether the
+    /// session exists.
+    SetTargetOutput {
+        session: SessionId,
+        output: Option<String>,
+        sync: oneshot::Sender<bool>,
+    },
+    /// Move a session's pointer through global `points` over `duration`.
+    /// Replies `None` if the session is unknown and `Some(false)` if the path
+    /// is empty or leaves the output layout.
+    MovePointerPath {
+        session: SessionId,
+        points: Vec<(f64, f64)>,
+        duration: Duration,
+        sync: oneshot::Sender<Option<bool>>,
+    },
+    /// Report whether a session currently controls the pointer, or `None` if
+    /// the session is unknown.
+    IsPointerOwner {
+        session: SessionId,
+        sync: oneshot::Sender<Option<bool>>,
+    },
+    /// Move the session's pointer straight to a global position, reporting
+    /// whether it moved, or `None` if the session is unknown.
+    WarpPointer {
+        session: SessionId,
+        x: f64,
+        y: f64,
+        sync: oneshot::Sender<Option<bool>>,
+    },
+    /// Press the configured shortcut of an editing command such as paste on
+    /// the session's keyboard, reporting whether it was pressed, or `None` if
+    /// the session is unknown.
+    Edit {
+        session: SessionId,
+        command: EditingCommand,
+        sync: oneshot::Sender<Option<bool>>,
+    },
+    /// Open a context menu by pressing the Menu key or right-clicking at the
+    /// pointer, as configured, reporting whether the seat had the device to
+    /// do it, or `None` if the session is unknown.
+    ContextMenu {
+        session: SessionId,
+        sync: oneshot::Sender<Option<bool>>,
+    },
+    /// Press the configured close shortcut if a window, with `app_id` if
+    /// given, is focused on the session's seat. Replies whether it was
+    /// pressed, or `None` if the session is unknown.
+    CloseFocusedWindow {
+        session: SessionId,
+        app_id: Option<String>,
+        sync: oneshot::Sender<Option<bool>>,
+    },
+    /// Report whether the session's event source is registered on the event
+    /// loop, or `None` if the session is unknown.
+    SourceRegistered {
+        session: SessionId,
+        sync: oneshot::Sender<Option<bool>>,
+    },
+    /// Report whether the session's last key event had a focused surface to
+    /// receive it, or `None` if the session is unknown.
+    LastKeyHadFocus {
+        session: SessionId,
+        sync: oneshot::Sender<Option<bool>>,
+    },
+    /// Report the app_id and global geometry of the window focused on the
+    /// session's seat, or `None` if the session is unknown.
+    FocusInfo {
+        session: SessionId,
+        sync: oneshot::Sender<Option<FocusInfo>>,
+    },
+    /// Give a session's keyboard the keymap of these RMLVO names, or the
+    /// compositor's again with an empty layout. Replies `None` if the session
+    /// is unknown.
+    SetKeymap {
+        session: SessionId,
+        names: KeymapInfo,
+        sync: oneshot::Sender<Option<Result<(), EisError>>>,
+    },
+    /// Report the RMLVO names of a session's keymap, or `None` if the session
+    /// is unknown.
+    KeymapInfo {
+        session: SessionId,
+        sync: oneshot::Sender<Option<KeymapInfo>>,
+    },
+    /// Report the XKB keymap text sent to a session's keyboard, or `None` if
+    /// the session is unknown.
+    Keymap {
+        session: SessionId,
+        sync: oneshot::Sender<Option<Result<String, EisError>>>,
+    },
+    /// Report `(unread, unsent)` bytes queued on a session's socket, or `None`
+    /// if the session is unknown.
+    SocketOccupancy {
+        session: SessionId,
+        sync: oneshot::Sender<Option<(u32, u32)>>,
+    },
+    /// Report the `(granted, denied)` capabilities of a session's last bind,
+    /// or `None` if the session is unknown.
+    Capabilities {
+        session: SessionId,
+        sync: oneshot::Sender<Option<(Vec<String>, Vec<String>)>>,
+    },
+    /// Report `(consumed, budget)` of a session's rolling event budget, or
+    /// `None` if the session is unknown.
+    EventBudget {
+        session: SessionId,
+        sync: oneshot::Sender<Option<(u64, u64)>>,
+    },
+    /// Report when a session last sent an event per capability, or `None`
+    /// if the session is unknown.
+    CapabilityActivity {
+        session: SessionId,
+        sync: oneshot::Sender<Option<HashMap<String, u64>>>,
+    },
+    /// Enter or leave drain mode, e.g. ahead of a planned restart.
+    SetDrainMode {
+        enabled: bool,
+        sync: oneshot::Sender<()>,
+    },
+    /// Report all live sessions.
+    ListSessions {
+        sync: oneshot::Sender<Vec<SessionInfo>>,
+    },
+    /// Report the number of open connections.
+    ActiveSessions { sync: oneshot::Sender<u32> },
+    /// Report whether a caller that isn't the portal may hand over sockets.
+    CallerAllowed {
+        caller: Caller,
+        sync: oneshot::Sender<bool>,
+    },
+    /// Forcibly end a session. Replies whether the session existed.
+    DisconnectSession {
+        session: SessionId,
+        sync: oneshot::Sender<bool>,
+    },
+    /// Report `(hits, misses, hit_rate)` of the surface-under cache.
+    SurfaceCacheStats {
+        sync: oneshot::Sender<(u64, u64, f64)>,
+    },
+    /// Report the most recent errors that ended connections, oldest first.
+    RecentErrors { sync: oneshot::Sender<Vec<String>> },
+    /// Start or stop capturing input instead of injecting it.
+    SetCapture {
+        enabled: bool,
+        sync: oneshot::Sender<()>,
+    },
+    /// Report the captured input events, oldest first.
+    CapturedEvents {
+        sync: oneshot::Sender<Vec<CapturedEvent>>,
+    },
+    /// Report the event counters and the number of active connections.
+    Metrics {
+        sync: oneshot::Sender<HashMap<String, u64>>,
+    },
+}
+
+/// Session lifecycle changes, announced as D-Bus signals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// A client completed the EIS handshake.
+    Opened { id: SessionId, client_name: String },
+    /// A session that was opened went away.
+    Closed(SessionId),
+}
+
+/// Manages EIS connections on the compositor's calloop event loop.
+#[derive(Debug)]
+pub struct EisState {
+    evlh: calloop::LoopHandle<'static, State>,
+    active_connections: AtomicUsize,
+    sessions: HashMap<SessionId, EisSession>,
+    next_session_id: SessionId,
+    surface_cache: SurfaceUnderCache<PointerFocusTarget>,
+    touch_slots: TouchSlots,
+    /// Name of the seat each touch slot was put down on.
+    touch_seats: HashMap<u32, String>,
+    pointer_arbiter: PointerArbiter,
+    /// Whether the timer lifting stale touches is scheduled.
+    touch_timer_armed: bool,
+    /// Refuse new connections while existing sessions carry on.
+    draining: bool,
+    /// Sessions allowed at once; existing sessions are kept when lowered.
+    max_connections: usize,
+    /// Highest touch ID accepted from clients.
+    max_touch_id: u32,
+    /// Silence after which a connection is dropped; zero disables.
+    idle_timeout: Duration,
+    /// Where session lifecycle changes are sent for the D-Bus task.
+    session_events: Option<std::sync::mpsc::Sender<SessionEvent>>,
+    /// Errors that ended connections.
+    dead_letters: DeadLetters,
+    /// Injected and rejected events by kind and reason.
+    metrics: Metrics,
+    /// Sampling of rejection warnings that aren't tied to a session.
+    rejection_log: RejectionLog,
+    /// Input recorded instead of injected while capturing.
+    capture: EventCapture,
+    /// Spacing of accepted connections by the minimum connection interval.
+    connection_pacer: ConnectionPacer,
+    /// Whether injection is suspended while the compositor's session is
+    /// inactive.
+    paused: bool,
+    /// When input was last injected for any session, and whether that keeps
+    /// the compositor from idling.
+    last_injected: Option<Instant>,
+    idle_inhibited: bool,
+    /// The keymap text last compiled for a session's keyboard.
+    keymap_cache: KeymapCache,
+}
+
+impl EisState {
+    /// Create a new EIS state accepting up to `max_connections` sessions.
+    pub fn new(
+        evlh: &calloop::LoopHandle<'static, State>,
+        max_connections: usize,
+    ) -> Result<Self, EisError> {
+        info!("EIS input receiver initialized");
+        Ok(Self {
+            evlh: evlh.clone(),
+            max_connections,
+            active_connections: AtomicUsize::new(0),
+            sessions: HashMap::new(),
+            next_session_id: 1,
+            surface_cache: SurfaceUnderCache::default(),
+            touch_slots: TouchSlots::default(),
+            touch_seats: HashMap::new(),
+            pointer_arbiter: PointerArbiter::default(),
+            touch_timer_armed: false,
+            draining: false,
+            max_touch_id: DEFAULT_MAX_TOUCH_ID,
+            idle_timeout: Duration::ZERO,
+            session_events: None,
+            dead_letters: DeadLetters::default(),
+            metrics: Metrics::default(),
+            rejection_log: RejectionLog::default(),
+            capture: EventCapture::default(),
+            connection_pacer: ConnectionPacer::default(),
+            paused: false,
+            last_injected: None,
+            idle_inhibited: false,
+            keymap_cache: KeymapCache::default(),
+        })
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Suspend or resume injection, telling every bound device it is paused
+    /// or resumed. Returns whether that changed anything.
+    pub fn set_paused(&mut self, paused: bool) -> bool {
+        if self.paused == paused {
+            return false;
+        }
+        self.paused = paused;
+        info!(paused, "EIS input pause changed");
+        for session in self.sessions.values() {
+            for device in &session.bound_devices {
+                if paused {
+                    device.paused();
+                } else {
+                    device.resumed();
+                }
+            }
+            if let Some(connection) = &session.connection
+                && let Err(e) = connection.flush()
+            {
+                warn!(session = session.id, "Failed to flush EIS pause: {e}");
+            }
+        }
+        true
+    }
+
+    /// Record the `events` of a D-Bus command in place of injecting them
+    /// while capturing, returning whether they were.
+    fn capture_command(
+        &mut self,
+        session_id: SessionId,
+        time: u32,
+        events: impl FnOnce() -> Vec<String>,
+    ) -> bool {
+        if !self.capture.is_enabled() {
+            return false;
+        }
+        for event in events() {
+            self.capture.push(session_id, time, event);
+        }
+        true
+    }
+
+    /// While capturing, validated input is recorded for `GetCapturedEvents`
+    /// instead of being injected.
+    pub fn set_capture(&mut self, enabled: bool) {
+        if self.capture.is_enabled() != enabled {
+            info!(enabled, "EIS input capture changed");
+        }
+        self.capture.set_enabled(enabled);
+    }
+
+    /// Keep the error that ended a session's connection for `RecentErrors`.
+    fn record_connection_error(&mut self, session_id: SessionId, error: &str) {
+        self.dead_letters.push(session_id, SystemTime::now(), error);
+    }
+
+    pub fn set_session_events(&mut self, events: std::sync::mpsc::Sender<SessionEvent>) {
+        self.session_events = Some(events);
+    }
+
+    fn notify(&self, event: SessionEvent) {
+        if let Some(events) = &self.session_events {
+            let _ = events.send(event);
+        }
+    }
+
+    /// Record the name a session's client announced in its handshake and
+    /// report the session as opened.
+    fn open_session(&mut self, session_id: SessionId, client_name: String) {
+        let Some(session) = self.sessions.get_mut(&session_id) else {
+            return;
+        };
+        session.client_name = client_name.clone();
+        session.opened = true;
+        self.notify(SessionEvent::Opened {
+            id: session_id,
+            client_name,
+        });
+    }
+
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.max_connections = max_connections;
+    }
+
+    /// Set the highest touch ID accepted, kept within `MAX_TOUCH_ID_RANGE`.
+    pub fn set_max_touch_id(&mut self, max_touch_id: u32) {
+        let clamped = max_touch_id.clamp(*MAX_TOUCH_ID_RANGE.start(), *MAX_TOUCH_ID_RANGE.end());
+        if clamped != max_touch_id {
+            warn!(
+                max_touch_id,
+                clamped, "Configured EIS max_touch_id is out of range"
+            );
+        }
+        self.max_touch_id = clamped;
+    }
+
+    /// When a connection arriving at `now` may be set up with
+    /// [`EisState::add_connection`]: `None` right away, or the time it waits
+    /// for in the queue. Connections arriving within the minimum connection
+    /// interval are refused unless the policy queues them.
+    pub fn pace_connection(
+        &mut self,
+        config: &EisConfig,
+        now: Instant,
+    ) -> Result<Option<Instant>, ConnectionRejected> {
+        let interval = Duration::from_millis(config.min_connection_interval_ms);
+        if interval.is_zero() {
+            return Ok(None);
+        }
+        let slot = match config.connection_pacing {
+            ConnectionPacing::Refuse => self.connection_pacer.admit(now, interval).then_some(now),
+            ConnectionPacing::Queue => {
+                self.connection_pacer
+                    .reserve(now, interval, MAX_QUEUED_CONNECTIONS)
+            }
+        };
+        match slot {
+            Some(slot) if slot <= now => Ok(None),
+            Some(slot) => Ok(Some(slot)),
+            None => {
+                warn!("Rejecting EIS connection: arriving too fast");
+                Err(ConnectionRejected::TooFast(interval))
+            }
+        }
+    }
+
+    /// Set how long a connection may stay silent. Applies to connections
+    /// accepted from now on; zero stops the timers of existing ones.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Disconnect a session that sent no request within the idle timeout.
+    /// Returns when to check it again, or `None` once the session is gone or
+    /// the timeout is disabled.
+    fn check_idle(&mut self, session_id: SessionId, now: Instant) -> Option<Instant> {
+        let session = self.sessions.get_mut(&session_id)?;
+        if self.idle_timeout.is_zero() {
+            session.idle_timer = None;
+            return None;
+        }
+        let deadline = session.last_request + self.idle_timeout;
+        if now < deadline {
+            return Some(deadline);
+        }
+        // The timer goes away by returning, not by being removed
+        session.idle_timer = None;
+        warn!(
+            session = session_id,
+            timeout_ms = self.idle_timeout.as_millis() as u64,
+            "Disconnecting EIS session that stayed silent"
+        );
+        self.disconnect_session(session_id);
+        None
+    }
+
+    fn arm_idle_timer(&mut self, session_id: SessionId) {
+        if self.idle_timeout.is_zero() {
+            return;
+        }
+        let timer = Timer::from_duration(self.idle_timeout);
+        let res = self.evlh.insert_source(timer, move |now, _, state| {
+            state
+                .common
+                .eis_state
+                .as_mut()
+                .and_then(|eis_state| eis_state.check_idle(session_id, now))
+                .map_or(TimeoutAction::Drop, TimeoutAction::ToInstant)
+        });
+        match res {
+            Ok(token) => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.idle_timer = Some(token);
+                }
+            }
+            Err(err) => warn!(
+                session = session_id,
+                "Failed to schedule EIS idle timeout: {}", err.error
+            ),
+        }
+    }
+
+    fn touch_id_allowed(&self, touch_id: u32) -> bool {
+        touch_id <= self.max_touch_id
+    }
+
+    /// Whether recent remote input keeps the compositor from idling.
+    pub fn inhibits_idle(&self) -> bool {
+        self.idle_inhibited
+    }
+
+    /// Note input injected at `now`, inhibiting idle until no input came for
+    /// `window`. Returns whether the inhibition just started.
+    fn note_injected(&mut self, now: Instant, window: Duration) -> bool {
+        self.last_injected = Some(now);
+        if window.is_zero() || self.idle_inhibited {
+            return false;
+        }
+        let timer = Timer::from_deadline(now + window);
+        let res = self.evlh.insert_source(timer, move |now, _, state| {
+            let window = Duration::from_millis(
+                state
+                    .common
+                    .config
+                    .cosmic_conf
+                    .eis_config
+                    .idle_inhibit_window_ms,
+            );
+            let Some(eis_state) = state.common.eis_state.as_mut() else {
+                return TimeoutAction::Drop;
+            };
+            if idle_inhibited(eis_state.last_injected, now, window)
+                && let Some(last) = eis_state.last_injected
+            {
+                return TimeoutAction::ToInstant(last + window);
+            }
+            debug!("EIS sessions went idle, releasing idle inhibition");
+            eis_state.idle_inhibited = false;
+            state.common.refresh_idle_inhibit();
+            TimeoutAction::Drop
+        });
+        if let Err(err) = res {
+            warn!("Failed to schedule EIS idle inhibition: {}", err.error);
+            return false;
+        }
+        debug!("Inhibiting idle for EIS input");
+        self.idle_inhibited = true;
+        true
+    }
+
+    /// Count an event carrying NaN/Inf `values` as rejected. These point at a
+    /// buggy client rather than a bad value, so they are tracked apart from
+    /// out-of-range rejections and only warned about periodically per session.
+    fn check_finite(
+        &mut self,
+        session_id: SessionId,
+        event: &'static str,
+        (x, y): (f64, f64),
+    ) -> Result<(), Rejection> {
+        if x.is_finite() && y.is_finite() {
+            return Ok(());
+        }
+        self.metrics.rejected(Rejection::NonFinite);
+        let Some(session) = self.sessions.get_mut(&session_id) else {
+            return Err(Rejection::NonFinite);
+        };
+        session.rejected_non_finite += 1;
+        if let Some(suppressed) = session
+            .non_finite_warning
+            .check(Instant::now(), REJECTION_WARN_INTERVAL)
+        {
+            warn!(
+                session = session_id,
+                event,
+                total = session.rejected_non_finite,
+                suppressed,
+                "Rejecting EIS event: non-finite values"
+            );
+        }
+        Err(Rejection::NonFinite)
+    }
+
+    /// Drop the cached surface-under result after the scene changed.
+    pub fn invalidate_surface_cache(&mut self) {
+        self.surface_cache.invalidate();
+    }
+
+    /// Drop the cached surface-under result if a commit of `surface` may have
+    /// changed it: one of the surface tree it found, or of a popup, which can
+    /// open over anything. Windows being mapped, moved, resized or raised
+    /// already change the scene the result was cached for.
+    pub fn surface_committed(&mut self, surface: &WlSurface) {
+        use smithay::wayland::{
+            compositor::get_role, seat::WaylandFocus, shell::xdg::XDG_POPUP_ROLE,
+        };
+
+        if get_role(surface) == Some(XDG_POPUP_ROLE) {
+            self.surface_cache.invalidate();
+            return;
+        }
+        let root = root_surface(surface);
+        self.surface_cache.invalidate_if(|target| {
+            target
+                .wl_surface()
+                .is_some_and(|cached| root_surface(&cached) == root)
+        });
+    }
+
+    /// Handle a request forwarded from the D-Bus interface.
+    pub fn handle_command(&mut self, config: &EisConfig, shell: &Shell, command: EisCommand) {
+        match command {
+            EisCommand::SetCalibration {
+                session,
+                calibration,
+                sync,
+            } => {
+                let layout = shell
+                    .outputs()
+                    .map(|output| output.geometry())
+                    .reduce(|layout, geometry| layout.merge(geometry))
+                    .unwrap_or_default();
+                let result = self.sessions.get_mut(&session).map(|session| {
+                    let valid = calibration.fits(layout.size.w.into(), layout.size.h.into());
+                    if valid {
+                        session.calibration = calibration;
+                    }
+                    valid
+                });
+                let _ = sync.send(result);
+            }
+            EisCommand::SetRequiredAppId {
+                session,
+                app_id,
+                sync,
+            } => {
+                let found = if let Some(session) = self.sessions.get_mut(&session) {
+                    info!(
+                        session = session.id,
+                        app_id = app_id.as_deref().unwrap_or("<none>"),
+                        "Updated EIS session focus guard"
+                    );
+                    session.required_app_id = app_id;
+                    true
+                } else {
+                    false
+                };
+                let _ = sync.send(found);
+            }
+            EisCommand::SetLabel {
+                session,
+                label,
+                sync,
+            } => {
+                let found = if let Some(session) = self.sessions.get_mut(&session) {
+                    session.label = label.as_deref().and_then(session::sanitize_label);
+                    true
+                } else {
+                    false
+                };
+                let _ = sync.send(found);
+            }
+            EisCommand::SetInputDelay {
+                session,
+                delay,
+                sync,
+            } => {
+                let found = match self.sessions.get_mut(&session) {
+                    Some(session) => {
+                        session.input_delay = delay;
+                        true
+                    }
+                    None => false,
+                };
+                let _ = sync.send(found);
+            }
+            EisCommand::SetTargetOutput {
+                session,
+                output,
+                sync,
+            } => {
+                let found = match self.sessions.get_mut(&session) {
+                    Some(session) => {
+                        session.target_output = output;
+                        true
+                    }
+                    None => false,
+                };
+                let _ = sync.send(found);
+            }
+            EisCommand::MovePointerPath {
+                session,
+                points,
+                duration,
+                sync,
+            } => {
+                let result = self.sessions.contains_key(&session).then(|| {
+                    let in_layout = points.iter().all(|&(x, y)| {
+                        let point = (x, y).into();
+                        shell
+                            .outputs()
+                            .any(|output| output.geometry().to_f64().contains(point))
+                    });
+                    in_layout && self.move_pointer_path(session, points, duration)
+                });
+                let _ = sync.send(result);
+            }
+            EisCommand::IsPointerOwner { session, sync } => {
+                let timeout = Duration::from_millis(config.pointer_release_timeout_ms);
+                let owner = self
+                    .sessions
+                    .contains_key(&session)
+                    .then(|| self.pointer_arbiter.holds(session, Instant::now(), timeout));
+                let _ = sync.send(owner);
+            }
+            EisCommand::WarpPointer {
+                session,
+                x,
+                y,
+                sync,
+            } => {
+                // Pointer motion needs the whole state as well
+                self.evlh.insert_idle(move |state| {
+                    let _ = sync.send(warp_pointer(state, session, (x, y).into()));
+                });
+            }
+            EisCommand::Edit {
+                session,
+                command,
+                sync,
+            } => {
+                // Keys need the whole state, so press them from the loop
+                self.evlh.insert_idle(move |state| {
+                    let _ = sync.send(edit(state, session, command));
+                });
+            }
+            EisCommand::ContextMenu { session, sync } => {
+                self.evlh.insert_idle(move |state| {
+                    let _ = sync.send(context_menu(state, session));
+                });
+            }
+            EisCommand::CloseFocusedWindow {
+                session,
+                app_id,
+                sync,
+            } => {
+                self.evlh.insert_idle(move |state| {
+                    let _ = sync.send(close_focused_window(state, session, app_id.as_deref()));
+                });
+            }
+            EisCommand::SetKeymap {
+                session,
+                names,
+                sync,
+            } => {
+                self.evlh.insert_idle(move |state| {
+                    let _ = sync.send(set_session_keymap(state, session, names));
+                });
+            }
+            EisCommand::KeymapInfo { session, sync } => {
+                // The fallback is the compositor's XKB config, which lives in
+                // the whole state
+                self.evlh.insert_idle(move |state| {
+                    let default = state.common.config.xkb_config();
+                    let info = state
+                        .common
+                        .eis_state
+                        .as_ref()
+                        .and_then(|eis_state| eis_state.sessions.get(&session))
+                        .map(|session| session.keymap_info(&default));
+                    let _ = sync.send(info);
+                });
+            }
+            EisCommand::Keymap { session, sync } => {
+                self.evlh.insert_idle(move |state| {
+                    let default = state.common.config.xkb_config();
+                    let Some(eis_state) = state.common.eis_state.as_mut() else {
+                        let _ = sync.send(None);
+                        return;
+                    };
+                    let keymap = eis_state.sessions.get(&session).map(|session| {
+                        let xkb_config = session.keymap_config(&default).clone();
+                        eis_state.keymap_cache.get(&xkb_config)
+                    });
+                    let _ = sync.send(keymap);
+                });
+            }
+            EisCommand::SourceRegistered { session, sync } => {
+                let _ = sync.send(self.source_registered(session));
+            }
+            EisCommand::LastKeyHadFocus { session, sync } => {
+                let focused = self
+                    .sessions
+                    .get(&session)
+                    .map(|session| session.last_key_had_focus == Some(true));
+                let _ = sync.send(focused);
+            }
+            EisCommand::FocusInfo { session, sync } => {
+                // Resolving the session's seat needs the whole state
+                self.evlh.insert_idle(move |state| {
+                    let known = state
+                        .common
+                        .eis_state
+                        .as_ref()
+                        .is_some_and(|eis_state| eis_state.sessions.contains_key(&session));
+                    let info = known.then(|| {
+                        let seat = target_seat(state, session, None);
+                        let shell = state.common.shell.read();
+                        focus_info(seat.and_then(|seat| focused_window(&shell, &seat)))
+                    });
+                    let _ = sync.send(info);
+                });
+            }
+            EisCommand::SocketOccupancy { session, sync } => {
+                let occupancy = self
+                    .sessions
+                    .get(&session)
+                    .map(|session| session.socket_occupancy().unwrap_or_default());
+                let _ = sync.send(occupancy);
+            }
+            EisCommand::Capabilities { session, sync } => {
+                let names = |capabilities: &[DeviceCapability]| {
+                    capabilities
+                        .iter()
+                        .map(|capability| format!("{capability:?}"))
+                        .collect()
+                };
+                let capabilities = self.sessions.get(&session).map(|session| {
+                    (
+                        names(&session.granted_capabilities),
+                        names(&session.denied_capabilities),
+                    )
+                });
+                let _ = sync.send(capabilities);
+            }
+            EisCommand::EventBudget { session, sync } => {
+                let window = Duration::from_secs(config.event_budget_window_s);
+                let budget = self.sessions.get_mut(&session).map(|session| {
+                    let consumed = session.budget.consumed_at(Instant::now(), window);
+                    (consumed, config.event_budget)
+                });
+                let _ = sync.send(budget);
+            }
+            EisCommand::CapabilityActivity { session, sync } => {
+                let activity = self
+                    .sessions
+                    .get(&session)
+                    .map(|session| session.activity.clone());
+                let _ = sync.send(activity);
+            }
+            EisCommand::SetDrainMode { enabled, sync } => {
+                self.set_draining(enabled);
+                let _ = sync.send(());
+            }
+            EisCommand::ListSessions { sync } => {
+                let _ = sync.send(self.session_infos());
+            }
+            EisCommand::ActiveSessions { sync } => {
+                let _ = sync.send(self.active_sessions() as u32);
+            }
+            EisCommand::CallerAllowed { caller, sync } => {
+                let _ = sync.send(caller_allowed(config, &caller));
+            }
+            EisCommand::DisconnectSession { session, sync } => {
+                let _ = sync.send(self.disconnect_session(session));
+            }
+            EisCommand::SurfaceCacheStats { sync } => {
+                let cache = &self.surface_cache;
+                let _ = sync.send((cache.hits, cache.misses, cache.hit_rate()));
+            }
+            EisCommand::RecentErrors { sync } => {
+                let _ = sync.send(self.dead_letters.recent());
+            }
+            EisCommand::SetCapture { enabled, sync } => {
+                self.set_capture(enabled);
+                let _ = sync.send(());
+            }
+            EisCommand::CapturedEvents { sync } => {
+                let _ = sync.send(self.capture.events());
+            }
+            EisCommand::Metrics { sync } => {
+                let _ = sync.send(self.metrics.snapshot(self.active_sessions() as u64));
+            }
+        }
+    }
+
+    /// Accept a new EIS client connection from a UNIX socket fd.
+    ///
+    /// Creates an `EisRequestSource` calloop event source that processes the
+    /// EIS protocol directly on the compositor's event loop. No background
+    /// threads are spawned.
+    pub fn add_connection(&mut self, socket: UnixStream) -> Result<SessionId, ConnectionRejected> {
+        self.connect(socket, None)
+    }
+
+    /// Accept a new EIS client connection pinned to the compositor seat
+    /// `seat`, which must be one of `seats`. Only that seat is announced to
+    /// the client.
+    pub fn add_connection_for_seat(
+        &mut self,
+        socket: UnixStream,
+        seat: &str,
+        seats: &[&str],
+    ) -> Result<SessionId, ConnectionRejected> {
+        if !seats.contains(&seat) {
+            warn!(seat, "Rejecting EIS connection for an unknown seat");
+            return Err(ConnectionRejected::UnknownSeat(seat.to_string()));
+        }
+        self.connect(socket, Some(seat.to_string()))
+    }
+
+    fn connect(
+        &mut self,
+        socket: UnixStream,
+        seat: Option<String>,
+    ) -> Result<SessionId, ConnectionRejected> {
+        if self.draining {
+            info!("Rejecting EIS connection: draining");
+            return Err(ConnectionRejected::Draining);
+        }
+        let current = self.active_connections.load(Ordering::Acquire);
+        if let Err(err) = check_connection_limit(current, self.max_connections) {
+            warn!(current, "Rejecting EIS connection: {err}");
+            return Err(err);
+        }
+
+        if let (Some(open), Some(limit)) = (open_fds(), fd_limit())
+            && fd_pressure(open, limit)
+        {
+            warn!(
+                open,
+                limit, "Rejecting EIS connection: close to the fd limit"
+            );
+            self.metrics
+                .connection_failed(ConnectionFailure::FdPressure);
+            return Err(ConnectionRejected::FdPressure);
+        }
+
+        let (context, socket_probe) = match open_context(socket, eis::Context::new) {
+            Ok(opened) => opened,
+            Err(e) => {
+                error!("Failed to create EIS context: {e}");
+                let failure = ConnectionFailure::of(&e);
+                self.metrics.connection_failed(failure);
+                return Err(match failure {
+                    ConnectionFailure::FdPressure => ConnectionRejected::FdPressure,
+                    ConnectionFailure::Context => ConnectionRejected::Context(e.to_string()),
+                });
+            }
+        };
+
+        let source = reis::calloop::EisRequestSource::new(context, 0);
+
+        let session_id = self.next_session_id;
+        self.next_session_id += 1;
+        let mut session = EisSession::new(session_id);
+        session.socket = socket_probe;
+        session.pinned_seat = seat;
+        self.sessions.insert(session_id, session);
+        self.active_connections.fetch_add(1, Ordering::AcqRel);
+        let active = self.active_connections.load(Ordering::Acquire);
+        info!(
+            active,
+            session = session_id,
+            "Accepting new EIS client connection"
+        );
+
+        // However the source goes away (disconnect, protocol error, EOF),
+        // dropping the closure and with it this guard ends the session
+        let guard = SessionGuard {
+            evlh: self.evlh.clone(),
+            session_id,
+        };
+        let res = self
+            .evlh
+            .insert_source(source, move |event, connection, state| {
+                let _guard = &guard;
+                match event {
+                    Ok(EisRequestSourceEvent::Connected) => {
+                        // Keep the whole name for D-Bus, but truncate it for the
+                        // info line to prevent log flooding
+                        let client_name =
+                            sanitize_client_name(connection.name().unwrap_or("<unknown>"));
+                        let log_len = state
+                            .common
+                            .config
+                            .cosmic_conf
+                            .eis_config
+                            .client_name_log_len;
+                        info!(
+                            session = session_id,
+                            client = truncate_client_name(&client_name, log_len),
+                            "EIS client connected"
+                        );
+                        debug!(session = session_id, client = %client_name, "EIS client name");
+                        if let Some(eis_state) = state.common.eis_state.as_mut() {
+                            eis_state.open_session(session_id, client_name);
+                        }
+                        if let Some(session) = session_mut(state, session_id) {
+                            session.connection = Some(connection.clone());
+                        }
+
+                        // Add a seat for every compositor seat, so a client
+                        // can pin itself to one by binding it, with the
+                        // capabilities the seat has handles for. These don't
+                        // follow local device hotplug: the handles outlive
+                        // physical devices and injected events don't depend
+                        // on them, so the announced set never goes stale and
+                        // needs no re-announcement. A session handed over for
+                        // one seat only sees that.
+                        let pinned = session_mut(state, session_id)
+                            .and_then(|session| session.pinned_seat.clone());
+                        let seats = state
+                            .common
+                            .shell
+                            .read()
+                            .seats
+                            .iter()
+                            .map(|seat| {
+                                let missing = missing_seat_capabilities(
+                                    seat.get_keyboard().is_some(),
+                                    seat.get_pointer().is_some(),
+                                    seat.get_touch().is_some(),
+                                );
+                                (seat.name().to_string(), missing)
+                            })
+                            .collect::<Vec<_>>();
+                        let eis_seats = announced_seats(seats, pinned.as_deref())
+                            .into_iter()
+                            .map(|(name, missing)| {
+                                let mut capabilities = DeviceCapability::Keyboard
+                                    | DeviceCapability::Pointer
+                                    | DeviceCapability::PointerAbsolute
+                                    | DeviceCapability::Button
+                                    | DeviceCapability::Scroll
+                                    | DeviceCapability::Touch;
+                                for capability in missing {
+                                    capabilities.remove(capability);
+                                }
+                                let seat = connection.add_seat(Some(&name), capabilities);
+                                (seat, name)
+                            })
+                            .collect();
+                        if let Some(session) = session_mut(state, session_id) {
+                            session.eis_seats = eis_seats;
+                        }
+                        defer_flush(state, session_id);
+
+                        if state
+                            .common
+                            .config
+                            .cosmic_conf
+                            .eis_config
+                            .wake_displays_on_connect
+                        {
+                            wake_displays(state);
+                        }
+                    }
+                    Ok(EisRequestSourceEvent::Request(request)) => {
+                        if let Some(session) = session_mut(state, session_id) {
+                            session.last_request = Instant::now();
+                        }
+                        // Requests are delayed as a whole so that e.g. a
+                        // disconnect can't overtake queued input
+                        if is_delaying(state, session_id) {
+                            delay_request(state, session_id, connection, request);
+                        } else {
+                            process_eis_request(state, session_id, connection, request);
+                        }
+                    }
+                    Err(e) => {
+                        warn!(session = session_id, "EIS protocol error: {e}");
+                        if let Some(eis_state) = state.common.eis_state.as_mut() {
+                            eis_state.record_connection_error(session_id, &e.to_string());
+                        }
+                        return Ok(calloop::PostAction::Remove);
+                    }
+                }
+                if session_mut(state, session_id).is_some() {
+                    Ok(calloop::PostAction::Continue)
+                } else {
+                    Ok(calloop::PostAction::Remove)
+                }
+            });
+        match res {
+            Ok(token) => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.token = Some(token);
+                }
+                self.arm_idle_timer(session_id);
+                Ok(session_id)
+            }
+            Err(e) => {
+                error!("Failed to insert EIS calloop source: {}", e.error);
+                self.remove_session(session_id);
+                Err(ConnectionRejected::EventSource)
+            }
+        }
+    }
+
+    /// In drain mode new connections are refused while existing sessions
+    /// keep injecting input.
+    pub fn set_draining(&mut self, draining: bool) {
+        if self.draining != draining {
+            info!(
+                draining,
+                sessions = self.sessions.len(),
+                "EIS drain mode changed"
+            );
+        }
+        self.draining = draining;
+    }
+
+    /// Start moving the pointer along `points` on behalf of a session,
+    /// replacing a path it is still following.
+    fn move_pointer_path(
+        &mut self,
+        session_id: SessionId,
+        points: Vec<(f64, f64)>,
+        duration: Duration,
+    ) -> bool {
+        let Some(path) = PointerPath::new(points, Instant::now(), duration) else {
+            return false;
+        };
+        let Some(session) = self.sessions.get_mut(&session_id) else {
+            return false;
+        };
+        if let Some(token) = session.pointer_path.take() {
+            self.evlh.remove(token);
+        }
+
+        let res = self
+            .evlh
+            .insert_source(Timer::immediate(), move |now, _, state| {
+                let ((x, y), done) = path.position_at(now);
+                if session_mut(state, session_id).is_none() {
+                    return TimeoutAction::Drop;
+                }
+                let gate = command_gate(state, session_id, true, || vec![capture::absolute(x, y)]);
+                if gate == CommandGate::Inject {
+                    let time = state.common.clock.now().as_millis();
+                    inject_pointer_absolute(state, session_id, x, y, time);
+                }
+                if done {
+                    if let Some(session) = session_mut(state, session_id) {
+                        session.pointer_path = None;
+                    }
+                    TimeoutAction::Drop
+                } else {
+                    TimeoutAction::ToDuration(PATH_STEP)
+                }
+            });
+        match res {
+            Ok(token) => {
+                if let Some(session) = self.sessions.get_mut(&session_id) {
+                    session.pointer_path = Some(token);
+                }
+                true
+            }
+            Err(err) => {
+                warn!(
+                    session = session_id,
+                    "Failed to start EIS pointer path: {}", err.error
+                );
+                false
+            }
+        }
+    }
+
+    /// Whether a session's connection source is registered on the event loop,
+    /// or `None` once the session is gone along with its source.
+    pub fn source_registered(&self, session_id: SessionId) -> Option<bool> {
+        self.sessions
+            .get(&session_id)
+            .map(|session| session.token.is_some())
+    }
+
+    /// Number of open connections, whether or not their handshake completed.
+    pub fn active_sessions(&self) -> usize {
+        self.active_connections.load(Ordering::Acquire)
+    }
+
+    /// Live sessions, ordered by id.
+    pub fn session_infos(&self) -> Vec<SessionInfo> {
+        let mut sessions = self
+            .sessions
+            .values()
+            .map(EisSession::info)
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|info| info.id);
+        sessions
+    }
+
+    /// Forcibly end a session: tell the client, drop its event source and
+    /// release the keys, buttons and touches it held. Returns whether the
+    /// session existed.
+    pub fn disconnect_session(&mut self, session_id: SessionId) -> bool {
+        let held = self.take_held_input(session_id);
+        let Some(session) = self.remove_session(session_id) else {
+            return false;
+        };
+        info!(session = session_id, "Disconnecting EIS session");
+        if let Some(connection) = &session.connection {
+            connection.disconnected(
+                eis::connection::DisconnectReason::Disconnected,
+                "session ended by the compositor",
+            );
+            let _ = connection.flush();
+        }
+        if let Some(token) = session.token {
+            self.evlh.remove(token);
+        }
+        if let Some(held) = held.filter(|held| !held.is_empty()) {
+            self.evlh
+                .insert_idle(move |state| release_held(state, held));
+        }
+        true
+    }
+
+    /// Disconnect every session, e.g. when the compositor exits, so clients
+    /// get a proper EIS disconnect instead of their socket vanishing. New
+    /// connections are refused from then on.
+    pub fn shutdown(&mut self) {
+        self.draining = true;
+        let sessions = self.sessions.keys().copied().collect::<Vec<_>>();
+        for session_id in sessions {
+            self.disconnect_session(session_id);
+        }
+    }
+
+    /// Take the keys, buttons and touches a session still holds down, so they
+    /// can be released on the seat they went to.
+    fn take_held_input(&mut self, session_id: SessionId) -> Option<HeldInput> {
+        let session = self.sessions.get_mut(&session_id)?;
+        let keys = session.held_keys.take();
+        let mut buttons = session.held_buttons.drain().collect::<Vec<_>>();
+        buttons.sort_unstable();
+        let seat = session
+            .pinned_seat
+            .clone()
+            .or_else(|| session.routed_seat.clone());
+        let touches = self.touch_slots.release_session(session_id);
+        Some(HeldInput {
+            keys,
+            buttons,
+            touches,
+            seat,
+        })
+    }
+
+    /// Whether `release` lets go of a key or button the session holds. These
+    /// pass the input checks, since a key or button kept down by the seat
+    /// would carry over into whatever it delivers input to next.
+    fn releases_held(&self, session_id: SessionId, release: Option<Release>) -> bool {
+        release.is_some_and(|release| {
+            self.sessions
+                .get(&session_id)
+                .is_some_and(|session| session.holds(release))
+        })
+    }
+
+    /// Check an event of a session against its focused-app guard while the
+    /// app `focused` has keyboard focus, counting the events it drops.
+    /// Releases of held input always pass.
+    fn focus_guard(
+        &mut self,
+        session_id: SessionId,
+        focused: Option<&str>,
+        release: Option<Release>,
+    ) -> bool {
+        if self.releases_held(session_id, release) {
+            return true;
+        }
+        let Some(session) = self.sessions.get_mut(&session_id) else {
+            return true;
+        };
+        let Some(required) = session.required_app_id.as_deref() else {
+            return true;
+        };
+        if app_id_matches(required, focused) {
+            return true;
+        }
+
+        session.dropped_unfocused += 1;
+        debug!(
+            session = session_id,
+            required,
+            focused = focused.unwrap_or("<none>"),
+            dropped = session.dropped_unfocused,
+            "Dropping EIS input: required app is not focused"
+        );
+        false
+    }
+
+    /// Forget a session and free its connection slot, touches and pointer
+    /// ownership. Does nothing if the session is already gone.
+    pub fn remove_session(&mut self, session_id: SessionId) -> Option<EisSession> {
+        let session = self.sessions.remove(&session_id)?;
+        let timers = [
+            session.idle_timer,
+            session.motion_timer,
+            session.pointer_path,
+            session.delay_timer,
+        ];
+        for token in timers.into_iter().flatten() {
+            self.evlh.remove(token);
+        }
+        self.active_connections.fetch_sub(1, Ordering::AcqRel);
+        self.touch_slots.release_session(session_id);
+        self.pointer_arbiter.release(session_id);
+        if session.opened {
+            self.notify(SessionEvent::Closed(session_id));
+        }
+        Some(session)
+    }
+}
+
+/// Ends its session once the connection's event source is dropped.
+#[derive(Debug)]
+struct SessionGuard {
+    evlh: calloop::LoopHandle<'static, State>,
+    session_id: SessionId,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let session_id = self.session_id;
+        self.evlh.insert_idle(move |state| {
+            if end_session(state, session_id) {
+                info!(session = session_id, "EIS connection closed");
+            }
+        });
+    }
+}
+
+/// Release what a session held on the seat and remove it. Returns whether the
+/// session still existed.
+fn end_session(state: &mut State, session_id: SessionId) -> bool {
+    // Don't leave keys, buttons or touches stuck down on the local seat
+    let Some(held) = state
+        .common
+        .eis_state
+        .as_mut()
+        .and_then(|eis_state| eis_state.take_held_input(session_id))
+    else {
+        return false;
+    };
+    release_held(state, held);
+    if let Some(eis_state) = state.common.eis_state.as_mut() {
+        eis_state.remove_session(session_id);
+    }
+    true
+}
+
+/// Keys, buttons and touch slots a session left pressed, and the seat they
+/// went to.
+#[derive(Debug)]
+struct HeldInput {
+    keys: Vec<u32>,
+    buttons: Vec<u32>,
+    touches: Vec<u32>,
+    seat: Option<String>,
+}
+
+impl HeldInput {
+    fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.buttons.is_empty() && self.touches.is_empty()
+    }
+}
+
+fn release_held(state: &mut State, held: HeldInput) {
+    let time = state.common.clock.now().as_millis();
+    for slot in held.touches {
+        inject_touch_up(state, slot, time);
+    }
+    let Some(seat) = seat_named(state, held.seat.as_deref()) else {
+        return;
+    };
+    if let Some(pointer) = seat.get_pointer() {
+        for button in held.buttons {
+            pointer.button(
+                state,
+                &smithay::input::pointer::ButtonEvent {
+                    button,
+                    state: smithay::backend::input::ButtonState::Released,
+                    serial: SERIAL_COUNTER.next_serial(),
+                    time,
+                },
+            );
+            pointer.frame(state);
+        }
+    }
+    for key in held.keys {
+        inject_key(state, &seat, key, KeyState::Released, time);
+    }
+}
+
+/// Why a socket handed to [`EisState::add_connection`] was refused.
+#[derive(thiserror::Error, Debug)]
+pub enum ConnectionRejected {
+    #[error("EIS connection limit of {0} reached")]
+    LimitReached(usize),
+    #[error("Not accepting new EIS sessions while draining")]
+    Draining,
+    #[error("No seat named {0:?}")]
+    UnknownSeat(String),
+    #[error("Failed to create EIS context: {0}")]
+    Context(String),
+    #[error("Too close to the file descriptor limit for an EIS connection")]
+    FdPressure,
+    #[error("EIS connections arriving faster than one per {0:?}")]
+    TooFast(Duration),
+    #[error("Failed to register EIS event source")]
+    EventSource,
+}
+
+/// Why setting up the EIS receiver, or something it hands to clients, failed.
+#[derive(thiserror::Error, Debug)]
+pub enum EisError {
+    #[error("EIS connection refused: {0}")]
+    Connection(#[from] ConnectionRejected),
+    #[error("Failed to insert EIS {0} into the event loop")]
+    EventSource(&'static str),
+    #[error("D-Bus error: {0}")]
+    DBus(#[from] zbus::Error),
+    #[error("Failed to spawn EIS signal thread: {0}")]
+    Thread(#[source] std::io::Error),
+    #[error("Failed to compile XKB keymap for EIS")]
+    Keymap,
+    #[error("Failed to prepare EIS keymap memfd: {0}")]
+    Memfd(#[source] std::io::Error),
+}
+
+/// Whether input last injected at `last` still inhibits idle at `now`, with
+/// inhibition lasting `window` past the input. A zero `window` disables it.
+fn idle_inhibited(last: Option<Instant>, now: Instant, window: Duration) -> bool {
+    !window.is_zero() && last.is_some_and(|last| now < last + window)
+}
+
+/// Connections waiting for the minimum connection interval at most.
+const MAX_QUEUED_CONNECTIONS: u32 = 8;
+
+/// File descriptors kept free for what a new connection and its session
+/// open besides the socket, such as keymap memfds.
+const FD_RESERVE: u64 = 16;
+
+/// Create the EIS context of a socket, together with a duplicate of the
+/// socket for queue diagnostics. On failure both fds are closed.
+fn open_context<C>(
+    socket: UnixStream,
+    new: impl FnOnce(UnixStream) -> std::io::Result<C>,
+) -> std::io::Result<(C, Option<UnixStream>)> {
+    let probe = socket.try_clone().ok();
+    match new(socket) {
+        Ok(context) => Ok((context, probe)),
+        Err(err) => {
+            drop(probe);
+            Err(err)
+        }
+    }
+}
+
+/// Whether `open` file descriptors leave too few of `limit` for another
+/// connection.
+fn fd_pressure(open: usize, limit: u64) -> bool {
+    (open as u64).saturating_add(FD_RESERVE) >= limit
+}
+
+/// Number of file descriptors the compositor has open.
+fn open_fds() -> Option<usize> {
+    std::fs::read_dir("/proc/self/fd")
+        .ok()
+        .map(|entries| entries.count())
+}
+
+/// The soft limit on open file descriptors, if there is one.
+fn fd_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0
+        || limit.rlim_cur == libc::RLIM_INFINITY
+    {
+        return None;
+    }
+    Some(limit.rlim_cur)
+}
+
+impl EisError {
+    /// Whether this is running out of file descriptors, which may pass, as
+    /// opposed to e.g. a keymap that doesn't compile.
+    fn is_fd_exhaustion(&self) -> bool {
+        matches!(self, EisError::Memfd(err)
+            if matches!(err.raw_os_error(), Some(libc::EMFILE | libc::ENFILE)))
+    }
+}
+
+fn check_connection_limit(current: usize, max: usize) -> Result<(), ConnectionRejected> {
+    if current >= max {
+        Err(ConnectionRejected::LimitReached(max))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether the socket allowlist admits `caller`. With it empty only the portal
+/// may hand over sockets.
+///
+/// Only the user is checked: it comes from the bus daemon, whereas anything a
+/// process tells about itself, like its command line, can be forged by any
+/// other process of the user.
+fn caller_allowed(config: &EisConfig, caller: &Caller) -> bool {
+    let uids = &config.allowed_socket_uids;
+    caller.uid.is_some_and(|uid| uids.contains(&uid))
+}
+
+/// Whether a session's requests go through its delay queue, which they keep
+/// doing until the queue drained after the delay was turned off.
+fn is_delaying(state: &State, session_id: SessionId) -> bool {
+    state
+        .common
+        .eis_state
+        .as_ref()
+        .and_then(|eis_state| eis_state.sessions.get(&session_id))
+        .is_some_and(|session| session.input_delay.is_some() || !session.delayed.is_empty())
+}
+
+/// Hold back `request` by the session's input delay, keeping its place
+/// relative to the session's other requests. A timer drains the queue
+/// as deadlines pass and stays armed until the queue is empty.
+fn delay_request(
+    state: &mut State,
+    session_id: SessionId,
+    connection: &reis::request::Connection,
+    request: EisRequest,
+) {
+    let Some(session) = session_mut(state, session_id) else {
+        return;
+    };
+    let delay = session.input_delay.unwrap_or_default();
+    let was_empty = session.delayed.is_empty();
+    let due = session.delayed.push(Instant::now(), delay, request);
+    if !was_empty {
+        return;
+    }
+
+    let mut connection = connection.clone();
+    let timer = Timer::from_deadline(due);
+    let res = state
+        .common
+        .event_loop_handle
+        .insert_source(timer, move |now, _, state| {
+            let Some(session) = session_mut(state, session_id) else {
+                return TimeoutAction::Drop;
+            };
+            // The input may end the session, and the timer goes away by
+            // returning, not by being removed
+            let token = session.delay_timer.take();
+            let due = session.delayed.pop_due(now);
+            for request in due {
+                process_eis_request(state, session_id, &mut connection, request);
+            }
+            let Some(session) = session_mut(state, session_id) else {
+                return TimeoutAction::Drop;
+            };
+            let Some(next) = session.delayed.next_deadline() else {
+                return TimeoutAction::Drop;
+            };
+            session.delay_timer = token;
+            TimeoutAction::ToInstant(next)
+        });
+    match res {
+        Ok(token) => {
+            if let Some(session) = session_mut(state, session_id) {
+                session.delay_timer = Some(token);
+            }
+        }
+        Err(err) => {
+            warn!(
+                session = session_id,
+                "Failed to schedule delayed EIS input: {}", err.error
+            );
+            if let Some(session) = session_mut(state, session_id) {
+                session.delayed = Default::default();
+            }
+        }
+    }
+}
+
+/// Process a single EIS protocol request by injecting it into the compositor's
+/// Smithay input stack.
+fn process_eis_request(
+    state: &mut State,
+    session_id: SessionId,
+    connection: &mut reis::request::Connection,
+    request: EisRequest,
+) {
+    if !track_emulation(state, session_id, &request) {
+        debug!(
+            session = session_id,
+            "Dropping EIS input from a device that isn't emulating"
+        );
+        count_rejected(state, Rejection::NotEmulating);
+        return;
+    }
+    let now = state.common.clock.now().as_millis();
+    let ordering = state.common.config.cosmic_conf.eis_config.frame_ordering;
+    if ordering == FrameOrdering::Canonical
+        && let Some(class) = frame::classify(&request)
+    {
+        buffer_frame_event(state, session_id, class, request);
+        return;
+    }
+    // Events held back for the frame are stamped with the client's time
+    let time = match &request {
+        EisRequest::Frame(frame) => {
+            smooth_frame_time(state, session_id, frame_time(frame.time, now))
+        }
+        _ => now,
+    };
+    if let EisRequest::Frame(_) = request {
+        flush_frame(state, session_id, time);
+    }
+    apply_eis_request(state, session_id, connection, request, time);
+}
+
+/// Follow the start and stop of emulation on the session's devices, returning
+/// whether `request` may be applied: input only while its device emulates.
+/// Checked once the input delay let the request through, which keeps it in
+/// order with the start and stop, but before a frame holds it back.
+fn track_emulation(state: &mut State, session_id: SessionId, request: &EisRequest) -> bool {
+    let Some(session) = session_mut(state, session_id) else {
+        return true;
+    };
+    match request {
+        EisRequest::DeviceStartEmulating(start) => {
+            session.emulating.start(start.device.clone());
+            true
+        }
+        EisRequest::DeviceStopEmulating(stop) => {
+            session.emulating.stop(&stop.device);
+            true
+        }
+        _ => input_device(request).is_none_or(|device| session.emulating.is_emulating(device)),
+    }
+}
+
+/// The device an input request was sent from.
+fn input_device(request: &EisRequest) -> Option<&reis::request::Device> {
+    Some(match request {
+        EisRequest::KeyboardKey(event) => &event.device,
+        EisRequest::PointerMotion(event) => &event.device,
+        EisRequest::PointerMotionAbsolute(event) => &event.device,
+        EisRequest::Button(event) => &event.device,
+        EisRequest::ScrollDelta(event) => &event.device,
+        EisRequest::ScrollDiscrete(event) => &event.device,
+        EisRequest::ScrollStop(event) => &event.device,
+        EisRequest::ScrollCancel(event) => &event.device,
+        EisRequest::TouchDown(event) => &event.device,
+        EisRequest::TouchMotion(event) => &event.device,
+        EisRequest::TouchUp(event) => &event.device,
+        EisRequest::TouchCancel(event) => &event.device,
+        _ => return None,
+    })
+}
+
+/// Furthest a frame's timestamp may lag behind the compositor clock before it
+/// is considered bogus.
+const MAX_FRAME_AGE_MS: u32 = 1000;
+
+/// The time in milliseconds to stamp a frame's events with, given the
+/// client's timestamp in microseconds of `CLOCK_MONOTONIC`. Falls back to the
+/// compositor clock `now` for timestamps from the future or older than
+/// [`MAX_FRAME_AGE_MS`].
+fn frame_time(client_us: u64, now: u32) -> u32 {
+    // Both clocks wrap the same way once truncated to milliseconds
+    let client = (client_us / 1000) as u32;
+    let age = now.wrapping_sub(client);
+    if (age as i32) < 0 || age > MAX_FRAME_AGE_MS {
+        now
+    } else {
+        client
+    }
+}
+
+/// Regularize a frame time of the session as configured.
+fn smooth_frame_time(state: &mut State, session_id: SessionId, time: u32) -> u32 {
+    let smoothing = state
+        .common
+        .config
+        .cosmic_conf
+        .eis_config
+        .timestamp_smoothing;
+    if smoothing <= 0.0 {
+        return time;
+    }
+    session_mut(state, session_id)
+        .map_or(time, |session| session.timestamps.smooth(time, smoothing))
+}
+
+/// Hold back an input event until the end of the client's frame. Should the
+/// frame not arrive during this loop iteration, an idle callback applies the
+/// events anyway.
+fn buffer_frame_event(
+    state: &mut State,
+    session_id: SessionId,
+    class: EventClass,
+    request: EisRequest,
+) {
+    let Some(session) = session_mut(state, session_id) else {
+        return;
+    };
+    if session.frame.is_empty() {
+        state.common.event_loop_handle.insert_idle(move |state| {
+            let time = state.common.clock.now().as_millis();
+            flush_frame(state, session_id, time);
+            flush_motion(state, session_id, time);
+        });
+    }
+    if let Some(session) = session_mut(state, session_id) {
+        session.frame.push(class, request);
+    }
+}
+
+/// Apply the input held back for the session's current frame, in canonical
+/// order. Frames beyond `max_events_per_frame` are applied over several loop
+/// iterations, so one huge frame doesn't stall the compositor.
+fn flush_frame(state: &mut State, session_id: SessionId, time: u32) {
+    let max = state
+        .common
+        .config
+        .cosmic_conf
+        .eis_config
+        .max_events_per_frame;
+    let Some(session) = session_mut(state, session_id) else {
+        return;
+    };
+    let events = session.frame.take_batch(max);
+    if events.is_empty() {
+        return;
+    }
+    // Includes later frames that arrived while this one was split up
+    let more = !session.frame.is_empty();
+    let Some(mut connection) = session.connection.clone() else {
+        return;
+    };
+    for request in events {
+        apply_eis_request(state, session_id, &mut connection, request, time);
+    }
+
+    if more {
+        trace!(
+            session = session_id,
+            "Deferring rest of oversized EIS frame"
+        );
+        state.common.event_loop_handle.insert_idle(move |state| {
+            let time = state.common.clock.now().as_millis();
+            flush_frame(state, session_id, time);
+            flush_motion(state, session_id, time);
+        });
+    }
+}
+
+fn apply_eis_request(
+    state: &mut State,
+    session_id: SessionId,
+    connection: &mut reis::request::Connection,
+    request: EisRequest,
+    time: u32,
+) {
+    if is_input_request(&request)
+        && state
+            .common
+            .eis_state
+            .as_ref()
+            .is_some_and(EisState::is_paused)
+    {
+        count_rejected(state, Rejection::Paused);
+        return;
+    }
+    let release = Release::of(&request);
+    // Letting go of held input passes the limits and pointer arbitration,
+    // lest it stay stuck down
+    let held_release = state
+        .common
+        .eis_state
+        .as_ref()
+        .is_some_and(|eis_state| eis_state.releases_held(session_id, release));
+    // EI has no way to acknowledge or refuse a single event: rejections are
+    // only visible to operators through the D-Bus counters and warnings, and
+    // to clients only by what a bind leaves off their devices.
+    if is_input_request(&request) && !held_release && !rate_limit_allows(state, session_id) {
+        count_rejected(state, Rejection::RateLimited);
+        return;
+    }
+    if is_input_request(&request) && !held_release && !budget_allows(state, session_id) {
+        count_rejected(state, Rejection::Budget);
+        return;
+    }
+    if !bound_capabilities_allow(state, session_id, &request) {
+        count_rejected(state, Rejection::Capability);
+        return;
+    }
+    if is_input_request(&request) && !focus_guard_allows(state, session_id, release) {
+        count_rejected(state, Rejection::Unfocused);
+        return;
+    }
+    if is_pointer_request(&request)
+        && !held_release
+        && !pointer_arbitration_allows(state, session_id)
+    {
+        count_rejected(state, Rejection::Arbitration);
+        return;
+    }
+    // Capturing records what passed the checks above rather than injecting it
+    if let Some(eis_state) = state.common.eis_state.as_mut()
+        && eis_state.capture.is_enabled()
+        && let Some(event) = capture::describe(&request)
+    {
+        eis_state.capture.push(session_id, time, event);
+        return;
+    }
+    // Keep batched motion ahead of the buttons, keys and touches following it
+    if is_input_request(&request) && !is_motion_request(&request) {
+        flush_motion(state, session_id, time);
+    }
+    if let Some(capability) = capabilities::required(&request)
+        && let Some(session) = session_mut(state, session_id)
+    {
+        session.record_activity(capability, SystemTime::now());
+    }
+
+    // Events the arms below drop count as rejected instead of injected
+    let injected = Injected::of(&request);
+    if inject_request(state, session_id, connection, request, time).is_ok()
+        && let Some(kind) = injected
+    {
+        if let Some(metrics) = metrics(state) {
+            metrics.injected(kind);
+        }
+        if let Some(session) = session_mut(state, session_id) {
+            session.injected.count(kind);
+        }
+        let window = Duration::from_millis(
+            state
+                .common
+                .config
+                .cosmic_conf
+                .eis_config
+                .idle_inhibit_window_ms,
+        );
+        if let Some(eis_state) = state.common.eis_state.as_mut()
+            && eis_state.note_injected(Instant::now(), window)
+        {
+            state.common.refresh_idle_inhibit();
+        }
+    }
+}
+
+/// Inject a request that passed the session checks, or return why it was
+/// dropped, which is already counted in the metrics.
+fn inject_request(
+    state: &mut State,
+    session_id: SessionId,
+    connection: &mut reis::request::Connection,
+    request: EisRequest,
+    time: u32,
+) -> Result<(), Rejection> {
+    match request {
+        EisRequest::KeyboardKey(key_evt) => {
+            if key_evt.key > MAX_EVDEV_KEYCODE {
+                if let Some(suppressed) = count_out_of_range(state) {
+                    warn!(
+                        keycode = key_evt.key,
+                        suppressed, "Rejecting keyboard event: keycode out of range"
+                    );
+                }
+                return Err(Rejection::OutOfRange);
+            }
+            let pressed = key_evt.state == eis::keyboard::KeyState::Press;
+            let dedup = state.common.config.cosmic_conf.eis_config.dedup_key_events;
+            if !session_mut(state, session_id)
+                .is_none_or(|session| session.held_keys.update(key_evt.key, pressed, dedup))
+            {
+                trace!(
+                    keycode = key_evt.key,
+                    pressed, "Dropping repeated EIS key event"
+                );
+                return reject(state, Rejection::DuplicateKey);
+            }
+            let key_state = if pressed {
+                KeyState::Pressed
+            } else {
+                KeyState::Released
+            };
+            let Some(seat) = target_seat(state, session_id, None) else {
+                return Err(Rejection::NoSeat);
+            };
+            let focused = inject_key(state, &seat, key_evt.key, key_state, time);
+            if let Some(session) = session_mut(state, session_id) {
+                session.note_key_focus(focused);
+            }
+            send_modifiers(state, session_id);
+        }
+        EisRequest::PointerMotion(motion) => {
+            let dx = f64::from(motion.dx);
+            let dy = f64::from(motion.dy);
+            check_finite(state, session_id, "pointer motion", (dx, dy))?;
+
+            let config = &state.common.config.cosmic_conf.eis_config;
+            let (dx, dy) = motion::scale_motion(
+                dx,
+                dy,
+                config.relative_motion_scale,
+                config.relative_motion_acceleration,
+            );
+            queue_motion(state, session_id, Motion::Relative(dx, dy), time);
+        }
+        EisRequest::PointerMotionAbsolute(motion) => {
+            let x = f64::from(motion.dx_absolute);
+            let y = f64::from(motion.dy_absolute);
+            check_finite(state, session_id, "absolute pointer motion", (x, y))?;
+            let (x, y) = map_absolute(state, session_id, x, y);
+            let Some((x, y)) = bound_absolute_jump(state, session_id, (x, y)) else {
+                return reject(state, Rejection::Jump);
+            };
+            queue_motion(state, session_id, Motion::Absolute(x, y), time);
+        }
+        EisRequest::Button(btn) => {
+            if btn.button > MAX_EVDEV_KEYCODE {
+                if let Some(suppressed) = count_out_of_range(state) {
+                    warn!(
+                        button = btn.button,
+                        suppressed, "Rejecting button event: code out of range"
+                    );
+                }
+                return Err(Rejection::OutOfRange);
+            }
+            let Some(seat) = target_seat(state, session_id, None) else {
+                return Err(Rejection::NoSeat);
+            };
+            if let Some(pointer) = seat.get_pointer() {
+                let serial = SERIAL_COUNTER.next_serial();
+                let pressed = btn.state == eis::button::ButtonState::Press;
+                let state_val = if pressed {
+                    smithay::backend::input::ButtonState::Pressed
+                } else {
+                    smithay::backend::input::ButtonState::Released
+                };
+                pointer.button(
+                    state,
+                    &smithay::input::pointer::ButtonEvent {
+                        button: btn.button,
+                        state: state_val,
+                        serial,
+                        time,
+                    },
+                );
+                pointer.frame(state);
+                // Remember held buttons to release them if the session ends
+                if let Some(session) = session_mut(state, session_id) {
+                    if pressed {
+                        session.held_buttons.insert(btn.button);
+                    } else {
+                        session.held_buttons.remove(&btn.button);
+                    }
+                }
+            }
+        }
+        EisRequest::ScrollDelta(scroll) => {
+            let dx = f64::from(scroll.dx);
+            let dy = f64::from(scroll.dy);
+            check_finite(state, session_id, "scroll", (dx, dy))?;
+            let config = &state.common.config.cosmic_conf.eis_config;
+            let (acceleration, decay) = (
+                config.scroll_acceleration,
+                config.scroll_acceleration_decay_ms,
+            );
+            let factor = match session_mut(state, session_id) {
+                Some(session) if acceleration > 0.0 => {
+                    session
+                        .scroll_acceleration
+                        .factor(time, acceleration, decay)
+                }
+                _ => 1.0,
+            };
+            let (dx, dy) = (dx * factor, dy * factor);
+            let Some(seat) = target_seat(state, session_id, None) else {
+                return Err(Rejection::NoSeat);
+            };
+            if let Some(pointer) = seat.get_pointer() {
+                pointer.axis(state, delta_axis_frame(time, dx, dy));
+                pointer.frame(state);
+            }
+        }
+        EisRequest::ScrollDiscrete(scroll) => {
+            let Some(seat) = target_seat(state, session_id, None) else {
+                return Err(Rejection::NoSeat);
+            };
+            if let Some(pointer) = seat.get_pointer() {
+                let frame = discrete_axis_frame(time, scroll.discrete_dx, scroll.discrete_dy);
+                pointer.axis(state, frame);
+                pointer.frame(state);
+            }
+        }
+        EisRequest::ScrollStop(stop) => {
+            let Some(seat) = target_seat(state, session_id, None) else {
+                return Err(Rejection::NoSeat);
+            };
+            if let Some(pointer) = seat.get_pointer() {
+                // In a frame of its own, after the scroll it ends
+                pointer.axis(state, stop_axis_frame(time, stop.x, stop.y));
+                pointer.frame(state);
+            }
+        }
+        EisRequest::TouchDown(touch) => {
+            if !touch_id_allowed(state, touch.touch_id) {
+                if let Some(suppressed) = count_out_of_range(state) {
+                    warn!(
+                        touch_id = touch.touch_id,
+                        suppressed, "Rejecting touch down: ID out of range"
+                    );
+                }
+                return Err(Rejection::OutOfRange);
+            }
+            let x = f64::from(touch.x);
+            let y = f64::from(touch.y);
+            check_finite(state, session_id, "touch down", (x, y))?;
+            let (x, y) = map_absolute(state, session_id, x, y);
+            let (x, y) = clamp_touch(state, session_id, (x, y));
+            if touch_rejected(state, (x, y).into()) {
+                trace!(
+                    session = session_id,
+                    x, y, "Dropping EIS touch on a surface rejecting input"
+                );
+                return reject(state, Rejection::SurfaceRejected);
+            }
+            // A second down without an up in between restarts the touch
+            let stale =
+                state.common.eis_state.as_mut().and_then(|eis_state| {
+                    eis_state.touch_slots.release(session_id, touch.touch_id)
+                });
+            if let Some(stale) = stale {
+                debug!(
+                    session = session_id,
+                    touch_id = touch.touch_id,
+                    "Lifting EIS touch that was put down again"
+                );
+                inject_touch_up(state, stale, time);
+            }
+            let Some(seat) = target_seat(state, session_id, Some((x, y).into())) else {
+                return Err(Rejection::NoSeat);
+            };
+            let Some(slot) = state.common.eis_state.as_mut().map(|eis_state| {
+                eis_state
+                    .touch_slots
+                    .allocate(session_id, touch.touch_id, Instant::now())
+            }) else {
+                return Ok(());
+            };
+            if let Some(eis_state) = state.common.eis_state.as_mut() {
+                eis_state.touch_seats.insert(slot, seat.name().to_string());
+            }
+            focus_touched(state, &seat, (x, y).into());
+            let under = resolve_touch_target(state, x, y);
+            if let Some(touch_handle) = seat.get_touch() {
+                let serial = SERIAL_COUNTER.next_serial();
+                touch_handle.down(
+                    state,
+                    under,
+                    &DownEvent {
+                        slot: TouchSlot::from(Some(slot)),
+                        location: (x, y).into(),
+                        serial,
+                        time,
+                    },
+                );
+                queue_touch_frame(state, session_id, seat.name());
+            }
+            arm_touch_timeout(state);
+        }
+        EisRequest::TouchMotion(touch) => {
+            if !touch_id_allowed(state, touch.touch_id) {
+                if let Some(suppressed) = count_out_of_range(state) {
+                    warn!(
+                        touch_id = touch.touch_id,
+                        suppressed, "Rejecting touch motion: ID out of range"
+                    );
+                }
+                return Err(Rejection::OutOfRange);
+            }
+            let x = f64::from(touch.x);
+            let y = f64::from(touch.y);
+            check_finite(state, session_id, "touch motion", (x, y))?;
+            let (x, y) = map_absolute(state, session_id, x, y);
+            let (x, y) = clamp_touch(state, session_id, (x, y));
+            let Some(slot) = state.common.eis_state.as_mut().and_then(|eis_state| {
+                eis_state
+                    .touch_slots
+                    .refresh(session_id, touch.touch_id, Instant::now())
+            }) else {
+                debug!(
+                    touch_id = touch.touch_id,
+                    "Ignoring touch motion for a touch that is not down"
+                );
+                return reject(state, Rejection::NotDown);
+            };
+            // Stay on the seat the touch went down on
+            let name = state
+                .common
+                .eis_state
+                .as_ref()
+                .and_then(|eis_state| eis_state.touch_seats.get(&slot).cloned());
+            let Some(seat) = seat_named(state, name.as_deref()) else {
+                return Err(Rejection::NoSeat);
+            };
+            let under = resolve_touch_target(state, x, y);
+            if let Some(touch_handle) = seat.get_touch() {
+                touch_handle.motion(
+                    state,
+                    under,
+                    &TouchMotionEvent {
+                        slot: TouchSlot::from(Some(slot)),
+                        location: (x, y).into(),
+                        time,
+                    },
+                );
+                queue_touch_frame(state, session_id, seat.name());
+            }
+        }
+        EisRequest::TouchUp(touch) => {
+            let Some(slot) =
+                state.common.eis_state.as_mut().and_then(|eis_state| {
+                    eis_state.touch_slots.release(session_id, touch.touch_id)
+                })
+            else {
+                debug!(
+                    touch_id = touch.touch_id,
+                    "Ignoring touch up for a touch that is not down"
+                );
+                return reject(state, Rejection::NotDown);
+            };
+            if let Some(seat) = touch_up(state, slot, time) {
+                queue_touch_frame(state, session_id, seat.name());
+            }
+        }
+        EisRequest::TouchCancel(touch) => {
+            let name = state.common.eis_state.as_mut().and_then(|eis_state| {
+                let slot = eis_state.touch_slots.release(session_id, touch.touch_id)?;
+                eis_state.touch_seats.remove(&slot)
+            });
+            let seat = match name {
+                Some(name) => seat_named(state, Some(&name)),
+                None => target_seat(state, session_id, None),
+            };
+            let Some(seat) = seat else {
+                return Err(Rejection::NoSeat);
+            };
+            if let Some(touch_handle) = seat.get_touch() {
+                touch_handle.cancel(state);
+                queue_touch_frame(state, session_id, seat.name());
+            }
+        }
+        EisRequest::Disconnect => {
+            info!(session = session_id, "EIS client disconnected");
+            end_session(state, session_id);
+        }
+        EisRequest::Bind(bind) => {
+            debug!(
+                session = session_id,
+                "EIS client bound with capabilities: {:?}", bind.capabilities
+            );
+
+            // The live config, so a capability denied by a config reload is
+            // refused for every bind from then on
+            let (mut granted, denied) = capabilities::negotiate(
+                bind.capabilities.iter(),
+                &state
+                    .common
+                    .config
+                    .cosmic_conf
+                    .eis_config
+                    .denied_capabilities,
+            );
+            let mut capabilities = bind.capabilities;
+            for capability in &denied {
+                capabilities.remove(*capability);
+            }
+            if !denied.is_empty() {
+                info!(
+                    session = session_id,
+                    ?granted,
+                    ?denied,
+                    "Denied EIS capabilities by configuration"
+                );
+            }
+            // Binding one of the announced seats pins the session to it
+            let available = state
+                .common
+                .shell
+                .read()
+                .seats
+                .iter()
+                .map(|seat| seat.name().to_string())
+                .collect::<Vec<_>>();
+            let announced = session_mut(state, session_id).and_then(|session| {
+                session
+                    .eis_seats
+                    .iter()
+                    .find(|(seat, _)| *seat == bind.seat)
+                    .map(|(_, name)| name.clone())
+            });
+            let available = available.iter().map(String::as_str).collect::<Vec<_>>();
+            let Some(pinned) = bind_seat(announced.as_deref(), &available) else {
+                warn_no_seat(state, "Refusing EIS bind: there are no seats");
+                return Err(Rejection::NoSeat);
+            };
+            // Many devices of one kind are almost always a client bug, and
+            // each keyboard costs a keymap fd
+            let max = state
+                .common
+                .config
+                .cosmic_conf
+                .eis_config
+                .max_devices_per_kind;
+            if let Some(Err(kind)) =
+                session_mut(state, session_id).map(|session| session.devices.admit(&granted, max))
+            {
+                warn!(
+                    session = session_id,
+                    ?kind,
+                    max,
+                    "Refusing EIS bind: device limit reached"
+                );
+                return reject(state, Rejection::DeviceLimit);
+            }
+
+            // Prepare XKB keymap fd if keyboard capability is requested.
+            // XKB is the only keymap type EIS defines and clients can't ask
+            // for none: binding only names capabilities, so every bound
+            // keyboard gets the keymap and no memfd is made without one.
+            let keymap_fd = if capabilities.contains(DeviceCapability::Keyboard) {
+                let default = state.common.config.xkb_config();
+                let xkb_config = session_mut(state, session_id)
+                    .and_then(|session| session.xkb_config.clone())
+                    .unwrap_or(default);
+                let keymap = keyboard_keymap(session_id, &mut granted, || {
+                    prepare_xkb_keymap_fd(state, &xkb_config)
+                });
+                if keymap.is_none() {
+                    capabilities.remove(DeviceCapability::Keyboard);
+                }
+                keymap
+            } else {
+                None
+            };
+
+            // The device only advertises the granted capabilities, which is how
+            // the client learns about the denial; D-Bus reports both lists.
+            if let Some(session) = session_mut(state, session_id) {
+                session.granted_capabilities = granted;
+                session.denied_capabilities = denied;
+                session.pinned_seat = pinned;
+            }
+            if capabilities.is_empty() {
+                return Ok(());
+            }
+
+            let regions = {
+                let target = session_mut(state, session_id)
+                    .and_then(|session| session.target_output.clone());
+                let absolute_origin = state.common.config.cosmic_conf.eis_config.absolute_origin;
+                let shell = state.common.shell.read();
+                let outputs = shell
+                    .outputs()
+                    .filter(|output| target.as_ref().is_none_or(|name| output.name() == *name))
+                    .map(|output| (output.geometry(), output.current_scale().fractional_scale()))
+                    .collect::<Vec<_>>();
+                let all = shell
+                    .outputs()
+                    .map(|output| output.geometry())
+                    .collect::<Vec<_>>();
+                let target = target.and(outputs.first().map(|(geometry, _)| *geometry));
+                let origin = coordinate_origin(absolute_origin, target, &all);
+                device_regions(&outputs, origin)
+            };
+            if let Some(session) = session_mut(state, session_id) {
+                session.regions = regions.clone();
+            }
+
+            let device = bind.seat.add_device(
+                Some("remote-input"),
+                eis::device::DeviceType::Virtual,
+                capabilities,
+                |device| {
+                    // Announce the valid coordinate space before device.done()
+                    for region in &regions {
+                        device.device().region(
+                            region.x,
+                            region.y,
+                            region.width,
+                            region.height,
+                            region.scale,
+                        );
+                    }
+                    // Send compositor's XKB keymap to keyboard before device.done().
+                    // Unlike wl_keyboard, ei_keyboard has no repeat info event
+                    // to carry the configured rate and delay: clients repeat
+                    // held keys themselves by resending presses, which
+                    // `HeldKeys` lets through, so nothing is sent for it.
+                    if let Some((ref fd, size)) = keymap_fd {
+                        if let Some(keyboard) = device.interface::<eis::Keyboard>() {
+                            keyboard.keymap(eis::keyboard::KeymapType::Xkb, size, fd.as_fd());
+                        }
+                    }
+                },
+            );
+            // Devices bound while the compositor's session is inactive wait
+            // for it to be activated
+            if !state
+                .common
+                .eis_state
+                .as_ref()
+                .is_some_and(EisState::is_paused)
+            {
+                device.resumed();
+            }
+            if let Some(session) = session_mut(state, session_id) {
+                // Only now that the device exists does it count
+                session.devices.add(&session.granted_capabilities);
+                session.bound_devices.push(device.clone());
+                // Devices live as long as the session, so a later bind without
+                // a keyboard leaves the earlier one in place
+                if let Some(keyboard) = device.interface::<eis::Keyboard>() {
+                    session.keyboard = Some(keyboard);
+                    session.modifiers = ModifierFeedback::default();
+                }
+            }
+            // Start the client off with the compositor's current modifiers
+            send_modifiers(state, session_id);
+            defer_flush(state, session_id);
+        }
+        EisRequest::DeviceStartEmulating(_) | EisRequest::DeviceStopEmulating(_) => {}
+        EisRequest::Frame(_) => {
+            flush_motion(state, session_id, time);
+            flush_touch_frame(state, session_id);
+        }
+        // The EI protocol has no swipe, pinch or hold gesture interfaces, so
+        // there is nothing here to map onto the pointer gesture APIs; clients
+        // can only send the underlying touches or scrolls.
+        _ => {
+            debug!("Unhandled EIS request: {:?}", request);
+        }
+    }
+    Ok(())
+}
+
+fn session_mut(state: &mut State, session_id: SessionId) -> Option<&mut EisSession> {
+    state
+        .common
+        .eis_state
+        .as_mut()
+        .and_then(|eis_state| eis_state.sessions.get_mut(&session_id))
+}
+
+/// Turn DPMS-off displays back on and count as user activity, the same way
+/// local input does, so a remote user connecting to a sleeping machine sees
+/// the screen.
+fn wake_displays(state: &mut State) {
+    debug!("Waking displays for new EIS session");
+    crate::wayland::handlers::output_power::set_all_surfaces_dpms_on(state);
+    let seat = state.common.shell.read().seats.try_last_active().cloned();
+    if let Some(seat) = seat {
+        state.common.idle_notifier_state.notify_activity(&seat);
+    }
+}
+
+fn touch_id_allowed(state: &State, touch_id: u32) -> bool {
+    state
+        .common
+        .eis_state
+        .as_ref()
+        .is_some_and(|eis_state| eis_state.touch_id_allowed(touch_id))
+}
+
+/// Drop an event of a session whose `values` aren't all finite.
+fn check_finite(
+    state: &mut State,
+    session_id: SessionId,
+    event: &'static str,
+    values: (f64, f64),
+) -> Result<(), Rejection> {
+    match state.common.eis_state.as_mut() {
+        Some(eis_state) => eis_state.check_finite(session_id, event, values),
+        None if values.0.is_finite() && values.1.is_finite() => Ok(()),
+        None => Err(Rejection::NonFinite),
+    }
+}
+
+/// Take an event from the session's rate limit. Dropped events return before
+/// injecting anything, so no frame is emitted for them.
+fn rate_limit_allows(state: &mut State, session_id: SessionId) -> bool {
+    let rate = state
+        .common
+        .config
+        .cosmic_conf
+        .eis_config
+        .max_events_per_second;
+    if rate == 0 {
+        return true;
+    }
+    let Some(session) = session_mut(state, session_id) else {
+        return true;
+    };
+    let now = Instant::now();
+    if session.rate_limit.take(now, rate) {
+        return true;
+    }
+
+    session.rate_limited += 1;
+    if let Some(suppressed) = session
+        .rate_limit_warning
+        .check(now, REJECTION_WARN_INTERVAL)
+    {
+        warn!(
+            session = session_id,
+            rate,
+            total = session.rate_limited,
+            suppressed,
+            "Dropping EIS input: event rate limit exceeded"
+        );
+    }
+    false
+}
+
+/// Count an event against the session's rolling budget. Once it is used up
+/// the event is dropped and, depending on the policy, the session ended.
+fn budget_allows(state: &mut State, session_id: SessionId) -> bool {
+    let config = &state.common.config.cosmic_conf.eis_config;
+    let (budget, policy) = (config.event_budget, config.event_budget_policy);
+    let window = Duration::from_secs(config.event_budget_window_s);
+    if budget == 0 {
+        return true;
+    }
+    let Some(session) = session_mut(state, session_id) else {
+        return true;
+    };
+    let now = Instant::now();
+    if session.budget.take(now, window, budget) {
+        return true;
+    }
+
+    session.over_budget += 1;
+    if let Some(suppressed) = session.budget_warning.check(now, REJECTION_WARN_INTERVAL) {
+        warn!(
+            session = session_id,
+            budget,
+            window_s = window.as_secs(),
+            total = session.over_budget,
+            suppressed,
+            ?policy,
+            "Dropping EIS input: event budget used up"
+        );
+    }
+    if policy == BudgetPolicy::Disconnect {
+        // Not from within the connection's own source
+        state.common.event_loop_handle.insert_idle(move |state| {
+            if let Some(eis_state) = state.common.eis_state.as_mut() {
+                eis_state.disconnect_session(session_id);
+            }
+        });
+    }
+    false
+}
+
+/// Check that the session's device was granted the capability `request`
+/// needs, warning (throttled) about events for capabilities it never bound.
+fn bound_capabilities_allow(
+    state: &mut State,
+    session_id: SessionId,
+    request: &EisRequest,
+) -> bool {
+    let required = capabilities::required(request);
+    let Some(session) = session_mut(state, session_id) else {
+        return true;
+    };
+    if capabilities::allows(&session.granted_capabilities, required) {
+        return true;
+    }
+    if let Some(suppressed) = session
+        .capability_warning
+        .check(Instant::now(), REJECTION_WARN_INTERVAL)
+    {
+        warn!(
+            session = session_id,
+            ?required,
+            granted = ?session.granted_capabilities,
+            suppressed,
+            "Rejecting EIS event for a capability the client did not bind"
+        );
+    }
+    false
+}
+
+/// Count an event dropped for an out-of-range code or ID, returning
+/// `Some(suppressed)` if it should be logged. A misbehaving client may send
+/// these in bulk, so only a sample is logged.
+fn count_out_of_range(state: &mut State) -> Option<u64> {
+    count_rejected(state, Rejection::OutOfRange);
+    let eis_state = state.common.eis_state.as_mut()?;
+    eis_state
+        .rejection_log
+        .sample(Rejection::OutOfRange, Instant::now())
+}
+
+fn count_rejected(state: &State, reason: Rejection) {
+    if let Some(metrics) = metrics(state) {
+        metrics.rejected(reason);
+    }
+}
+
+/// Count an event dropped for `reason` and return it as the event's result.
+fn reject(state: &State, reason: Rejection) -> Result<(), Rejection> {
+    count_rejected(state, reason);
+    Err(reason)
+}
+
+fn metrics(state: &State) -> Option<&Metrics> {
+    state
+        .common
+        .eis_state
+        .as_ref()
+        .map(|eis_state| &eis_state.metrics)
+}
+
+/// Apply the session's calibration, region scale and the configured rounding
+/// to an absolute pointer or touch position.
+fn map_absolute(state: &State, session_id: SessionId, x: f64, y: f64) -> (f64, f64) {
+    let session = state
+        .common
+        .eis_state
+        .as_ref()
+        .and_then(|eis_state| eis_state.sessions.get(&session_id));
+    let (x, y) = session.map_or((x, y), |session| session.calibration.apply(x, y));
+    let (x, y) = session.map_or((x, y), |session| {
+        region_to_logical(&session.regions, (x, y))
+    });
+    let config = &state.common.config.cosmic_conf.eis_config;
+    let target = session
+        .and_then(|session| session.target_output.as_deref())
+        .and_then(|name| output_geometry(state, name));
+    let outputs = state
+        .common
+        .shell
+        .read()
+        .outputs()
+        .map(|output| output.geometry())
+        .collect::<Vec<_>>();
+    let origin = coordinate_origin(config.absolute_origin, target, &outputs);
+    let (x, y) = output_relative_to_global((x, y), origin);
+    let rounding = config.coordinate_rounding;
+    (round_coordinate(rounding, x), round_coordinate(rounding, y))
+}
+
+/// Apply the configured maximum jump to an absolute motion of a session,
+/// returning where to move the pointer, or `None` if the motion is dropped.
+fn bound_absolute_jump(
+    state: &mut State,
+    session_id: SessionId,
+    target: (f64, f64),
+) -> Option<(f64, f64)> {
+    let config = &state.common.config.cosmic_conf.eis_config;
+    let (max, policy) = (config.max_absolute_jump, config.absolute_jump_policy);
+    let Some(session) = session_mut(state, session_id) else {
+        return Some(target);
+    };
+    let limited = session
+        .last_absolute
+        .filter(|_| max > 0.0)
+        .and_then(|from| limit_jump(from, target, max));
+    let position = match limited {
+        None => target,
+        Some(clamped) => {
+            session.limited_jumps += 1;
+            debug!(
+                session = session_id,
+                ?policy,
+                total = session.limited_jumps,
+                "Limiting EIS absolute motion: jump too far"
+            );
+            match policy {
+                JumpPolicy::Clamp => clamped,
+                JumpPolicy::Reject => return None,
+            }
+        }
+    };
+    session.last_absolute = Some(position);
+    Some(position)
+}
+
+/// The point `max` away from `from` towards `to`, if `to` is further away.
+fn limit_jump(from: (f64, f64), to: (f64, f64), max: f64) -> Option<(f64, f64)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let distance = dx.hypot(dy);
+    (distance > max).then(|| {
+        let scale = max / distance;
+        (from.0 + dx * scale, from.1 + dy * scale)
+    })
+}
+
+/// An EIS device region, in the coordinate space the client sends absolute
+/// positions in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceRegion {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    scale: f32,
+    /// Logical offset from the origin of the output the region stands for.
+    logical: (u32, u32),
+}
+
+/// One region per output with its offset from `origin`, size and scale, the
+/// origin being that of [`coordinate_origin`].
+///
+/// Clients address a region scaled from its offset, so it covers
+/// `width * scale` by `height * scale` client units. Offsets are laid out in
+/// that scaled space, keeping the regions of neighbouring outputs apart.
+/// EIS offsets are unsigned, so outputs left of or above the origin can't be
+/// described and are left out.
+fn device_regions(
+    outputs: &[(Rectangle<i32, Global>, f64)],
+    origin: Point<i32, Global>,
+) -> Vec<DeviceRegion> {
+    let (columns, rows): (Vec<_>, Vec<_>) = outputs
+        .iter()
+        .map(|(geometry, scale)| {
+            let loc = geometry.loc - origin;
+            let size = (geometry.size.w.max(0), geometry.size.h.max(0));
+            (
+                (loc.x, loc.x + size.0, *scale),
+                (loc.y, loc.y + size.1, *scale),
+            )
+        })
+        .unzip();
+    outputs
+        .iter()
+        .filter_map(|(geometry, scale)| {
+            let loc = geometry.loc - origin;
+            let logical = (u32::try_from(loc.x).ok()?, u32::try_from(loc.y).ok()?);
+            Some(DeviceRegion {
+                x: scaled_offset(&columns, loc.x),
+                y: scaled_offset(&rows, loc.y),
+                width: geometry.size.w.max(0) as u32,
+                height: geometry.size.h.max(0) as u32,
+                scale: *scale as f32,
+                logical,
+            })
+        })
+        .collect()
+}
+
+/// Where the logical offset `to` lies along one axis in client units, every
+/// stretch of the axis before it scaled by the largest scale of the
+/// `(start, end, scale)` spans of the outputs covering it.
+fn scaled_offset(spans: &[(i32, i32, f64)], to: i32) -> u32 {
+    let mut edges = spans
+        .iter()
+        .flat_map(|(start, end, _)| [*start, *end])
+        .chain([0, to])
+        .filter(|edge| (0..=to).contains(edge))
+        .collect::<Vec<_>>();
+    edges.sort_unstable();
+    edges.dedup();
+    let offset: f64 = edges
+        .windows(2)
+        .map(|stretch| {
+            let (from, until) = (stretch[0], stretch[1]);
+            let scale = spans
+                .iter()
+                .filter(|(start, end, scale)| *start <= from && until <= *end && scale.is_finite())
+                .map(|(_, _, scale)| *scale)
+                .reduce(f64::max)
+                .unwrap_or(1.0);
+            f64::from(until - from) * scale
+        })
+        .sum();
+    offset.round() as u32
+}
+
+/// Convert a client position into logical coordinates using the scale of the
+/// region it falls in. Positions outside every region are passed through
+/// unchanged.
+fn region_to_logical(regions: &[DeviceRegion], (x, y): (f64, f64)) -> (f64, f64) {
+    regions
+        .iter()
+        .find_map(|region| {
+            let scale = f64::from(region.scale);
+            if !scale.is_finite() || scale <= 0.0 {
+                return None;
+            }
+            let (dx, dy) = (x - f64::from(region.x), y - f64::from(region.y));
+            let contains = (0.0..f64::from(region.width) * scale).contains(&dx)
+                && (0.0..f64::from(region.height) * scale).contains(&dy);
+            let (logical_x, logical_y) = region.logical;
+            contains.then(|| {
+                (
+                    f64::from(logical_x) + dx / scale,
+                    f64::from(logical_y) + dy / scale,
+                )
+            })
+        })
+        .unwrap_or((x, y))
+}
+
+fn output_geometry(state: &State, name: &str) -> Option<Rectangle<i32, Global>> {
+    state
+        .common
+        .shell
+        .read()
+        .outputs()
+        .find(|output| output.name() == name)
+        .map(|output| output.geometry())
+}
+
+/// The bounding box of all outputs, whose top-left is the origin of
+/// virtual desktop coordinates.
+fn desktop_bounds(outputs: &[Rectangle<i32, Global>]) -> Option<Rectangle<i32, Global>> {
+    outputs.iter().copied().reduce(|a, b| a.merge(b))
+}
+
+/// The global position a session's regions and absolute coordinates count
+/// from: its target output's top-left or the global origin, or for the
+/// virtual desktop the top-left of the bounding box of all `outputs`.
+fn coordinate_origin(
+    origin: AbsoluteOrigin,
+    target: Option<Rectangle<i32, Global>>,
+    outputs: &[Rectangle<i32, Global>],
+) -> Point<i32, Global> {
+    match origin {
+        AbsoluteOrigin::PerOutput => target.map(|geometry| geometry.loc),
+        AbsoluteOrigin::VirtualDesktop => desktop_bounds(outputs).map(|bounds| bounds.loc),
+    }
+    .unwrap_or_else(|| (0, 0).into())
+}
+
+/// Translate a position relative to `origin` into global space.
+fn output_relative_to_global((x, y): (f64, f64), origin: Point<i32, Global>) -> (f64, f64) {
+    (x + f64::from(origin.x), y + f64::from(origin.y))
+}
+
+/// The point on `geometry` closest to `position`.
+fn clamp_to_geometry(
+    position: Point<f64, Global>,
+    geometry: Rectangle<i32, Global>,
+) -> Point<f64, Global> {
+    let max_x = (geometry.loc.x + geometry.size.w - 1).max(geometry.loc.x);
+    let max_y = (geometry.loc.y + geometry.size.h - 1).max(geometry.loc.y);
+    (
+        position
+            .x
+            .clamp(f64::from(geometry.loc.x), f64::from(max_x)),
+        position
+            .y
+            .clamp(f64::from(geometry.loc.y), f64::from(max_y)),
+    )
+        .into()
+}
+
+/// Apply relative motion by `delta` from `start` under the `clamp` policy.
+///
+/// Returns the position to continue the next motion from and the position to
+/// move the pointer to. These only differ for [`PointerClamp::Union`] while
+/// travelling through a gap, where the pointer snaps to the nearest output.
+/// `fallback` is the output motion is confined to when it leaves all outputs
+/// under [`PointerClamp::PerOutput`].
+fn clamp_motion(
+    clamp: PointerClamp,
+    outputs: &[Rectangle<i32, Global>],
+    fallback: Rectangle<i32, Global>,
+    start: Point<f64, Global>,
+    (dx, dy): (f64, f64),
+) -> (Point<f64, Global>, Point<f64, Global>) {
+    let target: Point<f64, Global> = (start.x + dx, start.y + dy).into();
+    let containing = |position: Point<f64, Global>| {
+        outputs
+            .iter()
+            .find(|geometry| geometry.to_f64().contains(position))
+            .copied()
+    };
+
+    match clamp {
+        PointerClamp::PerOutput => {
+            let position = clamp_to_geometry(target, containing(target).unwrap_or(fallback));
+            (position, position)
+        }
+        PointerClamp::Union => {
+            let Some(bounds) = desktop_bounds(outputs) else {
+                let position = clamp_to_geometry(target, fallback);
+                return (position, position);
+            };
+            let tracked = clamp_to_geometry(target, bounds);
+            let position = match containing(tracked) {
+                Some(_) => tracked,
+                None => snap_to_outputs(outputs, tracked).unwrap_or(tracked),
+            };
+            (tracked, position)
+        }
+    }
+}
+
+/// `position` if it lies on one of `outputs`, else the closest point on the
+/// nearest one. `None` if there are no outputs.
+fn snap_to_outputs(
+    outputs: &[Rectangle<i32, Global>],
+    position: Point<f64, Global>,
+) -> Option<Point<f64, Global>> {
+    outputs
+        .iter()
+        .map(|geometry| clamp_to_geometry(position, *geometry))
+        .min_by(|a, b| {
+            let distance = |p: &Point<f64, Global>| (p.x - position.x).hypot(p.y - position.y);
+            distance(a).total_cmp(&distance(b))
+        })
+}
+
+/// Move a touch outside every output onto the nearest output edge, the way
+/// pointer motion is kept on the layout, instead of touching nothing.
+fn clamp_touch(state: &State, session_id: SessionId, (x, y): (f64, f64)) -> (f64, f64) {
+    let outputs = state
+        .common
+        .shell
+        .read()
+        .outputs()
+        .map(|output| output.geometry())
+        .collect::<Vec<_>>();
+    let position = (x, y).into();
+    match snap_to_outputs(&outputs, position) {
+        Some(clamped) if clamped != position => {
+            debug!(
+                session = session_id,
+                x,
+                y,
+                to = ?clamped,
+                "Clamping EIS touch outside all outputs"
+            );
+            (clamped.x, clamped.y)
+        }
+        _ => (x, y),
+    }
+}
+
+/// Whether input is dropped under `policy`, given whether the surface at its
+/// position rejects it. `covered` is only checked when it matters.
+fn rejected_input_dropped(policy: RejectedInput, covered: impl FnOnce() -> bool) -> bool {
+    match policy {
+        RejectedInput::FallThrough => false,
+        RejectedInput::Drop => covered(),
+    }
+}
+
+/// Whether an overlay or top layer surface covers `position` without
+/// accepting input there, so input would reach whatever lies below it.
+fn covered_without_input(output: &Output, position: Point<f64, Global>) -> bool {
+    let map = layer_map_for_output(output);
+    let local = position.to_local(output).as_logical();
+    [Layer::Overlay, Layer::Top].into_iter().any(|layer| {
+        map.layers_on(layer).any(|surface| {
+            map.layer_geometry(surface).is_some_and(|geometry| {
+                geometry.to_f64().contains(local)
+                    && surface
+                        .surface_under(local - geometry.loc.to_f64(), WindowSurfaceType::ALL)
+                        .is_none()
+            })
+        })
+    })
+}
+
+/// Whether a touch down at `position` is dropped for the surface there
+/// rejecting input.
+fn touch_rejected(state: &State, position: Point<f64, Global>) -> bool {
+    let policy = state.common.config.cosmic_conf.eis_config.rejected_input;
+    rejected_input_dropped(policy, || {
+        let shell = state.common.shell.read();
+        shell
+            .outputs()
+            .find(|output| output.geometry().to_f64().contains(position))
+            .is_some_and(|output| covered_without_input(output, position))
+    })
+}
+
+/// Snap an absolute coordinate according to the configured rounding mode.
+///
+/// Relative motion stays on the same grid: while rounding it moves by whole
+/// pixels and carries the fractions over to the next motion, whereas without
+/// rounding fractional deltas are applied as they come.
+fn round_coordinate(mode: CoordinateRounding, value: f64) -> f64 {
+    match mode {
+        CoordinateRounding::None => value,
+        CoordinateRounding::Floor => value.floor(),
+        CoordinateRounding::Round => value.round(),
+        CoordinateRounding::Ceil => value.ceil(),
+    }
+}
+
+/// Build the axis frame for smooth scrolling by `dx`/`dy`. EIS doesn't say
+/// what device scrolled, so it is reported as continuous, leaving kinetic
+/// scrolling to the client's stops.
+fn delta_axis_frame(time: u32, dx: f64, dy: f64) -> smithay::input::pointer::AxisFrame {
+    use smithay::backend::input::{Axis, AxisSource};
+    let mut frame = smithay::input::pointer::AxisFrame::new(time).source(AxisSource::Continuous);
+    if dy.abs() > 0.0 {
+        frame = frame.value(Axis::Vertical, dy);
+    }
+    if dx.abs() > 0.0 {
+        frame = frame.value(Axis::Horizontal, dx);
+    }
+    frame
+}
+
+/// Build the axis frame for discrete wheel steps given in v120 units, with
+/// the matching continuous value for clients that ignore discrete steps.
+fn discrete_axis_frame(
+    time: u32,
+    discrete_dx: i32,
+    discrete_dy: i32,
+) -> smithay::input::pointer::AxisFrame {
+    use smithay::backend::input::{Axis, AxisSource};
+    let mut frame = smithay::input::pointer::AxisFrame::new(time).source(AxisSource::Wheel);
+    for (axis, steps) in [
+        (Axis::Horizontal, discrete_dx),
+        (Axis::Vertical, discrete_dy),
+    ] {
+        if steps != 0 {
+            frame = frame
+                .value(axis, f64::from(steps) / 120. * WHEEL_STEP_DISTANCE)
+                .v120(axis, steps);
+        }
+    }
+    frame
+}
+
+/// Build the axis frame ending kinetic scrolling on the given axes. Wayland
+/// clients only expect a stop from finger scrolling, so the frame says so.
+fn stop_axis_frame(time: u32, x: bool, y: bool) -> smithay::input::pointer::AxisFrame {
+    use smithay::backend::input::{Axis, AxisSource};
+    let mut frame = smithay::input::pointer::AxisFrame::new(time).source(AxisSource::Finger);
+    if x {
+        frame = frame.stop(Axis::Horizontal);
+    }
+    if y {
+        frame = frame.stop(Axis::Vertical);
+    }
+    frame
+}
+
+/// Add pointer motion to the session's batch, to be injected at the end of
+/// the client's frame. Should the frame not arrive during this loop
+/// iteration, an idle callback injects the motion anyway, or with a
+/// coalescing window a timer once the window expired.
+fn queue_motion(state: &mut State, session_id: SessionId, motion: Motion, time: u32) {
+    let config = &state.common.config.cosmic_conf.eis_config;
+    let window = Duration::from_millis(config.motion_coalesce_ms);
+    let priority = config.motion_priority;
+    let Some(session) = session_mut(state, session_id) else {
+        return;
+    };
+    let first = session.motion.is_empty();
+    let previous = session.motion.push(motion, priority);
+    if let Some(previous) = previous {
+        inject_motion(state, session_id, previous, time);
+    }
+    if window.is_zero() {
+        if first {
+            state.common.event_loop_handle.insert_idle(move |state| {
+                let time = state.common.clock.now().as_millis();
+                flush_motion(state, session_id, time);
+            });
+        }
+        return;
+    }
+
+    let Some(session) = session_mut(state, session_id) else {
+        return;
+    };
+    if session.motion.is_due(Instant::now(), window) {
+        flush_motion(state, session_id, time);
+    } else if session.motion_timer.is_none() {
+        arm_motion_timer(state, session_id, window);
+    }
+}
+
+/// Start the timer injecting a session's batched motion once it waited for
+/// the coalescing window, following the batches as frames flush them.
+fn arm_motion_timer(state: &mut State, session_id: SessionId, window: Duration) {
+    let Some(deadline) =
+        session_mut(state, session_id).and_then(|session| session.motion.deadline(window))
+    else {
+        return;
+    };
+    let res = state.common.event_loop_handle.insert_source(
+        Timer::from_deadline(deadline),
+        move |now, _, state| {
+            let window = Duration::from_millis(
+                state
+                    .common
+                    .config
+                    .cosmic_conf
+                    .eis_config
+                    .motion_coalesce_ms,
+            );
+            let Some(session) = session_mut(state, session_id) else {
+                return TimeoutAction::Drop;
+            };
+            if !window.is_zero()
+                && !session.motion.is_due(now, window)
+                && let Some(deadline) = session.motion.deadline(window)
+            {
+                return TimeoutAction::ToInstant(deadline);
+            }
+            session.motion_timer = None;
+            let time = state.common.clock.now().as_millis();
+            flush_motion(state, session_id, time);
+            TimeoutAction::Drop
+        },
+    );
+    match res {
+        Ok(token) => {
+            if let Some(session) = session_mut(state, session_id) {
+                session.motion_timer = Some(token);
+            }
+        }
+        Err(err) => {
+            warn!(
+                session = session_id,
+                "Failed to schedule EIS motion flush: {}", err.error
+            );
+            let time = state.common.clock.now().as_millis();
+            flush_motion(state, session_id, time);
+        }
+    }
+}
+
+/// Inject the motion batched for a session, if any.
+fn flush_motion(state: &mut State, session_id: SessionId, time: u32) {
+    if let Some(motion) = session_mut(state, session_id).and_then(|session| session.motion.take()) {
+        inject_motion(state, session_id, motion, time);
+    }
+}
+
+fn inject_motion(state: &mut State, session_id: SessionId, motion: Motion, time: u32) {
+    match motion {
+        Motion::Relative(dx, dy) => inject_pointer_relative(state, session_id, dx, dy, time),
+        Motion::Absolute(x, y) => {
+            inject_pointer_absolute(state, session_id, x, y, time);
+        }
+    }
+}
+
+/// Move the pointer by a relative delta, clamped to the output layout.
+fn inject_pointer_relative(state: &mut State, session_id: SessionId, dx: f64, dy: f64, time: u32) {
+    let config = &state.common.config.cosmic_conf.eis_config;
+    let (clamp, rounding) = (config.pointer_clamp, config.coordinate_rounding);
+    let Some(session) = session_mut(state, session_id) else {
+        return;
+    };
+    let tracked = session.pointer_position;
+    // On the pixel grid move by whole pixels, keeping what's left for later;
+    // off it the pointer position is fractional, so there is nothing to keep
+    let (dx, dy) = match rounding {
+        CoordinateRounding::None => (dx, dy),
+        _ => session.motion_remainder.take_whole(dx, dy),
+    };
+    if dx == 0.0 && dy == 0.0 && rounding != CoordinateRounding::None {
+        return;
+    }
+    let Some(seat) = target_seat(state, session_id, None) else {
+        return;
+    };
+    let shell = state.common.shell.read();
+    if let Some(pointer) = seat.get_pointer() {
+        let current = pointer.current_location().as_global();
+        // Continue from within a gap unless something else moved the pointer
+        let start = match tracked {
+            Some((tracked, injected)) if injected == current => tracked,
+            _ => current,
+        };
+
+        let outputs = shell
+            .outputs()
+            .map(|output| output.geometry())
+            .collect::<Vec<_>>();
+        let (tracked, position) = clamp_motion(
+            clamp,
+            &outputs,
+            seat.active_output().geometry(),
+            start,
+            (dx, dy),
+        );
+        let output = shell
+            .outputs()
+            .find(|o| o.geometry().to_f64().contains(position))
+            .cloned()
+            .unwrap_or_else(|| seat.active_output());
+        let policy = state.common.config.cosmic_conf.eis_config.rejected_input;
+        if rejected_input_dropped(policy, || covered_without_input(&output, position)) {
+            trace!(
+                ?position,
+                "Dropping EIS motion onto a surface rejecting input"
+            );
+            return;
+        }
+        // The shell stays locked, so reach the session through the field
+        if let Some(session) = state
+            .common
+            .eis_state
+            .as_mut()
+            .and_then(|eis_state| eis_state.sessions.get_mut(&session_id))
+        {
+            session.pointer_position = Some((tracked, position));
+            session.last_absolute = Some((position.x, position.y));
+        }
+
+        // Compute surface under the new pointer position
+        let under = surface_under_cached(
+            state.common.eis_state.as_mut(),
+            &state.common.config.cosmic_conf.eis_config,
+            position,
+            &output,
+            &shell,
+        )
+        .map(|(target, pos)| (target, pos.as_logical()));
+
+        let serial = SERIAL_COUNTER.next_serial();
+        std::mem::drop(shell);
+        pointer.motion(
+            state,
+            under,
+            &smithay::input::pointer::MotionEvent {
+                location: position.as_logical(),
+                serial,
+                time,
+            },
+        );
+        pointer.frame(state);
+    }
+}
+
+/// Where a warp to a global position lands: onto the nearest output if it is
+/// outside all of them. `None` for a position that can't be warped to.
+fn warp_position(
+    outputs: &[Rectangle<i32, Global>],
+    position: Point<f64, Global>,
+) -> Option<Point<f64, Global>> {
+    if !position.x.is_finite() || !position.y.is_finite() {
+        return None;
+    }
+    snap_to_outputs(outputs, position)
+}
+
+/// Teleport a session's pointer to a global position with a single motion,
+/// so only the surfaces left and entered there see focus change.
+fn warp_pointer(
+    state: &mut State,
+    session_id: SessionId,
+    position: Point<f64, Global>,
+) -> Option<bool> {
+    session_mut(state, session_id)?;
+    let outputs = state
+        .common
+        .shell
+        .read()
+        .outputs()
+        .map(|output| output.geometry())
+        .collect::<Vec<_>>();
+    let Some(position) = warp_position(&outputs, position) else {
+        return Some(false);
+    };
+    let gate = command_gate(state, session_id, true, || {
+        vec![capture::absolute(position.x, position.y)]
+    });
+    if let Some(done) = gate.skipped() {
+        return Some(done);
+    }
+    debug!(session = session_id, ?position, "Warping EIS pointer");
+    let time = state.common.clock.now().as_millis();
+    let warped = inject_pointer_absolute(state, session_id, position.x, position.y, time);
+    if warped && let Some(session) = session_mut(state, session_id) {
+        // Relative motion and absolute jumps go on from where the warp landed
+        session.last_absolute = Some((position.x, position.y));
+        session.pointer_position = Some((position, position));
+    }
+    Some(warped)
+}
+
+/// Move the pointer of the seat owning a global position to it, returning
+/// whether the motion was sent.
+fn inject_pointer_absolute(
+    state: &mut State,
+    session_id: SessionId,
+    x: f64,
+    y: f64,
+    time: u32,
+) -> bool {
+    let Some(seat) = target_seat(state, session_id, Some((x, y).into())) else {
+        return false;
+    };
+    let Some(pointer) = seat.get_pointer() else {
+        return false;
+    };
+    let shell = state.common.shell.read();
+    let position: smithay::utils::Point<f64, Global> = (x, y).into();
+
+    // Find the output containing this position
+    let output = shell
+        .outputs()
+        .find(|o| o.geometry().to_f64().contains(position))
+        .cloned()
+        .unwrap_or_else(|| seat.active_output());
+    let policy = state.common.config.cosmic_conf.eis_config.rejected_input;
+    if rejected_input_dropped(policy, || covered_without_input(&output, position)) {
+        trace!(
+            ?position,
+            "Dropping EIS motion onto a surface rejecting input"
+        );
+        return false;
+    }
+
+    // Compute surface under the pointer position
+    let under = surface_under_cached(
+        state.common.eis_state.as_mut(),
+        &state.common.config.cosmic_conf.eis_config,
+        position,
+        &output,
+        &shell,
+    )
+    .map(|(target, pos)| (target, pos.as_logical()));
+
+    let serial = SERIAL_COUNTER.next_serial();
+    std::mem::drop(shell);
+    // Paths and warps move the pointer too, so later jumps start from here
+    if let Some(session) = session_mut(state, session_id) {
+        session.last_absolute = Some((x, y));
+    }
+    pointer.motion(
+        state,
+        under,
+        &smithay::input::pointer::MotionEvent {
+            location: (x, y).into(),
+            serial,
+            time,
+        },
+    );
+    pointer.frame(state);
+    true
+}
+
+/// Send a key to a seat's keyboard, returning whether a surface had keyboard
+/// focus to receive it.
+fn inject_key(
+    state: &mut State,
+    seat: &Seat<State>,
+    key: u32,
+    key_state: KeyState,
+    time: u32,
+) -> bool {
+    let Some(keyboard) = seat.get_keyboard() else {
+        return false;
+    };
+    let focused = keyboard.current_focus().is_some();
+    let serial = SERIAL_COUNTER.next_serial();
+    keyboard.input(
+        state,
+        Keycode::new(key),
+        key_state,
+        serial,
+        time,
+        |_, _, _| FilterResult::Forward::<bool>,
+    );
+    focused
+}
+
+/// Hand `sequence`'s `(key, pressed)` events to `press` in order.
+fn press_sequence(
+    sequence: impl IntoIterator<Item = (u32, bool)>,
+    mut press: impl FnMut(u32, KeyState),
+) {
+    for (key, pressed) in sequence {
+        let key_state = if pressed {
+            KeyState::Pressed
+        } else {
+            KeyState::Released
+        };
+        press(key, key_state);
+    }
+}
+
+/// Press an editing command's shortcut for a session as one uninterrupted
+/// sequence, if a surface has keyboard focus to receive it.
+fn edit(state: &mut State, session_id: SessionId, command: EditingCommand) -> Option<bool> {
+    session_mut(state, session_id)?;
+    let Some(seat) = target_seat(state, session_id, None) else {
+        return Some(false);
+    };
+    let focused = seat
+        .get_keyboard()
+        .is_some_and(|keyboard| keyboard.current_focus().is_some());
+    let shortcuts = &state.common.config.cosmic_conf.eis_config.editing_shortcuts;
+    let sequence = keys::shortcut_sequence(command.shortcut(shortcuts), focused);
+    if sequence.is_empty() {
+        debug!(
+            session = session_id,
+            ?command,
+            "Skipping EIS editing command: nothing is focused"
+        );
+        return Some(false);
+    }
+    let gate = command_gate(state, session_id, false, || capture::keys(&sequence));
+    if let Some(done) = gate.skipped() {
+        return Some(done);
+    }
+
+    let time = state.common.clock.now().as_millis();
+    press_sequence(sequence, |key, key_state| {
+        inject_key(state, &seat, key, key_state, time);
+    });
+    send_modifiers(state, session_id);
+    Some(true)
+}
+
+/// Press the close shortcut on a session's keyboard, if a window is focused
+/// on its seat and, with `app_id`, that window's app_id matches.
+fn close_focused_window(
+    state: &mut State,
+    session_id: SessionId,
+    app_id: Option<&str>,
+) -> Option<bool> {
+    session_mut(state, session_id)?;
+    let Some(seat) = target_seat(state, session_id, None) else {
+        return Some(false);
+    };
+    let focused = seat_focused_app_id(&seat);
+    let shortcut = &state.common.config.cosmic_conf.eis_config.close_shortcut;
+    let sequence = close_sequence(shortcut, app_id, focused.as_deref());
+    if sequence.is_empty() {
+        info!(
+            session = session_id,
+            required = app_id,
+            focused = focused.as_deref(),
+            "Not closing window: none or another one is focused"
+        );
+        return Some(false);
+    }
+    let gate = command_gate(state, session_id, false, || capture::keys(&sequence));
+    if let Some(done) = gate.skipped() {
+        return Some(done);
+    }
+
+    let time = state.common.clock.now().as_millis();
+    press_sequence(sequence, |key, key_state| {
+        inject_key(state, &seat, key, key_state, time);
+    });
+    send_modifiers(state, session_id);
+    Some(true)
+}
+
+/// The close shortcut's events, or none unless a window is `focused` and,
+/// with a `required` app_id, it is that app's.
+fn close_sequence(
+    shortcut: &Shortcut,
+    required: Option<&str>,
+    focused: Option<&str>,
+) -> Vec<(u32, bool)> {
+    let allowed =
+        focused.is_some() && required.is_none_or(|required| app_id_matches(required, focused));
+    keys::shortcut_sequence(shortcut, allowed)
+}
+
+/// The `(code, pressed)` events opening a context menu: the Menu key's evdev
+/// code for [`ContextMenuInput::MenuKey`], or the right button's for
+/// [`ContextMenuInput::RightClick`].
+fn context_menu_sequence(input: ContextMenuInput) -> [(u32, bool); 2] {
+    const KEY_COMPOSE: u32 = 127;
+    const BTN_RIGHT: u32 = 0x111;
+    let code = match input {
+        ContextMenuInput::MenuKey => KEY_COMPOSE,
+        ContextMenuInput::RightClick => BTN_RIGHT,
+    };
+    [(code, true), (code, false)]
+}
+
+/// Open a context menu for a session with the configured input, if its seat
+/// has a keyboard or pointer for it.
+fn context_menu(state: &mut State, session_id: SessionId) -> Option<bool> {
+    session_mut(state, session_id)?;
+    let Some(seat) = target_seat(state, session_id, None) else {
+        return Some(false);
+    };
+    let input = state.common.config.cosmic_conf.eis_config.context_menu;
+    let sequence = context_menu_sequence(input);
+    let time = state.common.clock.now().as_millis();
+    match input {
+        ContextMenuInput::MenuKey => {
+            if seat.get_keyboard().is_none() {
+                debug!(session = session_id, "No keyboard to open a context menu");
+                return Some(false);
+            }
+            let gate = command_gate(state, session_id, false, || capture::keys(&sequence));
+            if let Some(done) = gate.skipped() {
+                return Some(done);
+            }
+            press_sequence(sequence, |key, key_state| {
+                inject_key(state, &seat, key, key_state, time);
+            });
+            send_modifiers(state, session_id);
+        }
+        ContextMenuInput::RightClick => {
+            let Some(pointer) = seat.get_pointer() else {
+                debug!(session = session_id, "No pointer to open a context menu");
+                return Some(false);
+            };
+            let gate = command_gate(state, session_id, true, || {
+                sequence
+                    .iter()
+                    .map(|(button, pressed)| capture::button(*button, *pressed))
+                    .collect()
+            });
+            if let Some(done) = gate.skipped() {
+                return Some(done);
+            }
+            for (button, pressed) in sequence {
+                let button_state = if pressed {
+                    smithay::backend::input::ButtonState::Pressed
+                } else {
+                    smithay::backend::input::ButtonState::Released
+                };
+                pointer.button(
+                    state,
+                    &smithay::input::pointer::ButtonEvent {
+                        button,
+                        state: button_state,
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time,
+                    },
+                );
+                pointer.frame(state);
+            }
+        }
+    }
+    Some(true)
+}
+
+/// Forward the compositor keyboard's modifier state to a session's keyboard,
+/// if it changed since the session was last told.
+fn send_modifiers(state: &mut State, session_id: SessionId) {
+    let Some(keyboard) = target_seat(state, session_id, None).and_then(|seat| seat.get_keyboard())
+    else {
+        return;
+    };
+    let serialized = keyboard.modifier_state().serialized;
+    let current = Modifiers {
+        depressed: serialized.depressed,
+        latched: serialized.latched,
+        locked: serialized.locked,
+        group: serialized.layout_effective,
+    };
+
+    let Some(session) = session_mut(state, session_id) else {
+        return;
+    };
+    let (Some(eis_keyboard), Some(connection)) = (&session.keyboard, &session.connection) else {
+        return;
+    };
+    if let Some(modifiers) = session.modifiers.update(current) {
+        eis_keyboard.modifiers(
+            connection.next_serial(),
+            modifiers.depressed,
+            modifiers.locked,
+            modifiers.latched,
+            modifiers.group,
+        );
+        defer_flush(state, session_id);
+    }
+}
+
+/// Flush a session's connection once the current wakeup is done, together
+/// with whatever else is announced until then.
+fn defer_flush(state: &mut State, session_id: SessionId) {
+    if session_mut(state, session_id).is_some_and(|session| session.flush.defer()) {
+        // Idles run after all events of this wakeup were dispatched
+        state
+            .common
+            .event_loop_handle
+            .insert_idle(move |state| flush_session(state, session_id));
+    }
+}
+
+fn flush_session(state: &mut State, session_id: SessionId) {
+    let Some(session) = session_mut(state, session_id) else {
+        return;
+    };
+    if session.flush.take()
+        && let Some(connection) = &session.connection
+        && let Err(e) = connection.flush()
+    {
+        warn!(session = session_id, "Failed to flush EIS connection: {e}");
+    }
+}
+
+/// Lift a touch slot and end the touch frame with it, for lifts outside of a
+/// client's frame.
+fn inject_touch_up(state: &mut State, slot: u32, time: u32) {
+    if let Some(touch_handle) = touch_up(state, slot, time).and_then(|seat| seat.get_touch()) {
+        touch_handle.frame(state);
+    }
+}
+
+/// Lift a touch slot, returning the seat it was on for the touch frame.
+fn touch_up(state: &mut State, slot: u32, time: u32) -> Option<Seat<State>> {
+    let name = state
+        .common
+        .eis_state
+        .as_mut()
+        .and_then(|eis_state| eis_state.touch_seats.remove(&slot));
+    let seat = seat_named(state, name.as_deref())?;
+    let touch_handle = seat.get_touch()?;
+    let serial = SERIAL_COUNTER.next_serial();
+    touch_handle.up(
+        state,
+        &UpEvent {
+            slot: TouchSlot::from(Some(slot)),
+            time,
+            serial,
+        },
+    );
+    Some(seat)
+}
+
+/// End the touch points a session changed on a seat with a single touch
+/// frame at the end of the client's frame. Should the frame not arrive
+/// during this loop iteration, an idle callback sends the touch frame anyway.
+fn queue_touch_frame(state: &mut State, session_id: SessionId, seat: &str) {
+    if session_mut(state, session_id).is_some_and(|session| session.touch_frame.touched(seat)) {
+        state
+            .common
+            .event_loop_handle
+            .insert_idle(move |state| flush_touch_frame(state, session_id));
+    }
+}
+
+/// Send the touch frames a session's touch events wait for, if any.
+fn flush_touch_frame(state: &mut State, session_id: SessionId) {
+    let Some(seats) = session_mut(state, session_id).map(|session| session.touch_frame.take())
+    else {
+        return;
+    };
+    for name in seats {
+        if let Some(touch_handle) = seat_named(state, Some(&name)).and_then(|seat| seat.get_touch())
+        {
+            touch_handle.frame(state);
+        }
+    }
+}
+
+/// Start the timer lifting touches whose client stopped sending events, if
+/// `touch_timeout_ms` is set and it isn't running yet.
+fn arm_touch_timeout(state: &mut State) {
+    let timeout =
+        Duration::from_millis(state.common.config.cosmic_conf.eis_config.touch_timeout_ms);
+    let Some(eis_state) = state.common.eis_state.as_mut() else {
+        return;
+    };
+    if timeout.is_zero() || eis_state.touch_timer_armed {
+        return;
+    }
+
+    let timer = Timer::from_duration(timeout);
+    let res = state
+        .common
+        .event_loop_handle
+        .insert_source(timer, |now, _, state| {
+            let timeout =
+                Duration::from_millis(state.common.config.cosmic_conf.eis_config.touch_timeout_ms);
+            let Some(eis_state) = state.common.eis_state.as_mut() else {
+                return TimeoutAction::Drop;
+            };
+            let expired = if timeout.is_zero() {
+                Vec::new()
+            } else {
+                eis_state.touch_slots.expire(now, timeout)
+            };
+            let next = eis_state
+                .touch_slots
+                .oldest_activity()
+                .filter(|_| !timeout.is_zero())
+                .map(|last_seen| last_seen + timeout);
+            eis_state.touch_timer_armed = next.is_some();
+
+            let time = state.common.clock.now().as_millis();
+            for slot in expired {
+                warn!(
+                    slot,
+                    "Lifting EIS touch that saw no events within the timeout"
+                );
+                inject_touch_up(state, slot, time);
+            }
+            match next {
+                Some(next) => TimeoutAction::ToInstant(next),
+                None => TimeoutAction::Drop,
+            }
+        });
+    match res {
+        Ok(_) => {
+            if let Some(eis_state) = state.common.eis_state.as_mut() {
+                eis_state.touch_timer_armed = true;
+            }
+        }
+        Err(err) => warn!("Failed to schedule EIS touch timeout: {}", err.error),
+    }
+}
+
+/// Whether `request` injects input (as opposed to negotiating devices).
+fn is_input_request(request: &EisRequest) -> bool {
+    matches!(
+        request,
+        EisRequest::KeyboardKey(_)
+            | EisRequest::PointerMotion(_)
+            | EisRequest::PointerMotionAbsolute(_)
+            | EisRequest::Button(_)
+            | EisRequest::ScrollDelta(_)
+            | EisRequest::ScrollDiscrete(_)
+            | EisRequest::ScrollStop(_)
+            | EisRequest::TouchDown(_)
+            | EisRequest::TouchMotion(_)
+            | EisRequest::TouchUp(_)
+            | EisRequest::TouchCancel(_)
+    )
+}
+
+fn is_motion_request(request: &EisRequest) -> bool {
+    matches!(
+        request,
+        EisRequest::PointerMotion(_) | EisRequest::PointerMotionAbsolute(_)
+    )
+}
+
+fn is_pointer_request(request: &EisRequest) -> bool {
+    matches!(
+        request,
+        EisRequest::PointerMotion(_)
+            | EisRequest::PointerMotionAbsolute(_)
+            | EisRequest::Button(_)
+            | EisRequest::ScrollDelta(_)
+            | EisRequest::ScrollDiscrete(_)
+            | EisRequest::ScrollStop(_)
+    )
+}
+
+/// Apply the configured pointer arbitration policy for `session_id`.
+fn pointer_arbitration_allows(state: &mut State, session_id: SessionId) -> bool {
+    let config = &state.common.config.cosmic_conf.eis_config;
+    let (policy, timeout) = (
+        config.pointer_arbitration,
+        Duration::from_millis(config.pointer_release_timeout_ms),
+    );
+    let Some(eis_state) = state.common.eis_state.as_mut() else {
+        return true;
+    };
+    let allowed = eis_state
+        .pointer_arbiter
+        .claim(policy, session_id, Instant::now(), timeout);
+    if !allowed {
+        trace!(
+            session = session_id,
+            owner = eis_state.pointer_arbiter.owner(),
+            "Dropping EIS pointer input: pointer held by another session"
+        );
+    }
+    allowed
+}
+
+/// Outcome of [`command_gate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommandGate {
+    Inject,
+    /// Recorded for `GetCapturedEvents` instead of being injected.
+    Captured,
+    Rejected,
+}
+
+impl CommandGate {
+    /// What a command reports when it doesn't inject, `None` if it should.
+    /// Captured input counts as done, as it would have been injected.
+    fn skipped(self) -> Option<bool> {
+        match self {
+            CommandGate::Inject => None,
+            CommandGate::Captured => Some(true),
+            CommandGate::Rejected => Some(false),
+        }
+    }
+}
+
+/// Pass input a D-Bus command injects on behalf of a session, e.g. the keys
+/// of `Paste`, through the checks EIS input passes before injection, with
+/// pointer arbitration for `pointer` input. The command counts as one event
+/// against the rate limit and budget. While capturing, `events` are recorded
+/// instead of injected.
+fn command_gate(
+    state: &mut State,
+    session_id: SessionId,
+    pointer: bool,
+    events: impl FnOnce() -> Vec<String>,
+) -> CommandGate {
+    if state
+        .common
+        .eis_state
+        .as_ref()
+        .is_some_and(EisState::is_paused)
+    {
+        count_rejected(state, Rejection::Paused);
+        return CommandGate::Rejected;
+    }
+    if !rate_limit_allows(state, session_id) {
+        count_rejected(state, Rejection::RateLimited);
+        return CommandGate::Rejected;
+    }
+    if !budget_allows(state, session_id) {
+        count_rejected(state, Rejection::Budget);
+        return CommandGate::Rejected;
+    }
+    if !focus_guard_allows(state, session_id, None) {
+        count_rejected(state, Rejection::Unfocused);
+        return CommandGate::Rejected;
+    }
+    if pointer && !pointer_arbitration_allows(state, session_id) {
+        count_rejected(state, Rejection::Arbitration);
+        return CommandGate::Rejected;
+    }
+    let time = state.common.clock.now().as_millis();
+    match state.common.eis_state.as_mut() {
+        Some(eis_state) if eis_state.capture_command(session_id, time, events) => {
+            CommandGate::Captured
+        }
+        _ => CommandGate::Inject,
+    }
+}
+
+/// Check an event of the session against its focused-app guard, `release`
+/// being the key or button it releases.
+fn focus_guard_allows(state: &mut State, session_id: SessionId, release: Option<Release>) -> bool {
+    if !state
+        .common
+        .eis_state
+        .as_ref()
+        .and_then(|eis_state| eis_state.sessions.get(&session_id))
+        .is_some_and(|session| session.required_app_id.is_some())
+    {
+        return true;
+    }
+
+    // The seat the session's input goes to, which on multi-seat setups needn't
+    // be the last active one
+    let focused = target_seat(state, session_id, None).and_then(|seat| seat_focused_app_id(&seat));
+    state
+        .common
+        .eis_state
+        .as_mut()
+        .is_none_or(|eis_state| eis_state.focus_guard(session_id, focused.as_deref(), release))
+}
+
+/// The app_id of the toplevel holding keyboard focus on `seat`.
+fn seat_focused_app_id(seat: &Seat<State>) -> Option<String> {
+    match seat.get_keyboard()?.current_focus()? {
+        KeyboardFocusTarget::Element(mapped) => Some(mapped.active_window().app_id()),
+        KeyboardFocusTarget::Fullscreen(surface) => Some(surface.app_id()),
+        _ => None,
+    }
+}
+
+fn app_id_matches(required: &str, focused: Option<&str>) -> bool {
+    focused == Some(required)
+}
+
+/// The app_id and global geometry of the toplevel holding keyboard focus on
+/// `seat`, the seat a session drives.
+fn focused_window(shell: &Shell, seat: &Seat<State>) -> Option<(String, Rectangle<i32, Global>)> {
+    match seat.get_keyboard()?.current_focus()? {
+        KeyboardFocusTarget::Element(mapped) => {
+            let geometry = shell.element_geometry(&mapped)?;
+            Some((mapped.active_window().app_id(), geometry))
+        }
+        KeyboardFocusTarget::Fullscreen(surface) => {
+            let workspace = shell
+                .workspaces
+                .spaces()
+                .find(|workspace| workspace.get_fullscreen() == Some(&surface))?;
+            Some((surface.app_id(), workspace.output().geometry()))
+        }
+        _ => None,
+    }
+}
+
+/// Flatten a focused window for D-Bus, reporting an empty app_id and zero
+/// geometry if nothing is focused.
+fn focus_info(focused: Option<(String, Rectangle<i32, Global>)>) -> FocusInfo {
+    match focused {
+        Some((app_id, geometry)) => (
+            app_id,
+            geometry.loc.x,
+            geometry.loc.y,
+            geometry.size.w,
+            geometry.size.h,
+        ),
+        None => Default::default(),
+    }
+}
+
+/// `State::surface_under`, served from the EIS surface cache when it is enabled.
+fn surface_under_cached(
+    eis_state: Option<&mut EisState>,
+    config: &EisConfig,
+    position: smithay::utils::Point<f64, Global>,
+    output: &Output,
+    shell: &Shell,
+) -> Option<(PointerFocusTarget, smithay::utils::Point<f64, Global>)> {
+    let Some(eis_state) = eis_state.filter(|_| config.surface_cache) else {
+        return State::surface_under(position, output, shell);
+    };
+    let Some(scene) = scene_key(shell, output) else {
+        return State::surface_under(position, output, shell);
+    };
+
+    if let Some(under) = eis_state.surface_cache.lookup(
+        position,
+        &output.name(),
+        scene,
+        config.surface_cache_threshold,
+    ) {
+        return under;
+    }
+
+    let under = State::surface_under(position, output, shell);
+    let bounds = under
+        .as_ref()
+        .and_then(|(target, origin)| surface_bounds(target, *origin));
+    eis_state
+        .surface_cache
+        .store(position, output.name(), scene, under.clone(), bounds);
+    under
+}
+
+/// A fingerprint of what is stacked where on `output`: the active workspace,
+/// its windows, sticky windows, layer surfaces and override-redirect windows
+/// in stacking order with their geometry. Raising, moving, resizing or
+/// retiling anything changes it. `None` while nothing may be cached, during
+/// a workspace switch, zoom or session lock.
+fn scene_key(shell: &Shell, output: &Output) -> Option<u64> {
+    use smithay::wayland::seat::WaylandFocus;
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let (previous, workspace) = shell.workspaces.active(output)?;
+    if previous.is_some() || shell.session_lock.is_some() || shell.zoom_state().is_some() {
+        return None;
+    }
+    fn rect<Kind>(geometry: Option<Rectangle<i32, Kind>>) -> Option<(i32, i32, i32, i32)> {
+        geometry.map(|geometry| {
+            (
+                geometry.loc.x,
+                geometry.loc.y,
+                geometry.size.w,
+                geometry.size.h,
+            )
+        })
+    }
+
+    let mut hasher = DefaultHasher::new();
+    workspace.handle.hash(&mut hasher);
+    crate::utils::quirks::workspace_overview_is_open(output).hash(&mut hasher);
+    let sticky = shell
+        .workspaces
+        .sets
+        .get(output)
+        .map(|set| &set.sticky_layer);
+    let sticky = sticky.into_iter().flat_map(|layer| {
+        layer
+            .mapped()
+            .map(move |mapped| (mapped, rect(layer.element_geometry(mapped))))
+    });
+    let windows = workspace
+        .mapped()
+        .map(|mapped| (mapped, rect(workspace.element_geometry(mapped))));
+    for (mapped, geometry) in sticky.chain(windows) {
+        mapped.hash(&mut hasher);
+        // the tab a stack shows
+        let active = mapped.active_window();
+        active
+            .wl_surface()
+            .map(|surface| surface.id())
+            .hash(&mut hasher);
+        geometry.hash(&mut hasher);
+    }
+    if let Some(fullscreen) = &workspace.fullscreen {
+        let surface = fullscreen.surface.wl_surface().map(|surface| surface.id());
+        surface.hash(&mut hasher);
+        rect(workspace.fullscreen_geometry()).hash(&mut hasher);
+    }
+    let layers = layer_map_for_output(output);
+    for layer in layers.layers() {
+        layer.wl_surface().id().hash(&mut hasher);
+        rect(layers.layer_geometry(layer)).hash(&mut hasher);
+    }
+    for window in &shell.override_redirect_windows {
+        window.window_id().hash(&mut hasher);
+        rect(Some(window.geometry())).hash(&mut hasher);
+    }
+    Some(hasher.finish())
+}
+
+/// Where the surface `target` found with its top-left at `origin` lies, if it
+/// is a Wayland surface with a buffer.
+fn surface_bounds(
+    target: &PointerFocusTarget,
+    origin: Point<f64, Global>,
+) -> Option<Rectangle<f64, Global>> {
+    use smithay::{
+        backend::renderer::utils::with_renderer_surface_state, wayland::seat::WaylandFocus,
+    };
+
+    let surface = target.wl_surface()?;
+    let size = with_renderer_surface_state(&surface, |state| state.surface_size())??;
+    Some(Rectangle::new(origin, size.to_f64().as_global()))
+}
+
+/// The surface at the root of `surface`'s subsurface tree.
+fn root_surface(surface: &WlSurface) -> WlSurface {
+    let mut root = surface.clone();
+    while let Some(parent) = smithay::wayland::compositor::get_parent(&root) {
+        root = parent;
+    }
+    root
+}
+
+/// Whether switching from `previous` to `current` changes the compiled
+/// keymap, i.e. any of the RMLVO names differ.
+fn rmlvo_changed(previous: &XkbConfig, current: &XkbConfig) -> bool {
+    (
+        &previous.rules,
+        &previous.model,
+        &previous.layout,
+        &previous.variant,
+        &previous.options,
+    ) != (
+        &current.rules,
+        &current.model,
+        &current.layout,
+        &current.variant,
+        &current.options,
+    )
+}
+
+/// Suspend EIS injection while the compositor's session is inactive, e.g.
+/// after a VT switch, and resume it once the session is activated again.
+/// Input the sessions held is released on suspending, so nothing stays
+/// pressed meanwhile.
+pub fn session_active_changed(state: &mut State, active: bool) {
+    let Some(eis_state) = state.common.eis_state.as_mut() else {
+        return;
+    };
+    if !eis_state.set_paused(!active) || active {
+        return;
+    }
+    let sessions = eis_state.sessions.keys().copied().collect::<Vec<_>>();
+    for session_id in sessions {
+        if let Some(held) = state
+            .common
+            .eis_state
+            .as_mut()
+            .and_then(|eis_state| eis_state.take_held_input(session_id))
+        {
+            release_held(state, held);
+        }
+    }
+}
+
+/// Resend the keymap to every bound EIS keyboard after the compositor's XKB
+/// config changed from `previous`, so remote clients don't keep translating
+/// keycodes against a stale layout. Sessions with their own keymap keep it.
+pub fn xkb_config_changed(state: &mut State, previous: &XkbConfig) {
+    let current = state.common.config.xkb_config();
+    if !rmlvo_changed(previous, &current) {
+        return;
+    }
+    let Some(eis_state) = state.common.eis_state.as_ref() else {
+        return;
+    };
+    if !eis_state
+        .sessions
+        .values()
+        .any(|session| session.keyboard.is_some() && session.xkb_config.is_none())
+    {
+        return;
+    }
+    let (fd, size) = match prepare_xkb_keymap_fd(state, &current) {
+        Ok(keymap) => keymap,
+        Err(err) => {
+            warn!("{err}");
+            return;
+        }
+    };
+
+    let Some(eis_state) = state.common.eis_state.as_mut() else {
+        return;
+    };
+    for session in eis_state.sessions.values_mut() {
+        if session.xkb_config.is_none() {
+            resend_keymap(session, fd.as_fd(), size);
+        }
+    }
+}
+
+/// Give a session's keyboard its own keymap from the RMLVO `names`, or the
+/// compositor's again for an empty layout, resending it to a keyboard that
+/// is already bound. `None` if the session is unknown.
+fn set_session_keymap(
+    state: &mut State,
+    session_id: SessionId,
+    names: KeymapInfo,
+) -> Option<Result<(), EisError>> {
+    session_mut(state, session_id)?;
+    let default = state.common.config.xkb_config();
+    let xkb_config = session_xkb_config(&default, names);
+    // Compiling first keeps a session from being left with a broken keymap
+    let (fd, size) = match prepare_xkb_keymap_fd(state, xkb_config.as_ref().unwrap_or(&default)) {
+        Ok(keymap) => keymap,
+        Err(err) => return Some(Err(err)),
+    };
+    let session = session_mut(state, session_id)?;
+    info!(
+        session = session_id,
+        layout = xkb_config.as_ref().map(|conf| conf.layout.as_str()),
+        "Updated EIS session keymap"
+    );
+    session.xkb_config = xkb_config;
+    resend_keymap(session, fd.as_fd(), size);
+    Some(Ok(()))
+}
+
+/// The XKB config of a session keymap with the RMLVO `names`, where empty
+/// rules and model are the compositor's, or `None` for an empty layout.
+fn session_xkb_config(
+    default: &XkbConfig,
+    (rules, model, layout, variant, options): KeymapInfo,
+) -> Option<XkbConfig> {
+    if layout.is_empty() {
+        return None;
+    }
+    let or_default = |name: String, default: &String| {
+        if name.is_empty() {
+            default.clone()
+        } else {
+            name
+        }
+    };
+    Some(XkbConfig {
+        rules: or_default(rules, &default.rules),
+        model: or_default(model, &default.model),
+        layout,
+        variant,
+        options: (!options.is_empty()).then_some(options),
+        ..default.clone()
+    })
+}
+
+/// Send a new keymap to the session's keyboard, if it bound one.
+fn resend_keymap(session: &mut EisSession, fd: BorrowedFd<'_>, size: u32) {
+    let (Some(keyboard), Some(connection)) = (&session.keyboard, &session.connection) else {
+        return;
+    };
+    debug!(session = session.id, "Resending EIS keymap");
+    keyboard.keymap(eis::keyboard::KeymapType::Xkb, size, fd);
+    // the new keymap may lay out the modifiers differently
+    session.modifiers = ModifierFeedback::default();
+    if let Err(e) = connection.flush() {
+        warn!(session = session.id, "Failed to flush EIS keymap: {e}");
+    }
+}
+
+/// Prepare an XKB keymap as a sealed memfd for sending to EIS clients.
+///
+/// Compiles the keymap from the RMLVO names of `conf`, unless it is cached, writes
+/// it to a memfd with a null terminator, and seals the fd. Returns the fd and total
+/// size (including null terminator), or an error if keymap creation fails.
+fn prepare_xkb_keymap_fd(
+    state: &mut State,
+    conf: &XkbConfig,
+) -> Result<(std::os::fd::OwnedFd, u32), EisError> {
+    let keymap_string = match state.common.eis_state.as_mut() {
+        Some(eis_state) => eis_state.keymap_cache.get(conf)?,
+        None => compile_keymap(conf)?,
+    };
+    keymap_memfd(keymap_string.as_bytes())
+}
+
+/// The keymap text last compiled, with the XKB config it came from, so
+/// sending or reporting the same keymap again doesn't recompile it.
+#[derive(Debug, Default)]
+struct KeymapCache {
+    compiled: Option<(XkbConfig, String)>,
+}
+
+impl KeymapCache {
+    /// The keymap text for `conf`, compiled only if the cached one is for
+    /// another config.
+    fn get(&mut self, conf: &XkbConfig) -> Result<String, EisError> {
+        if let Some((cached, keymap)) = &self.compiled
+            && cached == conf
+        {
+            return Ok(keymap.clone());
+        }
+        let keymap = compile_keymap(conf)?;
+        self.compiled = Some((conf.clone(), keymap.clone()));
+        Ok(keymap)
+    }
+}
+
+/// Prepare the keymap of a keyboard being bound. A keyboard without a keymap
+/// can't type anything, so on failure it is left out of the `granted`
+/// capabilities rather than handed to the client.
+fn keyboard_keymap<T>(
+    session_id: SessionId,
+    granted: &mut Vec<DeviceCapability>,
+    prepare: impl FnOnce() -> Result<T, EisError>,
+) -> Option<T> {
+    match prepare() {
+        Ok(keymap) => Some(keymap),
+        Err(err) => {
+            error!(
+                session = session_id,
+                fd_exhaustion = err.is_fd_exhaustion(),
+                "Leaving out EIS keyboard without a keymap: {err}"
+            );
+            granted.retain(|capability| *capability != DeviceCapability::Keyboard);
+            None
+        }
+    }
+}
+
+/// Compile the keymap text for an XKB configuration.
+fn compile_keymap(conf: &XkbConfig) -> Result<String, EisError> {
+    use xkbcommon::xkb;
+
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    let keymap = xkb::Keymap::new_from_names(
+        &context,
+        &conf.rules,
+        &conf.model,
+        &conf.layout,
+        &conf.variant,
+        conf.options.clone(),
+        xkb::KEYMAP_COMPILE_NO_FLAGS,
+    )
+    .ok_or(EisError::Keymap)?;
+    Ok(keymap.get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1))
+}
+
+/// Put keymap text into a sealed memfd, returning it with its size.
+fn keymap_memfd(keymap_bytes: &[u8]) -> Result<(std::os::fd::OwnedFd, u32), EisError> {
+    use std::os::fd::FromRawFd;
+
+    let size = (keymap_bytes.len() + 1) as u32; // +1 for null terminator
+
+    // Create a sealed memfd for the keymap data
+    let name = c"eis-keymap";
+    let raw_fd =
+        unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING) };
+    if raw_fd < 0 {
+        return Err(EisError::Memfd(std::io::Error::last_os_error()));
+    }
+    let mut file = unsafe { std::fs::File::from_raw_fd(raw_fd) };
+
+    // Write keymap string + null terminator
+    use std::io::Write;
+    file.write_all(keymap_bytes)
+        .and_then(|()| file.write_all(&[0]))
+        .map_err(EisError::Memfd)?;
+
+    // Seal the memfd to prevent modification (best-effort)
+    unsafe {
+        libc::fcntl(
+            std::os::fd::AsRawFd::as_raw_fd(&file),
+            libc::F_ADD_SEALS,
+            libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL,
+        );
+    }
+
+    let owned_fd: std::os::fd::OwnedFd = file.into();
+    Ok((owned_fd, size))
+}
+
+/// Resolve the surface under a given position, acquiring and releasing the
+/// shell read lock before returning so callers can use `&mut State`.
+fn resolve_touch_target(
+    state: &State,
+    x: f64,
+    y: f64,
+) -> Option<(
+    <State as smithay::input::SeatHandler>::PointerFocus,
+    smithay::utils::Point<f64, smithay::utils::Logical>,
+)> {
+    let shell = state.common.shell.read();
+    let position = (x, y).into();
+    shell
+        .outputs()
+        .find(|output| output.geometry().to_f64().contains(position))
+        .and_then(|output| {
+            State::surface_under(position, output, &shell)
+                .map(|(target, pos)| (target, pos.as_logical()))
+        })
+}
+
+/// The window a touch-down should focus under `policy`, given the touched and
+/// the currently focused one. `None` leaves the focus alone.
+fn touch_focus_change<T: PartialEq>(
+    policy: TouchFocus,
+    touched: Option<T>,
+    focused: Option<&T>,
+) -> Option<T> {
+    match policy {
+        TouchFocus::Keep => None,
+        TouchFocus::Raise => touched.filter(|touched| Some(touched) != focused),
+    }
+}
+
+/// Raise and focus the window under a touch-down at a global position on
+/// `seat`, if the configured policy asks for it.
+fn focus_touched(state: &mut State, seat: &Seat<State>, position: Point<f64, Global>) {
+    let policy = state.common.config.cosmic_conf.eis_config.touch_focus;
+    if policy == TouchFocus::Keep {
+        return;
+    }
+    let touched = {
+        let shell = state.common.shell.read();
+        shell
+            .outputs()
+            .find(|output| output.geometry().to_f64().contains(position))
+            .and_then(|output| State::element_under(position, output, &shell, seat))
+    };
+    let focused = seat
+        .get_keyboard()
+        .and_then(|keyboard| keyboard.current_focus());
+    if let Some(target) = touch_focus_change(policy, touched, focused.as_ref()) {
+        let serial = SERIAL_COUNTER.next_serial();
+        Shell::set_focus(state, Some(&target), seat, Some(serial), false);
+    }
+}
+
+/// Pick the seat for an injected event among `(name, active output geometry)`
+/// pairs: the seat the session is pinned to, else the seat whose active
+/// output contains `position`, else the seat previously picked by position.
+/// `None` leaves the choice to the caller.
+fn pick_seat(
+    seats: &[(&str, Rectangle<i32, Global>)],
+    pinned: Option<&str>,
+    position: Option<Point<f64, Global>>,
+    previous: Option<&str>,
+) -> Option<usize> {
+    let named =
+        |name: Option<&str>| name.and_then(|name| seats.iter().position(|(seat, _)| *seat == name));
+    named(pinned)
+        .or_else(|| {
+            position.and_then(|position| {
+                seats
+                    .iter()
+                    .position(|(_, geometry)| geometry.to_f64().contains(position))
+            })
+        })
+        .or_else(|| named(previous))
+}
+
+/// Pick the seat for input nothing else routed among seat `names`, the last
+/// active seat first. `None` drops the input.
+fn fallback_seat(names: &[&str], policy: &SeatFallback) -> Option<usize> {
+    match policy {
+        SeatFallback::LastActive => (!names.is_empty()).then_some(0),
+        SeatFallback::Named(name) => names.iter().position(|seat| seat == name),
+        SeatFallback::Refuse => None,
+    }
+}
+
+/// The seats of the shell, the last active seat first. Empty while there are
+/// no seats, e.g. early during startup.
+fn seats_by_activity(state: &State) -> Vec<Seat<State>> {
+    let shell = state.common.shell.read();
+    let Some(last_active) = shell.seats.try_last_active().cloned() else {
+        return Vec::new();
+    };
+    std::iter::once(last_active.clone())
+        .chain(shell.seats.iter().filter(|s| **s != last_active).cloned())
+        .collect()
+}
+
+/// The compositor seat for input nothing else routed, if the configured
+/// fallback picks one.
+fn fallback(state: &mut State, seats: &[Seat<State>]) -> Option<Seat<State>> {
+    if seats.is_empty() {
+        warn_no_seat(state, "Dropping EIS input: there are no seats");
+        return None;
+    }
+    let names = seats.iter().map(|seat| seat.name()).collect::<Vec<_>>();
+    let policy = &state.common.config.cosmic_conf.eis_config.seat_fallback;
+    let seat = fallback_seat(&names, policy).map(|index| seats[index].clone());
+    if seat.is_none() {
+        trace!(?policy, "Dropping EIS input without a seat to route it to");
+        count_rejected(state, Rejection::NoSeat);
+    }
+    seat
+}
+
+/// Count an event or bind dropped for lack of any seat, with a sampled
+/// warning since every event would hit it.
+fn warn_no_seat(state: &mut State, message: &'static str) {
+    count_rejected(state, Rejection::NoSeat);
+    if let Some(suppressed) = state.common.eis_state.as_mut().and_then(|eis_state| {
+        eis_state
+            .rejection_log
+            .sample(Rejection::NoSeat, Instant::now())
+    }) {
+        warn!(suppressed, "{message}");
+    }
+}
+
+/// The compositor seats, by name, announced to a client: all of them, or
+/// only the seat a session was handed over for.
+fn announced_seats<T>(seats: Vec<(String, T)>, pinned: Option<&str>) -> Vec<(String, T)> {
+    seats
+        .into_iter()
+        .filter(|(name, _)| pinned.is_none_or(|pinned| pinned == name))
+        .collect()
+}
+
+/// Capabilities to leave off the EIS seat of a compositor seat that lacks a
+/// keyboard, pointer or touch handle.
+fn missing_seat_capabilities(keyboard: bool, pointer: bool, touch: bool) -> Vec<DeviceCapability> {
+    let mut missing = Vec::new();
+    if !keyboard {
+        missing.push(DeviceCapability::Keyboard);
+    }
+    if !pointer {
+        missing.extend([
+            DeviceCapability::Pointer,
+            DeviceCapability::PointerAbsolute,
+            DeviceCapability::Button,
+            DeviceCapability::Scroll,
+        ]);
+    }
+    if !touch {
+        missing.push(DeviceCapability::Touch);
+    }
+    missing
+}
+
+/// The compositor seat a bind pins its session to: the one behind the bound
+/// EIS seat, if it still exists, else none. `None` refuses the bind while
+/// there are no seats at all.
+fn bind_seat(announced: Option<&str>, available: &[&str]) -> Option<Option<String>> {
+    if available.is_empty() {
+        return None;
+    }
+    Some(
+        announced
+            .filter(|name| available.contains(name))
+            .map(str::to_string),
+    )
+}
+
+/// The seat a session's event at `position` goes to, if it or the configured
+/// fallback picks one. Takes the shell read lock, so it must not be held.
+fn target_seat(
+    state: &mut State,
+    session_id: SessionId,
+    position: Option<Point<f64, Global>>,
+) -> Option<Seat<State>> {
+    let (pinned, previous) = session_mut(state, session_id).map_or((None, None), |session| {
+        (session.pinned_seat.clone(), session.routed_seat.clone())
+    });
+    // The last active seat goes first so it wins ties on shared outputs
+    let seats = seats_by_activity(state);
+    let geometries = seats
+        .iter()
+        .map(|seat| (seat.name(), seat.active_output().geometry()))
+        .collect::<Vec<_>>();
+    let seat = match pick_seat(
+        &geometries,
+        pinned.as_deref(),
+        position,
+        previous.as_deref(),
+    ) {
+        Some(index) => seats[index].clone(),
+        None => fallback(state, &seats)?,
+    };
+    if position.is_some()
+        && let Some(session) = session_mut(state, session_id)
+    {
+        session.routed_seat = Some(seat.name().to_string());
+    }
+    Some(seat)
+}
+
+/// The compositor seat called `name`, or the configured fallback seat.
+fn seat_named(state: &mut State, name: Option<&str>) -> Option<Seat<State>> {
+    let seats = seats_by_activity(state);
+    match name.and_then(|name| seats.iter().find(|seat| seat.name() == name)) {
+        Some(seat) => Some(seat.clone()),
+        None => fallback(state, &seats),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Caller, CommandGate, ConnectionRejected, DeviceRegion, EditingCommand, EisError, EisState,
+        KeymapCache, Rejection, Release, SessionEvent, announced_seats, bind_seat, caller_allowed,
+        capture, check_connection_limit, clamp_motion, close_sequence, compile_keymap,
+        context_menu_sequence, coordinate_origin, delta_axis_frame, desktop_bounds, device_regions,
+        discrete_axis_frame, fallback_seat, fd_pressure, focus_info, frame_time, idle_inhibited,
+        keyboard_keymap, keymap_memfd, keys, limit_jump, missing_seat_capabilities, open_context,
+        output_relative_to_global, pick_seat, press_sequence, region_to_logical,
+        rejected_input_dropped, rmlvo_changed, round_coordinate, session_xkb_config,
+        snap_to_outputs, stop_axis_frame, touch_focus_change, warp_position,
+    };
+    use crate::state::State;
+    use cosmic_comp_config::{
+        XkbConfig,
+        eis::{
+            AbsoluteOrigin, ContextMenuInput, CoordinateRounding, EditingShortcuts, EisConfig,
+            PointerClamp, RejectedInput, SeatFallback, TouchFocus,
+        },
+    };
+    use reis::event::DeviceCapability;
+    use smithay::{
+        backend::input::{AxisSource, KeyState},
+        utils::{Point, Rectangle},
+    };
+    use std::{
+        io::{self, Read},
+        os::unix::net::UnixStream,
+        sync::atomic::Ordering,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_focus_guard() {
+        const KEY_A: u32 = 30;
+        const KEY_B: u32 = 48;
+        const BTN_LEFT: u32 = 0x110;
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state.add_connection(socket).unwrap();
+        let target = Some("org.example.Target");
+        let other = Some("org.example.Other");
+
+        // without a guard any app may have focus
+        assert!(eis_state.focus_guard(session, other, None));
+
+        // with the target focused, presses pass and are then held
+        let guarded = eis_state.sessions.get_mut(&session).unwrap();
+        guarded.required_app_id = target.map(str::to_string);
+        assert!(eis_state.focus_guard(session, target, None));
+        let guarded = eis_state.sessions.get_mut(&session).unwrap();
+        assert!(guarded.held_keys.update(KEY_A, true, false));
+        guarded.held_buttons.insert(BTN_LEFT);
+
+        // once focus moves away, or nothing is focused, presses are dropped
+        assert!(!eis_state.focus_guard(session, other, None));
+        assert!(!eis_state.focus_guard(session, None, None));
+        // and so are releases of what the session doesn't hold
+        assert!(!eis_state.focus_guard(session, other, Some(Release::Key(KEY_B))));
+        // but held keys and buttons can still be let go of
+        assert!(eis_state.focus_guard(session, other, Some(Release::Key(KEY_A))));
+        assert!(eis_state.focus_guard(session, None, Some(Release::Button(BTN_LEFT))));
+        assert_eq!(eis_state.sessions[&session].dropped_unfocused, 3);
+
+        // focus coming back lets presses through again
+        assert!(eis_state.focus_guard(session, target, None));
+        assert!(eis_state.disconnect_session(session));
+    }
+
+    #[test]
+    fn test_non_finite() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state.add_connection(socket).unwrap();
+        let rejected = |eis_state: &EisState| {
+            let snapshot = eis_state.metrics.snapshot(0);
+            let counts = eis_state.sessions[&session].info().injected;
+            (
+                snapshot["rejected_non_finite"],
+                counts["rejected_non_finite"],
+            )
+        };
+
+        assert_eq!(
+            eis_state.check_finite(session, "pointer motion", (1.5, -2.0)),
+            Ok(())
+        );
+        assert_eq!(rejected(&eis_state), (0, 0));
+
+        for values in [
+            (f64::NAN, 0.0),
+            (0.0, f64::INFINITY),
+            (f64::NEG_INFINITY, f64::NAN),
+        ] {
+            let checked = eis_state.check_finite(session, "pointer motion", values);
+            assert_eq!(checked, Err(Rejection::NonFinite));
+        }
+        assert_eq!(rejected(&eis_state), (3, 3));
+        assert!(eis_state.disconnect_session(session));
+    }
+
+    #[test]
+    fn test_round_coordinate() {
+        assert_eq!(round_coordinate(CoordinateRounding::None, 10.5), 10.5);
+        assert_eq!(round_coordinate(CoordinateRounding::Floor, 10.5), 10.0);
+        assert_eq!(round_coordinate(CoordinateRounding::Round, 10.5), 11.0);
+        assert_eq!(round_coordinate(CoordinateRounding::Ceil, 10.5), 11.0);
+        assert_eq!(round_coordinate(CoordinateRounding::Ceil, 10.0), 10.0);
+    }
+
+    #[test]
+    fn test_idle_inhibition() {
+        let window = Duration::from_secs(30);
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let start = Instant::now();
+        assert!(!eis_state.inhibits_idle());
+
+        // disabled
+        assert!(!eis_state.note_injected(start, Duration::ZERO));
+        assert!(!eis_state.inhibits_idle());
+
+        // injected input starts inhibiting idle, more input keeps it going
+        assert!(eis_state.note_injected(start, window));
+        assert!(eis_state.inhibits_idle());
+        let later = start + Duration::from_secs(29);
+        assert!(!eis_state.note_injected(later, window));
+        assert!(eis_state.inhibits_idle());
+
+        // it is released a window after the latest input, not the first
+        assert!(idle_inhibited(
+            eis_state.last_injected,
+            start + window,
+            window
+        ));
+        assert!(!idle_inhibited(
+            eis_state.last_injected,
+            later + window,
+            window
+        ));
+    }
+
+    #[test]
+    fn test_connection_limit() {
+        assert!(check_connection_limit(7, 8).is_ok());
+        let err = check_connection_limit(8, 8).unwrap_err();
+        assert!(matches!(err, ConnectionRejected::LimitReached(8)));
+        assert_eq!(err.to_string(), "EIS connection limit of 8 reached");
+    }
+
+    #[test]
+    fn test_context_failure() {
+        let (socket, mut client) = UnixStream::pair().unwrap();
+        let err = open_context(socket, |_socket| {
+            Err::<(), _>(io::Error::from_raw_os_error(libc::EMFILE))
+        })
+        .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EMFILE));
+        // neither the socket nor its duplicate is left open
+        assert_eq!(client.read(&mut [0; 1]).unwrap(), 0);
+
+        let (socket, mut client) = UnixStream::pair().unwrap();
+        let (_, probe) = open_context(socket, |_socket| Ok(())).unwrap();
+        assert!(probe.is_some());
+        client.set_nonblocking(true).unwrap();
+        assert!(client.read(&mut [0; 1]).is_err());
+
+        assert!(!fd_pressure(100, 1024));
+        assert!(fd_pressure(1010, 1024));
+    }
+
+    #[test]
+    fn test_session_pause() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (socket, _client) = UnixStream::pair().unwrap();
+        eis_state.add_connection(socket).unwrap();
+        assert!(!eis_state.is_paused());
+
+        // input is held off while the compositor's session is inactive
+        assert!(eis_state.set_paused(true));
+        assert!(eis_state.is_paused());
+        assert!(!eis_state.set_paused(true));
+
+        assert!(eis_state.set_paused(false));
+        assert!(!eis_state.is_paused());
+    }
+
+    #[test]
+    fn test_command_capture() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let shortcuts = EditingShortcuts::default();
+        let paste = keys::shortcut_sequence(EditingCommand::Paste.shortcut(&shortcuts), true);
+
+        // without capturing, Paste goes on to press its keys
+        assert!(!eis_state.capture_command(1, 100, || capture::keys(&paste)));
+        assert!(eis_state.capture.events().is_empty());
+
+        // while capturing its keys are recorded and none reach the keyboard
+        eis_state.set_capture(true);
+        assert!(eis_state.capture_command(1, 100, || capture::keys(&paste)));
+        let events = eis_state
+            .capture
+            .events()
+            .into_iter()
+            .map(|(_, _, event)| event)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                "key 29 pressed",
+                "key 47 pressed",
+                "key 47 released",
+                "key 29 released"
+            ]
+        );
+        assert_eq!(CommandGate::Captured.skipped(), Some(true));
+        assert_eq!(CommandGate::Rejected.skipped(), Some(false));
+        assert_eq!(CommandGate::Inject.skipped(), None);
+    }
+
+    #[test]
+    fn test_connection_for_seat() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let seats = ["seat0", "seat1"];
+
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state
+            .add_connection_for_seat(socket, "seat1", &seats)
+            .unwrap();
+        assert_eq!(
+            eis_state.sessions[&session].pinned_seat.as_deref(),
+            Some("seat1")
+        );
+
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let err = eis_state
+            .add_connection_for_seat(socket, "seat2", &seats)
+            .unwrap_err();
+        assert!(matches!(err, ConnectionRejected::UnknownSeat(ref seat) if seat == "seat2"));
+        assert_eq!(eis_state.active_connections.load(Ordering::Acquire), 1);
+    }
+
+    #[test]
+    fn test_configured_connection_limit() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 1).unwrap();
+        let (first, _first_client) = UnixStream::pair().unwrap();
+        let (second, _second_client) = UnixStream::pair().unwrap();
+
+        let first = eis_state.add_connection(first).unwrap();
+        let err = eis_state.add_connection(second).unwrap_err();
+        assert!(matches!(err, ConnectionRejected::LimitReached(1)));
+
+        // the slot frees up once the first session is gone
+        eis_state.remove_session(first);
+        let (third, _third_client) = UnixStream::pair().unwrap();
+        assert!(eis_state.add_connection(third).is_ok());
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 1).unwrap();
+        eis_state.set_idle_timeout(Duration::from_millis(50));
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let silent = eis_state.add_connection(socket).unwrap();
+        let connected = eis_state.sessions[&silent].last_request;
+        assert!(eis_state.sessions[&silent].idle_timer.is_some());
+
+        // checked early, the timer is rescheduled for the deadline
+        let deadline = connected + Duration::from_millis(50);
+        assert_eq!(
+            eis_state.check_idle(silent, connected + Duration::from_millis(20)),
+            Some(deadline)
+        );
+        // a request pushes the deadline out
+        let later = connected + Duration::from_millis(40);
+        eis_state.sessions.get_mut(&silent).unwrap().last_request = later;
+        assert_eq!(
+            eis_state.check_idle(silent, deadline),
+            Some(later + Duration::from_millis(50))
+        );
+
+        // silent for the whole window, the session is dropped
+        assert_eq!(
+            eis_state.check_idle(silent, later + Duration::from_millis(50)),
+            None
+        );
+        assert!(!eis_state.sessions.contains_key(&silent));
+        assert_eq!(eis_state.active_sessions(), 0);
+
+        // and its slot is free again
+        let (socket, _client) = UnixStream::pair().unwrap();
+        assert!(eis_state.add_connection(socket).is_ok());
+    }
+
+    #[test]
+    fn test_session_events() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        eis_state.set_session_events(tx);
+
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state.add_connection(socket).unwrap();
+        eis_state.open_session(session, "test-client".into());
+        eis_state.remove_session(session);
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![
+                SessionEvent::Opened {
+                    id: session,
+                    client_name: "test-client".into(),
+                },
+                SessionEvent::Closed(session),
+            ]
+        );
+
+        // a connection that never finished its handshake was never announced
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state.add_connection(socket).unwrap();
+        eis_state.remove_session(session);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_recent_errors() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state.add_connection(socket).unwrap();
+
+        // as the source does when the client breaks the protocol
+        eis_state.record_connection_error(session, "invalid object id 0");
+        eis_state.remove_session(session);
+
+        // the error outlives the session
+        let errors = eis_state.dead_letters.recent();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].ends_with(&format!("session {session}: invalid object id 0")));
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let clients = (0..3)
+            .map(|_| {
+                let (socket, client) = UnixStream::pair().unwrap();
+                let session = eis_state.add_connection(socket).unwrap();
+                assert!(eis_state.sessions[&session].token.is_some());
+                client
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(eis_state.active_connections.load(Ordering::Acquire), 3);
+
+        eis_state.shutdown();
+        assert_eq!(eis_state.active_connections.load(Ordering::Acquire), 0);
+        assert!(eis_state.sessions.is_empty());
+
+        // nothing new is accepted while going down
+        let (socket, _client) = UnixStream::pair().unwrap();
+        assert!(matches!(
+            eis_state.add_connection(socket),
+            Err(ConnectionRejected::Draining)
+        ));
+        drop(clients);
+    }
+
+    #[test]
+    fn test_configured_max_touch_id() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        assert!(eis_state.touch_id_allowed(256));
+
+        eis_state.set_max_touch_id(10);
+        assert!(eis_state.touch_id_allowed(10));
+        assert!(!eis_state.touch_id_allowed(11));
+
+        // nonsensical bounds are kept in range
+        eis_state.set_max_touch_id(0);
+        assert!(eis_state.touch_id_allowed(1));
+        assert!(!eis_state.touch_id_allowed(2));
+        eis_state.set_max_touch_id(u32::MAX);
+        assert!(!eis_state.touch_id_allowed(4097));
+    }
+
+    #[test]
+    fn test_connection_count() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let active = |eis_state: &EisState| eis_state.active_connections.load(Ordering::Acquire);
+
+        // connecting and disconnecting more often than the limit keeps working
+        for _ in 0..eis_state.max_connections * 2 {
+            let (socket, _client) = UnixStream::pair().unwrap();
+            let session = eis_state.add_connection(socket).unwrap();
+            assert_eq!(active(&eis_state), 1);
+            assert!(eis_state.remove_session(session).is_some());
+            assert_eq!(active(&eis_state), 0);
+        }
+
+        // removing a session twice doesn't underflow the count
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state.add_connection(socket).unwrap();
+        eis_state.remove_session(session);
+        assert!(eis_state.remove_session(session).is_none());
+        assert_eq!(active(&eis_state), 0);
+    }
+
+    #[test]
+    fn test_discrete_scroll() {
+        // one click down and half a click right in the same frame
+        let frame = discrete_axis_frame(0, 60, 120);
+        assert_eq!(frame.v120, Some((60, 120)));
+        assert_eq!(frame.axis, (7.5, 15.0));
+        assert_eq!(frame.source, Some(AxisSource::Wheel));
+
+        let frame = discrete_axis_frame(0, 0, -240);
+        assert_eq!(frame.v120, Some((0, -240)));
+        assert_eq!(frame.axis, (0.0, -30.0));
+    }
+
+    #[test]
+    fn test_close_sequence() {
+        let alt_f4 = EisConfig::default().close_shortcut;
+        let pressed = [(56, true), (62, true), (62, false), (56, false)];
+        assert_eq!(
+            close_sequence(&alt_f4, None, Some("org.gnome.Gedit")),
+            pressed
+        );
+        assert_eq!(
+            close_sequence(&alt_f4, Some("org.gnome.Gedit"), Some("org.gnome.Gedit")),
+            pressed
+        );
+
+        // another app's window, or none, is left alone
+        assert!(close_sequence(&alt_f4, Some("org.gnome.Gedit"), Some("firefox")).is_empty());
+        assert!(close_sequence(&alt_f4, Some("org.gnome.Gedit"), None).is_empty());
+        assert!(close_sequence(&alt_f4, None, None).is_empty());
+    }
+
+    #[test]
+    fn test_scroll_sources() {
+        let frame = delta_axis_frame(0, 0.0, 4.5);
+        assert_eq!(frame.source, Some(AxisSource::Continuous));
+        assert_eq!(frame.axis, (0.0, 4.5));
+        assert_eq!(
+            discrete_axis_frame(0, 0, 120).source,
+            Some(AxisSource::Wheel)
+        );
+        assert_eq!(
+            stop_axis_frame(0, false, true).source,
+            Some(AxisSource::Finger)
+        );
+    }
+
+    #[test]
+    fn test_scroll_stop() {
+        // ending a vertical scroll stops that axis only
+        let frame = stop_axis_frame(16, false, true);
+        assert_eq!(frame.stop, (false, true));
+        assert_eq!(frame.axis, (0.0, 0.0));
+        assert_eq!(frame.source, Some(AxisSource::Finger));
+        assert_eq!(frame.time, 16);
+
+        assert_eq!(stop_axis_frame(16, true, false).stop, (true, false));
+        assert_eq!(stop_axis_frame(16, true, true).stop, (true, true));
+    }
+
+    #[test]
+    fn test_focus_info() {
+        let geometry = Rectangle::new((1920, 40).into(), (800, 600).into());
+        assert_eq!(
+            focus_info(Some(("org.example.App".into(), geometry))),
+            ("org.example.App".to_string(), 1920, 40, 800, 600)
+        );
+        assert_eq!(focus_info(None), (String::new(), 0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_disconnect_session() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (first, _first_client) = UnixStream::pair().unwrap();
+        let (second, _second_client) = UnixStream::pair().unwrap();
+        let first = eis_state.add_connection(first).unwrap();
+        let second = eis_state.add_connection(second).unwrap();
+
+        let ids = |eis_state: &EisState| {
+            eis_state
+                .session_infos()
+                .into_iter()
+                .map(|info| info.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&eis_state), vec![first, second]);
+
+        assert!(eis_state.disconnect_session(first));
+        assert!(!eis_state.disconnect_session(first));
+        assert_eq!(ids(&eis_state), vec![second]);
+        assert_eq!(eis_state.active_connections.load(Ordering::Acquire), 1);
+    }
+
+    #[test]
+    fn test_orphaned_touches() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state.add_connection(socket).unwrap();
+
+        let now = Instant::now();
+        let first = eis_state.touch_slots.allocate(session, 0, now);
+        let second = eis_state.touch_slots.allocate(session, 1, now);
+
+        // both touches are handed back to be lifted when the session goes
+        let held = eis_state.take_held_input(session).unwrap();
+        assert_eq!(held.touches.len(), 2);
+        assert!(held.touches.contains(&first) && held.touches.contains(&second));
+        // so later motion or lifting finds them gone
+        assert_eq!(eis_state.touch_slots.refresh(session, 0, now), None);
+        assert_eq!(eis_state.touch_slots.release(session, 1), None);
+
+        // and nothing is left for a second teardown
+        assert!(eis_state.take_held_input(session).unwrap().is_empty());
+        assert!(eis_state.disconnect_session(session));
+        assert!(eis_state.take_held_input(session).is_none());
+    }
+
+    #[test]
+    fn test_active_sessions() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        assert_eq!(eis_state.active_sessions(), 0);
+
+        let (first, _first_client) = UnixStream::pair().unwrap();
+        let (second, _second_client) = UnixStream::pair().unwrap();
+        let first = eis_state.add_connection(first).unwrap();
+        let second = eis_state.add_connection(second).unwrap();
+        assert_eq!(eis_state.active_sessions(), 2);
+
+        eis_state.disconnect_session(first);
+        assert_eq!(eis_state.active_sessions(), 1);
+        eis_state.remove_session(second);
+        assert_eq!(eis_state.active_sessions(), 0);
+    }
+
+    #[test]
+    fn test_source_registration() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state.add_connection(socket).unwrap();
+        assert_eq!(eis_state.source_registered(session), Some(true));
+
+        assert!(eis_state.disconnect_session(session));
+        assert_eq!(eis_state.source_registered(session), None);
+    }
+
+    #[test]
+    fn test_held_buttons() {
+        const BTN_LEFT: u32 = 0x110;
+        const BTN_RIGHT: u32 = 0x111;
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (socket, _client) = UnixStream::pair().unwrap();
+        let session = eis_state.add_connection(socket).unwrap();
+
+        // pressing both and releasing one leaves the other to be released
+        let buttons = &mut eis_state.sessions.get_mut(&session).unwrap().held_buttons;
+        buttons.insert(BTN_LEFT);
+        buttons.insert(BTN_RIGHT);
+        buttons.remove(&BTN_RIGHT);
+        let held = eis_state.take_held_input(session).unwrap();
+        assert_eq!(held.buttons, vec![BTN_LEFT]);
+        assert!(!held.is_empty());
+
+        // and only once
+        assert!(eis_state.take_held_input(session).unwrap().is_empty());
+        assert!(eis_state.disconnect_session(session));
+    }
+
+    #[test]
+    fn test_output_relative_coordinates() {
+        // two side-by-side 1920x1080 outputs
+        let left = Rectangle::new((0, 0).into(), (1920, 1080).into());
+        let right = Rectangle::new((1920, 0).into(), (1920, 1080).into());
+        assert_eq!(
+            output_relative_to_global((0.0, 0.0), right.loc),
+            (1920.0, 0.0)
+        );
+        assert_eq!(
+            output_relative_to_global((100.5, 20.0), right.loc),
+            (2020.5, 20.0)
+        );
+        assert_eq!(
+            output_relative_to_global((100.5, 20.0), left.loc),
+            (100.5, 20.0)
+        );
+    }
+
+    #[test]
+    fn test_virtual_desktop_coordinates() {
+        // a left output placed at negative x, and one below the right output
+        let left = Rectangle::new((-1920, 0).into(), (1920, 1080).into());
+        let right = Rectangle::new((0, 0).into(), (2560, 1440).into());
+        let below = Rectangle::new((0, 1440).into(), (1920, 1080).into());
+        let bounds = desktop_bounds(&[left, right, below]).unwrap();
+        assert_eq!(
+            bounds,
+            Rectangle::new((-1920, 0).into(), (4480, 2520).into())
+        );
+
+        // the desktop's top-left is that of the union
+        let origin = coordinate_origin(
+            AbsoluteOrigin::VirtualDesktop,
+            Some(right),
+            &[left, right, below],
+        );
+        assert_eq!(origin, Point::from((-1920, 0)));
+        assert_eq!(
+            output_relative_to_global((0.0, 0.0), origin),
+            (-1920.0, 0.0)
+        );
+        assert_eq!(
+            output_relative_to_global((1920.0, 1440.0), origin),
+            (0.0, 1440.0)
+        );
+        assert_eq!(desktop_bounds(&[]), None);
+
+        // regions count from the same origin, so the left output is announced
+        // and each position is shifted by it only once
+        let outputs = [(left, 1.0), (right, 2.0)];
+        let regions = device_regions(&outputs, origin);
+        assert_eq!(regions.len(), 2);
+        assert_eq!((regions[0].x, regions[1].x), (0, 1920));
+        let global =
+            |position| output_relative_to_global(region_to_logical(&regions, position), origin);
+        assert_eq!(global((100.0, 100.0)), (-1820.0, 100.0));
+        assert_eq!(global((1920.0 + 200.0, 100.0)), (100.0, 50.0));
+
+        // per output, the target's top-left or else the global origin
+        let outputs = [left, right];
+        assert_eq!(
+            coordinate_origin(AbsoluteOrigin::PerOutput, Some(left), &outputs),
+            Point::from((-1920, 0))
+        );
+        assert_eq!(
+            coordinate_origin(AbsoluteOrigin::PerOutput, None, &outputs),
+            Point::from((0, 0))
+        );
+    }
+
+    #[test]
+    fn test_pointer_clamp() {
+        // two outputs with a 100px gap between them
+        let left = Rectangle::new((0, 0).into(), (100, 100).into());
+        let right = Rectangle::new((200, 0).into(), (100, 100).into());
+        let outputs = [left, right];
+
+        // per-output clamping keeps the pointer on the left output
+        let mut position = (90.0, 50.0).into();
+        for _ in 0..10 {
+            (_, position) = clamp_motion(
+                PointerClamp::PerOutput,
+                &outputs,
+                left,
+                position,
+                (20.0, 0.0),
+            );
+        }
+        assert_eq!(position, (99.0, 50.0).into());
+
+        // with the union the pointer crosses the gap, snapping to an edge
+        let mut tracked = (90.0, 50.0).into();
+        let mut pointer = Vec::new();
+        for _ in 0..8 {
+            let (next, position) =
+                clamp_motion(PointerClamp::Union, &outputs, left, tracked, (20.0, 0.0));
+            tracked = next;
+            pointer.push(position.x);
+        }
+        // within the gap the pointer sits on the nearest edge
+        assert_eq!(
+            pointer,
+            [99.0, 99.0, 200.0, 200.0, 200.0, 210.0, 230.0, 250.0]
+        );
+
+        // and the bounding box still stops it at the far edge
+        let (_, position) =
+            clamp_motion(PointerClamp::Union, &outputs, left, tracked, (500.0, 500.0));
+        assert_eq!(position, (299.0, 99.0).into());
+    }
+
+    #[test]
+    fn test_absolute_jump_limit() {
+        // a jump across the layout is cut down to the maximum distance
+        let (x, y) = limit_jump((0.0, 0.0), (3000.0, 4000.0), 50.0).unwrap();
+        assert!((x - 30.0).abs() < 1e-9 && (y - 40.0).abs() < 1e-9);
+        assert!(((x * x + y * y).sqrt() - 50.0).abs() < 1e-9);
+
+        // moves within the limit pass unchanged
+        assert_eq!(limit_jump((100.0, 100.0), (110.0, 90.0), 50.0), None);
+    }
+
+    #[test]
+    fn test_frame_time() {
+        // the client's frame time is used for its events
+        assert_eq!(frame_time(41_990_500, 42_000), 41_990);
+        assert_eq!(frame_time(42_000_000, 42_000), 42_000);
+
+        // timestamps from the future or long ago fall back to the clock
+        assert_eq!(frame_time(42_500_000, 42_000), 42_000);
+        assert_eq!(frame_time(1_000_000, 42_000), 42_000);
+
+        // across the wrap of the millisecond clock
+        let wrapped = (u64::from(u32::MAX) + 1 + 10) * 1000;
+        assert_eq!(frame_time(u64::from(u32::MAX - 5) * 1000, 10), u32::MAX - 5);
+        assert_eq!(frame_time(wrapped, 20), 10);
+    }
+
+    #[test]
+    fn test_drain_mode() {
+        let event_loop = calloop::EventLoop::<State>::try_new().unwrap();
+        let mut eis_state = EisState::new(&event_loop.handle(), 8).unwrap();
+        let (existing, _existing_client) = UnixStream::pair().unwrap();
+        let existing = eis_state.add_connection(existing).unwrap();
+
+        eis_state.set_draining(true);
+        let (socket, _client) = UnixStream::pair().unwrap();
+        assert!(matches!(
+            eis_state.add_connection(socket),
+            Err(ConnectionRejected::Draining)
+        ));
+        // the existing session is left alone
+        assert_eq!(eis_state.session_infos().len(), 1);
+        assert!(eis_state.sessions.contains_key(&existing));
+
+        eis_state.set_draining(false);
+        let (socket, _client) = UnixStream::pair().unwrap();
+        assert!(eis_state.add_connection(socket).is_ok());
+    }
+
+    #[test]
+    fn test_caller_allowlist() {
+        let helper = Caller { uid: Some(1000) };
+        // by default only the portal, which is checked by name, may connect
+        let mut config = EisConfig::default();
+        assert!(!caller_allowed(&config, &helper));
+
+        config.allowed_socket_uids = vec![1000];
+        assert!(caller_allowed(&config, &helper));
+        let other_user = Caller { uid: Some(1001) };
+        assert!(!caller_allowed(&config, &other_user));
+        assert!(!caller_allowed(&config, &Caller::default()));
+    }
+
+    #[test]
+    fn test_announced_seats() {
+        let seats = vec![
+            ("seat0".to_string(), ()),
+            ("seat1".to_string(), ()),
+            ("seat2".to_string(), ()),
+        ];
+        // one EIS seat per compositor seat
+        assert_eq!(announced_seats(seats.clone(), None).len(), 3);
+        // or just the seat a session was handed over for
+        assert_eq!(
+            announced_seats(seats.clone(), Some("seat1")),
+            vec![("seat1".to_string(), ())]
+        );
+        assert!(announced_seats(seats, Some("seat9")).is_empty());
+
+        // a seat without touch announces none
+        assert_eq!(
+            missing_seat_capabilities(true, true, false),
+            vec![DeviceCapability::Touch]
+        );
+        assert_eq!(missing_seat_capabilities(true, false, true).len(), 4);
+        assert!(missing_seat_capabilities(true, true, true).is_empty());
+    }
+
+    #[test]
+    fn test_device_regions() {
+        let outputs = [
+            (Rectangle::new((0, 0).into(), (1920, 1080).into()), 1.0),
+            (Rectangle::new((1920, 0).into(), (1280, 720).into()), 1.5),
+        ];
+        let regions = device_regions(&outputs, (0, 0).into());
+        assert_eq!(regions.len(), outputs.len());
+        assert_eq!(
+            regions[1],
+            DeviceRegion {
+                x: 1920,
+                y: 0,
+                width: 1280,
+                height: 720,
+                scale: 1.5,
+                logical: (1920, 0),
+            }
+        );
+
+        // a scaled output pushes the regions after it out of its way
+        let outputs = [
+            (Rectangle::new((0, 0).into(), (1920, 1080).into()), 2.0),
+            (Rectangle::new((1920, 0).into(), (1920, 1080).into()), 1.0),
+            (Rectangle::new((0, 1080).into(), (1920, 1080).into()), 1.0),
+        ];
+        let regions = device_regions(&outputs, (0, 0).into());
+        let offsets = regions
+            .iter()
+            .map(|region| (region.x, region.y))
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [(0, 0), (3840, 0), (0, 2160)]);
+
+        // a session targeting one output addresses it from the origin
+        let regions = device_regions(&outputs[1..], outputs[1].0.loc);
+        assert_eq!((regions[0].x, regions[0].y), (0, 0));
+
+        // negative offsets can't be expressed in EIS
+        let above = [(Rectangle::new((0, -1080).into(), (1920, 1080).into()), 1.0)];
+        assert!(device_regions(&above, (0, 0).into()).is_empty());
+    }
+
+    #[test]
+    fn test_region_scale() {
+        let outputs = [
+            (Rectangle::new((0, 0).into(), (1920, 1080).into()), 1.0),
+            (Rectangle::new((1920, 0).into(), (1280, 720).into()), 2.0),
+        ];
+        let regions = device_regions(&outputs, (0, 0).into());
+        assert_eq!(region_to_logical(&regions, (100., 100.)), (100., 100.));
+        // a client coordinate on the 2.0-scaled output is halved from its origin
+        assert_eq!(region_to_logical(&regions, (2120., 100.)), (2020., 50.));
+        assert_eq!(
+            region_to_logical(&regions, (1920. + 2559., 1439.)),
+            (1920. + 1279.5, 719.5)
+        );
+        // outside every region the position is left alone
+        assert_eq!(region_to_logical(&regions, (100., 1500.)), (100., 1500.));
+
+        // with the scaled output first, all of its neighbour stays reachable
+        let outputs = [
+            (Rectangle::new((0, 0).into(), (1920, 1080).into()), 2.0),
+            (Rectangle::new((1920, 0).into(), (1920, 1080).into()), 1.0),
+        ];
+        let regions = device_regions(&outputs, (0, 0).into());
+        assert_eq!(region_to_logical(&regions, (3000., 100.)), (1500., 50.));
+        assert_eq!(region_to_logical(&regions, (3940., 100.)), (2020., 100.));
+
+        // an output-relative region starts at the origin
+        let regions = device_regions(&outputs[1..], outputs[1].0.loc);
+        assert_eq!(region_to_logical(&regions, (200., 100.)), (100., 50.));
+    }
+
+    #[test]
+    fn test_keymap_resend() {
+        let us = XkbConfig {
+            layout: "us".into(),
+            ..Default::default()
+        };
+        // switching layout needs a new keymap
+        let de = XkbConfig {
+            layout: "de".into(),
+            ..us.clone()
+        };
+        assert!(rmlvo_changed(&us, &de));
+
+        // repeat settings don't affect the keymap
+        let faster = XkbConfig {
+            repeat_rate: us.repeat_rate * 2,
+            ..us.clone()
+        };
+        assert!(!rmlvo_changed(&us, &faster));
+    }
+
+    #[test]
+    fn test_multi_seat_routing() {
+        let left = Rectangle::new((0, 0).into(), (1920, 1080).into());
+        let right = Rectangle::new((1920, 0).into(), (1920, 1080).into());
+        let seats = [("seat0", left), ("seat1", right)];
+
+        // absolute positions go to the seat whose active output holds them
+        assert_eq!(
+            pick_seat(&seats, None, Some((100., 100.).into()), None),
+            Some(0)
+        );
+        assert_eq!(
+            pick_seat(&seats, None, Some((2000., 100.).into()), None),
+            Some(1)
+        );
+
+        // events without a position stay with the seat last picked
+        assert_eq!(pick_seat(&seats, None, None, Some("seat1")), Some(1));
+        assert_eq!(
+            pick_seat(&seats, None, Some((-5., 0.).into()), Some("seat1")),
+            Some(1)
+        );
+        assert_eq!(pick_seat(&seats, None, None, None), None);
+
+        // a pinned seat wins, unless it went away
+        assert_eq!(
+            pick_seat(&seats, Some("seat1"), Some((100., 100.).into()), None),
+            Some(1)
+        );
+        assert_eq!(
+            pick_seat(&seats, Some("gone"), Some((100., 100.).into()), None),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_focus_seat() {
+        // seat0 is the last active seat, a session bound to seat1 reads the
+        // focus of seat1 rather than whatever seat0 has focused
+        let geometry = Rectangle::new((0, 0).into(), (1920, 1080).into());
+        let seats = [("seat0", geometry), ("seat1", geometry)];
+        assert_eq!(pick_seat(&seats, Some("seat1"), None, None), Some(1));
+        assert_eq!(pick_seat(&seats, None, None, Some("seat1")), Some(1));
+
+        // a session not routed anywhere yet goes by the fallback
+        assert_eq!(pick_seat(&seats, None, None, None), None);
+        assert_eq!(
+            fallback_seat(&["seat0", "seat1"], &SeatFallback::LastActive),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_seat_fallback() {
+        let seats = ["seat0", "seat1"];
+        assert_eq!(fallback_seat(&seats, &SeatFallback::LastActive), Some(0));
+        assert_eq!(
+            fallback_seat(&seats, &SeatFallback::Named("seat1".into())),
+            Some(1)
+        );
+        // a named seat that doesn't exist drops the input rather than guessing
+        assert_eq!(
+            fallback_seat(&seats, &SeatFallback::Named("gone".into())),
+            None
+        );
+        assert_eq!(fallback_seat(&seats, &SeatFallback::Refuse), None);
+        assert_eq!(fallback_seat(&[], &SeatFallback::LastActive), None);
+    }
+
+    #[test]
+    fn test_context_menu_sequence() {
+        const KEY_COMPOSE: u32 = 127;
+        const BTN_RIGHT: u32 = 0x111;
+        let pressed = |input| {
+            let mut pressed = Vec::new();
+            press_sequence(context_menu_sequence(input), |key, key_state| {
+                pressed.push((key, key_state));
+            });
+            pressed
+        };
+
+        // the Menu key goes down and comes back up
+        assert_eq!(
+            pressed(ContextMenuInput::MenuKey),
+            [
+                (KEY_COMPOSE, KeyState::Pressed),
+                (KEY_COMPOSE, KeyState::Released)
+            ]
+        );
+        // and so does the right button, leaving nothing held
+        assert_eq!(
+            pressed(ContextMenuInput::RightClick),
+            [
+                (BTN_RIGHT, KeyState::Pressed),
+                (BTN_RIGHT, KeyState::Released)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_seats() {
+        // injection finds no seat to go to, whatever the fallback
+        assert_eq!(
+            pick_seat(&[], Some("seat0"), Some((0., 0.).into()), None),
+            None
+        );
+        for policy in [
+            SeatFallback::LastActive,
+            SeatFallback::Named("seat0".into()),
+            SeatFallback::Refuse,
+        ] {
+            assert_eq!(fallback_seat(&[], &policy), None);
+        }
+
+        // binds are refused until a seat shows up
+        assert_eq!(bind_seat(Some("seat0"), &[]), None);
+        assert_eq!(bind_seat(None, &[]), None);
+        assert_eq!(
+            bind_seat(Some("seat0"), &["seat0"]),
+            Some(Some("seat0".to_string()))
+        );
+        // a seat gone since it was announced leaves the session unpinned
+        assert_eq!(bind_seat(Some("seat1"), &["seat0"]), Some(None));
+    }
+
+    #[test]
+    fn test_touch_focus() {
+        let (background, focused) = ("background", "focused");
+        // touching a background window only moves focus when raising
+        assert_eq!(
+            touch_focus_change(TouchFocus::Raise, Some(background), Some(&focused)),
+            Some(background)
+        );
+        assert_eq!(
+            touch_focus_change(TouchFocus::Keep, Some(background), Some(&focused)),
+            None
+        );
+        // the focused window or nothing at all needs no change
+        assert_eq!(
+            touch_focus_change(TouchFocus::Raise, Some(focused), Some(&focused)),
+            None
+        );
+        assert_eq!(
+            touch_focus_change(TouchFocus::Raise, None, Some(&focused)),
+            None
+        );
+        assert_eq!(
+            touch_focus_change(TouchFocus::Raise, Some(background), None),
+            Some(background)
+        );
+    }
+
+    #[test]
+    fn test_warp_position() {
+        let origin = Rectangle::new((0, 0).into(), (1920, 1080).into());
+        let right = Rectangle::new((1920, 0).into(), (1280, 1024).into());
+        let outputs = [origin, right];
+
+        // a position on the layout is kept exactly
+        assert_eq!(
+            warp_position(&outputs, (2500.5, 300.25).into()),
+            Some((2500.5, 300.25).into())
+        );
+        // a position below the shorter output lands on its bottom edge
+        assert_eq!(
+            warp_position(&outputs, (2500., 1050.).into()),
+            Some((2500., 1023.).into())
+        );
+        assert_eq!(warp_position(&outputs, (f64::NAN, 0.).into()), None);
+        assert_eq!(warp_position(&[], (0., 0.).into()), None);
+    }
+
+    #[test]
+    fn test_touch_clamp() {
+        let origin = Rectangle::new((0, 0).into(), (1920, 1080).into());
+        let right = Rectangle::new((1920, 0).into(), (1280, 1024).into());
+        let outputs = [origin, right];
+
+        // off the top left corner lands on the origin output's corner
+        assert_eq!(
+            snap_to_outputs(&outputs, (-5., -5.).into()),
+            Some((0., 0.).into())
+        );
+        // below the shorter output snaps up onto its bottom edge
+        assert_eq!(
+            snap_to_outputs(&outputs, (2000., 1050.).into()),
+            Some((2000., 1023.).into())
+        );
+        // touches on an output are left alone
+        assert_eq!(
+            snap_to_outputs(&outputs, (100., 100.).into()),
+            Some((100., 100.).into())
+        );
+        assert_eq!(snap_to_outputs(&[], (100., 100.).into()), None);
+    }
+
+    #[test]
+    fn test_rejected_input() {
+        // over an input-disabled overlay
+        let covered = || true;
+        assert!(rejected_input_dropped(RejectedInput::Drop, covered));
+        assert!(!rejected_input_dropped(RejectedInput::FallThrough, covered));
+
+        // surfaces accepting input get the event either way
+        assert!(!rejected_input_dropped(RejectedInput::Drop, || false));
+
+        // falling through never looks at the surfaces
+        assert!(!rejected_input_dropped(RejectedInput::FallThrough, || {
+            panic!("checked surfaces needlessly")
+        }));
+    }
+
+    #[test]
+    fn test_keyboard_keymap_failure() {
+        let granted = || vec![DeviceCapability::Keyboard, DeviceCapability::Pointer];
+        let out_of_fds = || EisError::Memfd(io::Error::from_raw_os_error(libc::EMFILE));
+
+        // a memfd that can't be created leaves the keyboard out
+        let mut capabilities = granted();
+        let keymap = keyboard_keymap(1, &mut capabilities, || Err::<(), _>(out_of_fds()));
+        assert_eq!(keymap, None);
+        assert_eq!(capabilities, vec![DeviceCapability::Pointer]);
+
+        // and so does a keymap that doesn't compile
+        let mut capabilities = granted();
+        let keymap = keyboard_keymap(1, &mut capabilities, || Err::<(), _>(EisError::Keymap));
+        assert_eq!(keymap, None);
+        assert_eq!(capabilities, vec![DeviceCapability::Pointer]);
+
+        let mut capabilities = granted();
+        let keymap = keyboard_keymap(1, &mut capabilities, || Ok(7));
+        assert_eq!(keymap, Some(7));
+        assert_eq!(capabilities, granted());
+    }
+
+    #[test]
+    fn test_session_keymap_override() {
+        let compositor = XkbConfig {
+            rules: "evdev".into(),
+            model: "pc105".into(),
+            layout: "us".into(),
+            variant: "dvorak".into(),
+            ..XkbConfig::default()
+        };
+        let names = |layout: &str, variant: &str| {
+            (
+                String::new(),
+                String::new(),
+                layout.to_string(),
+                variant.to_string(),
+                String::new(),
+            )
+        };
+
+        // rules and model left empty are the compositor's, the rest is the
+        // session's own
+        let de = session_xkb_config(&compositor, names("de", "")).unwrap();
+        assert_eq!((de.rules.as_str(), de.model.as_str()), ("evdev", "pc105"));
+        assert_eq!((de.layout.as_str(), de.variant.as_str()), ("de", ""));
+        assert_eq!(de.options, None);
+
+        // an empty layout goes back to the compositor's keymap
+        assert_eq!(session_xkb_config(&compositor, names("", "")), None);
+    }
+
+    #[test]
+    fn test_keymap_cache() {
+        let us = XkbConfig {
+            rules: "evdev".into(),
+            layout: "us".into(),
+            ..XkbConfig::default()
+        };
+        let mut cache = KeymapCache::default();
+        let keymap = cache.get(&us).unwrap();
+        assert!(keymap.starts_with("xkb_keymap"));
+        assert!(keymap.contains("+us"));
+        // asking again hands out the same text
+        assert_eq!(cache.get(&us).unwrap(), keymap);
+
+        // a changed config is compiled anew
+        let de = XkbConfig {
+            layout: "de".into(),
+            ..us.clone()
+        };
+        let keymap = cache.get(&de).unwrap();
+        assert!(keymap.contains("+de"));
+        assert!(!keymap.contains("+us"));
+    }
+
+    #[test]
+    fn test_setup_errors() {
+        // a refused connection keeps its reason
+        let err = EisError::from(check_connection_limit(8, 8).unwrap_err());
+        assert!(matches!(
+            err,
+            EisError::Connection(ConnectionRejected::LimitReached(8))
+        ));
+
+        let bogus = XkbConfig {
+            layout: "no-such-layout".into(),
+            ..XkbConfig::default()
+        };
+        assert!(matches!(compile_keymap(&bogus), Err(EisError::Keymap)));
+        assert!(matches!(
+            KeymapCache::default().get(&bogus),
+            Err(EisError::Keymap)
+        ));
+
+        let (fd, size) = keymap_memfd(b"xkb_keymap {};").unwrap();
+        assert_eq!(size, 15);
+        assert_eq!(std::fs::File::from(fd).metadata().unwrap().len(), 15);
+
+        // callers on anyhow keep working
+        let err: anyhow::Error = EisError::Keymap.into();
+        assert!(err.downcast_ref::<EisError>().is_some());
+    }
+}
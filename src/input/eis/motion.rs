@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Batching of pointer motion up to the client's frame boundary.
+
+use std::time::{Duration, Instant};
+
+use cosmic_comp_config::eis::MotionPriority;
+
+/// Delta at which acceleration doubles the motion for an acceleration of 1.
+const ACCELERATION_REFERENCE: f64 = 10.0;
+
+/// Scale a relative motion event by `scale`, and by `acceleration` with the
+/// size of the delta, similar to libinput's adaptive profile. Deltas stay
+/// fractional so many small motions add up without loss.
+pub fn scale_motion(dx: f64, dy: f64, scale: f64, acceleration: f64) -> (f64, f64) {
+    let speed = dx.hypot(dy) / ACCELERATION_REFERENCE;
+    let factor = scale * (1.0 + acceleration.max(0.0) * speed);
+    if !factor.is_finite() {
+        return (dx, dy);
+    }
+    (dx * factor, dy * factor)
+}
+
+/// Sub-pixel remainder of relative motion moved in whole pixels, so many
+/// small deltas add up instead of each being truncated away.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FractionalMotion {
+    x: f64,
+    y: f64,
+}
+
+impl FractionalMotion {
+    /// Add a delta and take the whole pixels accumulated so far, keeping the
+    /// fraction for later motion.
+    pub fn take_whole(&mut self, dx: f64, dy: f64) -> (f64, f64) {
+        let whole = |value: f64| {
+            // sums like ten times 0.3 fall just short of the whole pixel
+            let rounded = value.round();
+            if (value - rounded).abs() < 1e-9 {
+                rounded
+            } else {
+                value.trunc()
+            }
+        };
+        let (x, y) = (self.x + dx, self.y + dy);
+        let (whole_x, whole_y) = (whole(x), whole(y));
+        self.x = x - whole_x;
+        self.y = y - whole_y;
+        (whole_x, whole_y)
+    }
+}
+
+/// Pointer motion waiting for the end of the client's frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    /// Summed relative deltas.
+    Relative(f64, f64),
+    /// The latest absolute position, in global coordinates.
+    Absolute(f64, f64),
+}
+
+/// Accumulates the motion of one EIS frame so it is injected as a single
+/// pointer motion and frame.
+#[derive(Debug, Default)]
+pub struct MotionBatch {
+    pending: Option<Motion>,
+    /// When the pending motion started to be batched.
+    started: Option<Instant>,
+}
+
+impl MotionBatch {
+    /// Add motion to the batch. Returns the pending motion if it is of the
+    /// other kind and has to be injected first. Unless `priority` applies
+    /// both kinds in order, the kind it prefers replaces or drops the other.
+    pub fn push(&mut self, motion: Motion, priority: MotionPriority) -> Option<Motion> {
+        match (self.pending, motion, priority) {
+            (Some(Motion::Absolute(..)), Motion::Relative(..), MotionPriority::Absolute)
+            | (Some(Motion::Relative(..)), Motion::Absolute(..), MotionPriority::Relative) => None,
+            (Some(Motion::Relative(..)), Motion::Absolute(..), MotionPriority::Absolute)
+            | (Some(Motion::Absolute(..)), Motion::Relative(..), MotionPriority::Relative) => {
+                self.pending = Some(motion);
+                None
+            }
+
+            (Some(Motion::Relative(x, y)), Motion::Relative(dx, dy), _) => {
+                self.pending = Some(Motion::Relative(x + dx, y + dy));
+                None
+            }
+            (Some(Motion::Absolute(..)), Motion::Absolute(..), _) => {
+                self.pending = Some(motion);
+                None
+            }
+            (None, ..) => {
+                self.pending = Some(motion);
+                self.started = Some(Instant::now());
+                None
+            }
+            (previous, ..) => {
+                self.pending = Some(motion);
+                self.started = Some(Instant::now());
+                previous
+            }
+        }
+    }
+
+    pub fn take(&mut self) -> Option<Motion> {
+        self.started = None;
+        self.pending.take()
+    }
+
+    /// When the pending motion has to be injected without waiting any longer
+    /// for the frame, if batching may last at most `window`.
+    pub fn deadline(&self, window: Duration) -> Option<Instant> {
+        self.started.map(|started| started + window)
+    }
+
+    /// Whether the pending motion waited `window` by `now`.
+    pub fn is_due(&self, now: Instant, window: Duration) -> bool {
+        self.deadline(window)
+            .is_some_and(|deadline| deadline <= now)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FractionalMotion, Motion, MotionBatch, scale_motion};
+    use cosmic_comp_config::eis::MotionPriority;
+    use std::time::Duration;
+
+    #[test]
+    fn test_motion_batch() {
+        let mut batch = MotionBatch::default();
+        assert!(batch.is_empty());
+
+        // three motions within a frame become one
+        for (dx, dy) in [(1.0, 2.0), (3.0, -1.0), (0.5, 0.5)] {
+            assert_eq!(
+                batch.push(Motion::Relative(dx, dy), MotionPriority::InOrder),
+                None
+            );
+        }
+        assert_eq!(batch.take(), Some(Motion::Relative(4.5, 1.5)));
+        assert_eq!(batch.take(), None);
+
+        // absolute positions replace each other
+        batch.push(Motion::Absolute(10.0, 10.0), MotionPriority::InOrder);
+        batch.push(Motion::Absolute(20.0, 30.0), MotionPriority::InOrder);
+        // switching kinds hands back what came before
+        assert_eq!(
+            batch.push(Motion::Relative(1.0, 1.0), MotionPriority::InOrder),
+            Some(Motion::Absolute(20.0, 30.0))
+        );
+        assert_eq!(batch.take(), Some(Motion::Relative(1.0, 1.0)));
+    }
+
+    #[test]
+    fn test_fractional_motion() {
+        let mut remainder = FractionalMotion::default();
+        let (x, y) = (0..10).fold((0.0, 0.0), |(x, y), _| {
+            let (dx, dy) = remainder.take_whole(0.3, -0.3);
+            (x + dx, y + dy)
+        });
+        assert_eq!((x, y), (3.0, -3.0));
+        assert_eq!(remainder.take_whole(0.0, 0.0), (0.0, 0.0));
+
+        // the fraction carries over to the next motion
+        assert_eq!(remainder.take_whole(1.6, 0.0), (1.0, 0.0));
+        assert_eq!(remainder.take_whole(0.5, 0.0), (1.0, 0.0));
+    }
+
+    #[test]
+    fn test_motion_priority() {
+        let frame = |priority| {
+            let mut batch = MotionBatch::default();
+            let injected = [
+                Motion::Relative(5.0, 0.0),
+                Motion::Absolute(100.0, 100.0),
+                Motion::Relative(1.0, 2.0),
+            ]
+            .into_iter()
+            .filter_map(|motion| batch.push(motion, priority))
+            .collect::<Vec<_>>();
+            (injected, batch.take())
+        };
+
+        assert_eq!(
+            frame(MotionPriority::InOrder),
+            (
+                vec![Motion::Relative(5.0, 0.0), Motion::Absolute(100.0, 100.0)],
+                Some(Motion::Relative(1.0, 2.0))
+            )
+        );
+        assert_eq!(
+            frame(MotionPriority::Absolute),
+            (vec![], Some(Motion::Absolute(100.0, 100.0)))
+        );
+        assert_eq!(
+            frame(MotionPriority::Relative),
+            (vec![], Some(Motion::Relative(6.0, 2.0)))
+        );
+    }
+
+    #[test]
+    fn test_motion_window() {
+        let window = Duration::from_millis(8);
+        let mut batch = MotionBatch::default();
+        assert_eq!(batch.deadline(window), None);
+
+        // further motion doesn't push the deadline out
+        batch.push(Motion::Relative(1.0, 0.0), MotionPriority::InOrder);
+        let deadline = batch.deadline(window).unwrap();
+        batch.push(Motion::Relative(1.0, 0.0), MotionPriority::InOrder);
+        assert_eq!(batch.deadline(window), Some(deadline));
+
+        // and once the window expired the motion goes out without a frame
+        assert!(!batch.is_due(deadline - Duration::from_millis(1), window));
+        assert!(batch.is_due(deadline, window));
+        assert_eq!(batch.take(), Some(Motion::Relative(2.0, 0.0)));
+        assert!(!batch.is_due(deadline, window));
+        assert_eq!(batch.deadline(window), None);
+    }
+
+    #[test]
+    fn test_motion_scale() {
+        assert_eq!(scale_motion(1.0, 1.0, 2.0, 0.0), (2.0, 2.0));
+        assert_eq!(scale_motion(1.0, -3.0, 1.0, 0.0), (1.0, -3.0));
+
+        // sub-pixel deltas add up without being rounded away
+        let mut batch = MotionBatch::default();
+        for _ in 0..1000 {
+            let (dx, dy) = scale_motion(0.01, 0.0, 0.5, 0.0);
+            batch.push(Motion::Relative(dx, dy), MotionPriority::InOrder);
+        }
+        let Some(Motion::Relative(x, _)) = batch.take() else {
+            panic!("no motion batched");
+        };
+        assert!((x - 5.0).abs() < 1e-9);
+
+        // acceleration speeds up fast motion more than slow motion
+        let (slow, _) = scale_motion(1.0, 0.0, 1.0, 1.0);
+        let (fast, _) = scale_motion(10.0, 0.0, 1.0, 1.0);
+        assert!((slow - 1.1).abs() < 1e-9);
+        assert_eq!(fast, 20.0);
+    }
+}
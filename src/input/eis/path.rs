@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Scripted pointer movement along a path of absolute points.
+
+use std::time::{Duration, Instant};
+
+/// Interval between injected motion events while following a path.
+pub const PATH_STEP: Duration = Duration::from_millis(16);
+
+/// A polyline the pointer travels at constant speed over `duration`.
+#[derive(Debug, Clone)]
+pub struct PointerPath {
+    points: Vec<(f64, f64)>,
+    start: Instant,
+    duration: Duration,
+}
+
+impl PointerPath {
+    /// Returns `None` for an empty path.
+    pub fn new(points: Vec<(f64, f64)>, start: Instant, duration: Duration) -> Option<Self> {
+        (!points.is_empty()).then_some(Self {
+            points,
+            start,
+            duration,
+        })
+    }
+
+    /// The position at `now`, and whether the path is complete.
+    pub fn position_at(&self, now: Instant) -> ((f64, f64), bool) {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= self.duration {
+            return (*self.points.last().unwrap(), true);
+        }
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+
+        let segments = self.points.windows(2).map(|segment| {
+            let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+            ((x0, y0), (x1, y1), (x1 - x0).hypot(y1 - y0))
+        });
+        let total: f64 = segments.clone().map(|(_, _, length)| length).sum();
+        if total == 0.0 {
+            return (*self.points.last().unwrap(), false);
+        }
+
+        // walk the segments until the travelled distance is reached
+        let mut remaining = progress * total;
+        for ((x0, y0), (x1, y1), length) in segments {
+            if remaining <= length {
+                let t = if length > 0.0 {
+                    remaining / length
+                } else {
+                    1.0
+                };
+                return ((x0 + (x1 - x0) * t, y0 + (y1 - y0) * t), false);
+            }
+            remaining -= length;
+        }
+        (*self.points.last().unwrap(), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PointerPath;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_pointer_path() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let points = vec![(0.0, 0.0), (100.0, 0.0), (100.0, 100.0)];
+        let path = PointerPath::new(points, start, Duration::from_millis(200)).unwrap();
+
+        assert_eq!(path.position_at(at(0)), ((0.0, 0.0), false));
+        assert_eq!(path.position_at(at(50)), ((50.0, 0.0), false));
+        assert_eq!(path.position_at(at(100)), ((100.0, 0.0), false));
+        assert_eq!(path.position_at(at(150)), ((100.0, 50.0), false));
+        assert_eq!(path.position_at(at(250)), ((100.0, 100.0), true));
+
+        // sampling moves forward along the path and ends on the last point
+        let samples = (0..=20)
+            .map(|step| path.position_at(at(step * 10)))
+            .collect::<Vec<_>>();
+        assert!(samples.windows(2).all(|pair| {
+            let ((x0, y0), _) = pair[0];
+            let ((x1, y1), _) = pair[1];
+            x1 >= x0 && y1 >= y0
+        }));
+        assert_eq!(samples.last(), Some(&((100.0, 100.0), true)));
+
+        assert!(PointerPath::new(Vec::new(), start, Duration::ZERO).is_none());
+    }
+}
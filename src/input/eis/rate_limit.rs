@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Per-connection limit on the rate of injected events, and the limit on the
+//! rate of new connections.
+
+use std::time::{Duration, Instant};
+
+/// Token bucket allowing `rate` events per second with bursts of up to one
+/// second's worth.
+#[derive(Debug, Default)]
+pub struct TokenBucket {
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl TokenBucket {
+    /// Take a token for an event at `now`, returning whether the event may
+    /// pass.
+    pub fn take(&mut self, now: Instant, rate: u32) -> bool {
+        let capacity = f64::from(rate);
+        self.tokens = match self.last {
+            Some(last) => {
+                let refill = now.saturating_duration_since(last).as_secs_f64() * capacity;
+                (self.tokens + refill).min(capacity)
+            }
+            None => capacity,
+        };
+        self.last = Some(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Spaces out accepted connections by a minimum interval.
+#[derive(Debug, Default)]
+pub struct ConnectionPacer {
+    /// Earliest time the next connection may be accepted.
+    next: Option<Instant>,
+}
+
+impl ConnectionPacer {
+    /// Whether a connection arriving at `now` may be accepted right away,
+    /// counting it if so.
+    pub fn admit(&mut self, now: Instant, interval: Duration) -> bool {
+        if self.next.is_some_and(|next| now < next) {
+            return false;
+        }
+        self.next = Some(now + interval);
+        true
+    }
+
+    /// When a connection arriving at `now` may be accepted, queued behind
+    /// those accepted or queued before it. `None` if that is more than
+    /// `max_queued` intervals away, in which case nothing is reserved.
+    pub fn reserve(
+        &mut self,
+        now: Instant,
+        interval: Duration,
+        max_queued: u32,
+    ) -> Option<Instant> {
+        let slot = self.next.map_or(now, |next| next.max(now));
+        if slot > now + interval * max_queued {
+            return None;
+        }
+        self.next = Some(slot + interval);
+        Some(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConnectionPacer, TokenBucket};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::default();
+
+        // a burst beyond the rate is cut off
+        let passed = (0..150).filter(|_| bucket.take(start, 100)).count();
+        assert_eq!(passed, 100);
+        assert!(!bucket.take(start, 100));
+
+        // the bucket refills over time
+        let later = start + Duration::from_millis(100);
+        let passed = (0..50).filter(|_| bucket.take(later, 100)).count();
+        assert_eq!(passed, 10);
+
+        // but never beyond one second's worth
+        let idle = later + Duration::from_secs(10);
+        let passed = (0..150).filter(|_| bucket.take(idle, 100)).count();
+        assert_eq!(passed, 100);
+    }
+
+    #[test]
+    fn test_connection_pacer() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(500);
+
+        // back-to-back connections are refused until the interval passed
+        let mut pacer = ConnectionPacer::default();
+        assert!(pacer.admit(start, interval));
+        assert!(!pacer.admit(start, interval));
+        assert!(!pacer.admit(start + Duration::from_millis(499), interval));
+        assert!(pacer.admit(start + interval, interval));
+
+        // or queued one interval apart
+        let mut pacer = ConnectionPacer::default();
+        let slots = (0..4)
+            .map(|_| pacer.reserve(start, interval, 2))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            slots,
+            vec![
+                Some(start),
+                Some(start + interval),
+                Some(start + interval * 2),
+                None
+            ]
+        );
+        // a refused connection doesn't hold up later ones
+        let later = start + interval * 2;
+        assert_eq!(pacer.reserve(later, interval, 2), Some(later + interval));
+    }
+}
@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Acceleration of scrolls arriving in quick succession.
+
+/// Largest factor a streak of scrolls can reach.
+const MAX_SCROLL_FACTOR: f64 = 4.0;
+
+/// Tracks how many scrolls arrived in quick succession. The streak halves
+/// every `decay` milliseconds without scrolling.
+#[derive(Debug, Default)]
+pub struct ScrollAcceleration {
+    streak: f64,
+    last: Option<u32>,
+}
+
+impl ScrollAcceleration {
+    /// Note a scroll at `time` (in milliseconds) and return the factor to
+    /// scale it by: 1 for an isolated scroll, growing by `acceleration` per
+    /// scroll of the streak.
+    pub fn factor(&mut self, time: u32, acceleration: f64, decay: u32) -> f64 {
+        let decayed = match self.last {
+            Some(last) if decay > 0 => {
+                let elapsed = f64::from(time.wrapping_sub(last));
+                self.streak * 0.5f64.powf(elapsed / f64::from(decay))
+            }
+            _ => 0.0,
+        };
+        self.streak = decayed + 1.0;
+        self.last = Some(time);
+
+        let factor = 1.0 + acceleration.max(0.0) * (self.streak - 1.0);
+        if factor.is_finite() {
+            factor.min(MAX_SCROLL_FACTOR)
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScrollAcceleration;
+
+    #[test]
+    fn test_scroll_acceleration() {
+        let mut rapid = ScrollAcceleration::default();
+        let factors = (0..4)
+            .map(|i| rapid.factor(i * 10, 0.5, 200))
+            .collect::<Vec<_>>();
+        // a streak keeps getting faster
+        assert_eq!(factors[0], 1.0);
+        assert!(factors.windows(2).all(|pair| pair[1] > pair[0]));
+
+        // slow scrolls stay at the base magnitude
+        let mut slow = ScrollAcceleration::default();
+        for i in 0..4 {
+            let factor = slow.factor(i * 5000, 0.5, 200);
+            assert!((factor - 1.0).abs() < 1e-6);
+        }
+
+        // and without acceleration nothing changes
+        let mut off = ScrollAcceleration::default();
+        assert_eq!(off.factor(0, 0.0, 200), 1.0);
+        assert_eq!(off.factor(1, 0.0, 200), 1.0);
+    }
+}
@@ -0,0 +1,592 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Per-connection bookkeeping for EIS clients.
+
+use std::{
+    collections::{HashMap, HashSet},
+    os::{fd::AsRawFd, unix::net::UnixStream},
+    time::{Duration, Instant, SystemTime},
+};
+
+use calloop::RegistrationToken;
+use cosmic_comp_config::XkbConfig;
+use reis::{
+    eis,
+    event::DeviceCapability,
+    request::{Connection, Device, EisRequest, Seat},
+};
+
+use smithay::utils::Point;
+
+use super::{
+    DeviceRegion,
+    budget::RollingBudget,
+    capabilities::DeviceCounts,
+    delay::DelayQueue,
+    emulation::EmulatingDevices,
+    flush::DeferredFlush,
+    frame::FrameBuffer,
+    keys::{HeldKeys, ModifierFeedback},
+    metrics::InjectedCounts,
+    motion::{FractionalMotion, MotionBatch},
+    rate_limit::TokenBucket,
+    scroll::ScrollAcceleration,
+    throttle::LogThrottle,
+    timestamp::TimestampSmoother,
+    touch::TouchFrame,
+};
+use crate::utils::geometry::Global;
+
+/// Identifier assigned to every accepted EIS connection.
+pub type SessionId = u64;
+
+/// `(rules, model, layout, variant, options)` of a session's keymap.
+pub type KeymapInfo = (String, String, String, String, String);
+
+/// Maximum length of an operator-provided session label, in characters.
+const MAX_LABEL_LEN: usize = 64;
+
+/// Maximum length of a stored client name, in characters.
+const MAX_CLIENT_NAME_LEN: usize = 512;
+
+/// Snapshot of a session as reported over D-Bus.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub id: SessionId,
+    pub client_name: String,
+    pub label: String,
+    pub capabilities: Vec<String>,
+    /// Seconds since the UNIX epoch.
+    pub connected_since: u64,
+    /// Injected events by kind, e.g. `key` or `absolute_motion`,
+    /// `key_without_focus` for the keys no surface was focused to receive and
+    /// `rejected_non_finite` for events dropped for NaN/Inf values.
+    pub injected: HashMap<String, u64>,
+}
+
+/// The release of a key or pointer button, by its evdev code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Release {
+    Key(u32),
+    Button(u32),
+}
+
+impl Release {
+    /// The release `request` is, if any.
+    pub fn of(request: &EisRequest) -> Option<Self> {
+        match request {
+            EisRequest::KeyboardKey(event) if event.state != eis::keyboard::KeyState::Press => {
+                Some(Release::Key(event.key))
+            }
+            EisRequest::Button(event) if event.state != eis::button::ButtonState::Press => {
+                Some(Release::Button(event.button))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// State tracked for a single EIS client connection.
+#[derive(Debug)]
+pub struct EisSession {
+    pub id: SessionId,
+    /// Name the client announced during the EIS handshake.
+    pub client_name: String,
+    pub connected_since: SystemTime,
+    /// Whether the client completed its handshake and was announced over D-Bus.
+    pub opened: bool,
+    /// The connection's event source and protocol handle, for tearing it down.
+    pub token: Option<RegistrationToken>,
+    pub connection: Option<Connection>,
+    /// When the client last sent a request, and the timer disconnecting it
+    /// once it stays silent for the idle timeout.
+    pub last_request: Instant,
+    pub idle_timer: Option<RegistrationToken>,
+    /// Human-readable label set by an operator.
+    pub label: Option<String>,
+    /// Only inject while a surface with this app_id holds keyboard focus.
+    pub required_app_id: Option<String>,
+    /// Events dropped because `required_app_id` was not focused.
+    pub dropped_unfocused: u64,
+    /// Events rejected for carrying NaN/Inf values.
+    pub rejected_non_finite: u64,
+    pub non_finite_warning: LogThrottle,
+    pub capability_warning: LogThrottle,
+    /// Budget for injected events and the events dropped for exceeding it.
+    pub rate_limit: TokenBucket,
+    pub rate_limited: u64,
+    pub rate_limit_warning: LogThrottle,
+    /// Events injected over the configured budget window, and the events
+    /// dropped for exceeding the budget.
+    pub budget: RollingBudget,
+    pub over_budget: u64,
+    pub budget_warning: LogThrottle,
+    /// Duplicate of the connection's socket, used to inspect its queues.
+    pub socket: Option<UnixStream>,
+    /// Artificial latency added before injecting input, for testing.
+    pub input_delay: Option<Duration>,
+    /// Input held back by `input_delay`, and the timer injecting it as it
+    /// falls due.
+    pub delayed: DelayQueue<EisRequest>,
+    pub delay_timer: Option<RegistrationToken>,
+    /// Keys pressed by this session and not yet released.
+    pub held_keys: HeldKeys,
+    /// Pointer buttons pressed by this session and not yet released.
+    pub held_buttons: HashSet<u32>,
+    /// Whether the last injected key had a focused surface, and how many keys
+    /// went to no surface at all.
+    pub last_key_had_focus: Option<bool>,
+    pub keys_without_focus: u64,
+    /// The bound keyboard and the modifier state it was last sent.
+    pub keyboard: Option<eis::Keyboard>,
+    pub modifiers: ModifierFeedback,
+    /// Capabilities of the last bind that were granted and refused.
+    pub granted_capabilities: Vec<DeviceCapability>,
+    pub denied_capabilities: Vec<DeviceCapability>,
+    /// Devices bound so far, by kind.
+    pub devices: DeviceCounts,
+    /// Time of the last input event per capability, in milliseconds since
+    /// the UNIX epoch.
+    pub activity: HashMap<String, u64>,
+    /// Correction applied to absolute and touch coordinates.
+    pub calibration: Calibration,
+    /// Output the client's absolute coordinates are relative to.
+    pub target_output: Option<String>,
+    /// Regions announced with the last bound device.
+    pub regions: Vec<DeviceRegion>,
+    /// XKB config of the session's keymap, if it overrides the compositor's.
+    pub xkb_config: Option<XkbConfig>,
+    /// Pointer motion of the client's current frame, and the timer injecting
+    /// it should the frame take longer than the coalescing window.
+    pub motion: MotionBatch,
+    pub motion_timer: Option<RegistrationToken>,
+    /// Seats waiting for a touch frame at the end of the client's frame.
+    pub touch_frame: TouchFrame,
+    /// Streak of quickly successive scrolls, for scroll acceleration.
+    pub scroll_acceleration: ScrollAcceleration,
+    /// Messages waiting for the flush at the end of the wakeup.
+    pub flush: DeferredFlush,
+    /// Input of the current frame held back for canonical ordering.
+    pub frame: FrameBuffer<EisRequest>,
+    /// Regularizes the spacing of the client's frame timestamps.
+    pub timestamps: TimestampSmoother,
+    /// Where the session last moved the pointer, which absolute jumps are
+    /// measured from.
+    pub last_absolute: Option<(f64, f64)>,
+    /// Events injected for the session by kind.
+    pub injected: InjectedCounts,
+    /// Absolute motions clamped or rejected for exceeding the maximum jump.
+    pub limited_jumps: u64,
+    /// Position relative motion continues from while it crosses a gap between
+    /// outputs, and where the pointer was put for it.
+    pub pointer_position: Option<(Point<f64, Global>, Point<f64, Global>)>,
+    /// Fraction of a pixel of relative motion not yet moved, while absolute
+    /// coordinates are rounded to the pixel grid.
+    pub motion_remainder: FractionalMotion,
+    /// Timer moving the pointer along a scripted path.
+    pub pointer_path: Option<RegistrationToken>,
+    /// Announced EIS seats with the compositor seat each stands for.
+    pub eis_seats: Vec<(Seat, String)>,
+    /// Devices created for the client's binds, and those of them it started
+    /// emulating on, the only ones whose input is applied.
+    pub bound_devices: Vec<Device>,
+    pub emulating: EmulatingDevices<Device>,
+    /// Compositor seat the client bound, which receives all its input.
+    pub pinned_seat: Option<String>,
+    /// Seat last picked by position, for events that carry none.
+    pub routed_seat: Option<String>,
+}
+
+impl EisSession {
+    pub fn new(id: SessionId) -> Self {
+        Self {
+            id,
+            client_name: String::new(),
+            connected_since: SystemTime::now(),
+            opened: false,
+            token: None,
+            last_request: Instant::now(),
+            idle_timer: None,
+            connection: None,
+            label: None,
+            required_app_id: None,
+            dropped_unfocused: 0,
+            rejected_non_finite: 0,
+            non_finite_warning: LogThrottle::default(),
+            capability_warning: LogThrottle::default(),
+            rate_limit: TokenBucket::default(),
+            rate_limited: 0,
+            rate_limit_warning: LogThrottle::default(),
+            budget: RollingBudget::default(),
+            over_budget: 0,
+            budget_warning: LogThrottle::default(),
+            socket: None,
+            input_delay: None,
+            delayed: DelayQueue::default(),
+            delay_timer: None,
+            held_keys: HeldKeys::default(),
+            held_buttons: HashSet::new(),
+            last_key_had_focus: None,
+            keys_without_focus: 0,
+            keyboard: None,
+            modifiers: ModifierFeedback::default(),
+            granted_capabilities: Vec::new(),
+            denied_capabilities: Vec::new(),
+            devices: DeviceCounts::default(),
+            activity: HashMap::new(),
+            calibration: Calibration::default(),
+            target_output: None,
+            regions: Vec::new(),
+            xkb_config: None,
+            motion: MotionBatch::default(),
+            motion_timer: None,
+            touch_frame: TouchFrame::default(),
+            scroll_acceleration: ScrollAcceleration::default(),
+            flush: DeferredFlush::default(),
+            frame: FrameBuffer::default(),
+            timestamps: TimestampSmoother::default(),
+            last_absolute: None,
+            injected: InjectedCounts::default(),
+            limited_jumps: 0,
+            pointer_position: None,
+            motion_remainder: FractionalMotion::default(),
+            pointer_path: None,
+            eis_seats: Vec::new(),
+            bound_devices: Vec::new(),
+            emulating: EmulatingDevices::default(),
+            pinned_seat: None,
+            routed_seat: None,
+        }
+    }
+
+    /// Bytes queued on the session's socket as `(unread, unsent)`.
+    ///
+    /// Unread bytes are client requests we haven't processed yet, unsent bytes
+    /// are events the client hasn't read yet. Growing values point at a slow
+    /// client or link.
+    pub fn socket_occupancy(&self) -> Option<(u32, u32)> {
+        let fd = self.socket.as_ref()?.as_raw_fd();
+        let queued = |request| {
+            let mut bytes: libc::c_int = 0;
+            let ret = unsafe { libc::ioctl(fd, request, std::ptr::addr_of_mut!(bytes)) };
+            (ret == 0).then_some(bytes.max(0) as u32)
+        };
+        Some((queued(libc::FIONREAD)?, queued(libc::TIOCOUTQ)?))
+    }
+
+    /// Whether `release` lets go of a key or button the session holds down.
+    pub fn holds(&self, release: Release) -> bool {
+        match release {
+            Release::Key(key) => self.held_keys.contains(key),
+            Release::Button(button) => self.held_buttons.contains(&button),
+        }
+    }
+
+    pub fn note_key_focus(&mut self, focused: bool) {
+        self.last_key_had_focus = Some(focused);
+        if !focused {
+            self.keys_without_focus += 1;
+        }
+    }
+
+    /// Note that an event needing `capability` arrived at `at`.
+    pub fn record_activity(&mut self, capability: DeviceCapability, at: SystemTime) {
+        let millis = at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        self.activity.insert(format!("{capability:?}"), millis);
+    }
+
+    /// The XKB config the session's keymap is compiled from, falling back to
+    /// the compositor's `default`.
+    pub fn keymap_config<'a>(&'a self, default: &'a XkbConfig) -> &'a XkbConfig {
+        self.xkb_config.as_ref().unwrap_or(default)
+    }
+
+    pub fn keymap_info(&self, default: &XkbConfig) -> KeymapInfo {
+        let config = self.keymap_config(default);
+        (
+            config.rules.clone(),
+            config.model.clone(),
+            config.layout.clone(),
+            config.variant.clone(),
+            config.options.clone().unwrap_or_default(),
+        )
+    }
+
+    pub fn info(&self) -> SessionInfo {
+        let mut injected = self.injected.by_name();
+        injected.insert("key_without_focus".to_string(), self.keys_without_focus);
+        injected.insert("rejected_non_finite".to_string(), self.rejected_non_finite);
+        SessionInfo {
+            id: self.id,
+            client_name: self.client_name.clone(),
+            label: self.label.clone().unwrap_or_default(),
+            capabilities: self
+                .granted_capabilities
+                .iter()
+                .map(|capability| format!("{capability:?}"))
+                .collect(),
+            connected_since: self
+                .connected_since
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            injected,
+        }
+    }
+}
+
+/// Per-session correction for systematic misalignment of a remote
+/// touchscreen: positions are scaled, then offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    pub offset_x: f64,
+    pub offset_y: f64,
+    pub scale: f64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            offset_x: 0.0,
+            offset_y: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Calibration {
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            x * self.scale + self.offset_x,
+            y * self.scale + self.offset_y,
+        )
+    }
+
+    /// Whether the calibration is usable for a layout of the given size:
+    /// finite values, a positive scale and offsets no larger than the layout.
+    pub fn fits(&self, width: f64, height: f64) -> bool {
+        self.offset_x.is_finite()
+            && self.offset_y.is_finite()
+            && self.scale.is_finite()
+            && self.scale > 0.0
+            && self.offset_x.abs() <= width
+            && self.offset_y.abs() <= height
+    }
+}
+
+/// Strip control characters and surrounding whitespace from a label and cap
+/// its length. Returns `None` if nothing printable remains.
+pub fn sanitize_label(label: &str) -> Option<String> {
+    let label: String = label
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .chars()
+        .take(MAX_LABEL_LEN)
+        .collect();
+    (!label.is_empty()).then_some(label)
+}
+
+/// Escape control characters in a client-announced name and cap its length,
+/// keeping it intact for diagnostics otherwise.
+pub fn sanitize_client_name(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_control() {
+            sanitized.extend(c.escape_default());
+        } else {
+            sanitized.push(c);
+        }
+    }
+    sanitized.chars().take(MAX_CLIENT_NAME_LEN).collect()
+}
+
+/// The first `len` characters of a client name, for log lines.
+pub fn truncate_client_name(name: &str, len: usize) -> &str {
+    name.char_indices()
+        .nth(len)
+        .map_or(name, |(end, _)| &name[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Calibration, EisSession, sanitize_client_name, sanitize_label, truncate_client_name,
+    };
+    use crate::input::eis::metrics::Injected;
+    use cosmic_comp_config::XkbConfig;
+    use reis::event::DeviceCapability;
+    use std::{
+        io::Write,
+        os::unix::net::UnixStream,
+        time::{Duration, SystemTime},
+    };
+
+    #[test]
+    fn test_session_label() {
+        let mut session = EisSession::new(1);
+        session.label = sanitize_label("  Front desk\n\u{1b}[31m kiosk ");
+        assert_eq!(session.info().label, "Front desk[31m kiosk");
+
+        assert_eq!(sanitize_label(&"x".repeat(200)).unwrap().len(), 64);
+        assert_eq!(sanitize_label(" \t\n"), None);
+    }
+
+    #[test]
+    fn test_injected_counts() {
+        let mut session = EisSession::new(1);
+        for kind in [
+            Injected::Key,
+            Injected::Key,
+            Injected::Button,
+            Injected::AbsoluteMotion,
+            Injected::Touch,
+        ] {
+            session.injected.count(kind);
+        }
+
+        let injected = session.info().injected;
+        assert_eq!(injected["key"], 2);
+        assert_eq!(injected["button"], 1);
+        assert_eq!(injected["absolute_motion"], 1);
+        assert_eq!(injected["touch"], 1);
+        // kinds without events are reported too
+        assert_eq!(injected["motion"], 0);
+        assert_eq!(injected["scroll"], 0);
+    }
+
+    #[test]
+    fn test_client_name() {
+        let long = "org.example.".repeat(25);
+        assert_eq!(long.len(), 300);
+        let stored = sanitize_client_name(&long);
+        assert_eq!(stored, long);
+        assert_eq!(truncate_client_name(&stored, 128), &long[..128]);
+        assert_eq!(truncate_client_name("short", 128), "short");
+
+        // escaped rather than passed on to logs and D-Bus
+        assert_eq!(
+            sanitize_client_name("rdp\nclient\u{1b}"),
+            "rdp\\nclient\\u{1b}"
+        );
+        assert_eq!(sanitize_client_name(&"é".repeat(600)).chars().count(), 512);
+    }
+
+    #[test]
+    fn test_keymap_info() {
+        let compositor = XkbConfig {
+            rules: "evdev".into(),
+            model: "pc105".into(),
+            layout: "us".into(),
+            options: Some("compose:ralt".into()),
+            ..XkbConfig::default()
+        };
+        let mut session = EisSession::new(1);
+        assert_eq!(
+            session.keymap_info(&compositor),
+            (
+                "evdev".into(),
+                "pc105".into(),
+                "us".into(),
+                String::new(),
+                "compose:ralt".into()
+            )
+        );
+
+        session.xkb_config = Some(XkbConfig {
+            layout: "de".into(),
+            variant: "nodeadkeys".into(),
+            options: None,
+            ..compositor.clone()
+        });
+        assert_eq!(
+            session.keymap_info(&compositor),
+            (
+                "evdev".into(),
+                "pc105".into(),
+                "de".into(),
+                "nodeadkeys".into(),
+                String::new()
+            )
+        );
+    }
+
+    #[test]
+    fn test_calibration() {
+        let calibration = Calibration {
+            offset_x: 5.0,
+            offset_y: 5.0,
+            ..Default::default()
+        };
+        assert_eq!(calibration.apply(0.0, 0.0), (5.0, 5.0));
+        assert!(calibration.fits(1920.0, 1080.0));
+
+        let scaled = Calibration {
+            scale: 0.5,
+            ..calibration
+        };
+        assert_eq!(scaled.apply(100.0, 50.0), (55.0, 30.0));
+
+        // offsets beyond the layout or a degenerate scale are refused
+        let off_layout = Calibration {
+            offset_x: 2000.0,
+            ..calibration
+        };
+        assert!(!off_layout.fits(1920.0, 1080.0));
+        assert!(
+            !Calibration {
+                scale: 0.0,
+                ..calibration
+            }
+            .fits(1920.0, 1080.0)
+        );
+    }
+
+    #[test]
+    fn test_socket_occupancy() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let mut session = EisSession::new(1);
+        assert_eq!(session.socket_occupancy(), None);
+
+        session.socket = Some(server);
+        let unread = |session: &EisSession| session.socket_occupancy().map(|(unread, _)| unread);
+        assert_eq!(unread(&session), Some(0));
+        client.write_all(&[0; 64]).unwrap();
+        assert_eq!(unread(&session), Some(64));
+    }
+
+    #[test]
+    fn test_key_focus() {
+        let mut session = EisSession::new(1);
+        assert_eq!(session.last_key_had_focus, None);
+
+        // a key nobody was focused to receive
+        session.note_key_focus(false);
+        assert_eq!(session.last_key_had_focus, Some(false));
+
+        session.note_key_focus(true);
+        assert_eq!(session.last_key_had_focus, Some(true));
+        assert_eq!(session.info().injected["key_without_focus"], 1);
+    }
+
+    #[test]
+    fn test_capability_activity() {
+        let mut session = EisSession::new(1);
+        assert!(session.activity.is_empty());
+
+        let key_at = SystemTime::now();
+        session.record_activity(DeviceCapability::Keyboard, key_at);
+        session.record_activity(
+            DeviceCapability::Pointer,
+            key_at + Duration::from_millis(20),
+        );
+
+        let key = session.activity["Keyboard"];
+        let motion = session.activity["Pointer"];
+        assert!(key > 0 && motion > 0);
+        assert_eq!(motion - key, 20);
+        assert!(!session.activity.contains_key("Touch"));
+    }
+}
@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Cache for `State::surface_under` lookups during injected pointer motion.
+
+use smithay::utils::{IsAlive, Point, Rectangle};
+
+use crate::utils::geometry::Global;
+
+/// Remembers the last surface-under lookup so that small pointer movements
+/// don't walk the whole scene again.
+///
+/// Entries are only reused on the same output while its scene, a fingerprint
+/// of what is stacked where, is unchanged, within the bounds of the found
+/// target while it is still alive, and until [`SurfaceUnderCache::invalidate`]
+/// is called.
+#[derive(Debug)]
+pub struct SurfaceUnderCache<T> {
+    entry: Option<Entry<T>>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    position: Point<f64, Global>,
+    output: String,
+    scene: u64,
+    under: Option<(T, Point<f64, Global>)>,
+    bounds: Option<Rectangle<f64, Global>>,
+}
+
+impl<T> Default for SurfaceUnderCache<T> {
+    fn default() -> Self {
+        Self {
+            entry: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+impl<T: IsAlive + Clone> SurfaceUnderCache<T> {
+    /// Return the cached result if `position` is within `threshold` logical
+    /// pixels of the last lookup and still inside the target found there,
+    /// counting the hit or miss.
+    pub fn lookup(
+        &mut self,
+        position: Point<f64, Global>,
+        output: &str,
+        scene: u64,
+        threshold: f64,
+    ) -> Option<Option<(T, Point<f64, Global>)>> {
+        let hit = self
+            .entry
+            .as_ref()
+            .filter(|entry| {
+                entry.output == output
+                    && entry.scene == scene
+                    && distance(entry.position, position) <= threshold
+                    && entry.bounds.is_some_and(|bounds| bounds.contains(position))
+                    && entry
+                        .under
+                        .as_ref()
+                        .is_some_and(|(target, _)| target.alive())
+            })
+            .map(|entry| entry.under.clone());
+
+        if hit.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        hit
+    }
+
+    /// Remember the lookup at `position`, with the `bounds` of the target it
+    /// found. Without bounds the entry is never reused.
+    pub fn store(
+        &mut self,
+        position: Point<f64, Global>,
+        output: String,
+        scene: u64,
+        under: Option<(T, Point<f64, Global>)>,
+        bounds: Option<Rectangle<f64, Global>>,
+    ) {
+        self.entry = Some(Entry {
+            position,
+            output,
+            scene,
+            under,
+            bounds,
+        });
+    }
+
+    pub fn invalidate(&mut self) {
+        self.entry = None;
+    }
+
+    /// Drop the entry if its target is `stale`.
+    pub fn invalidate_if(&mut self, stale: impl FnOnce(&T) -> bool) {
+        if self
+            .entry
+            .as_ref()
+            .and_then(|entry| entry.under.as_ref())
+            .is_some_and(|(target, _)| stale(target))
+        {
+            self.entry = None;
+        }
+    }
+
+    /// Fraction of lookups served from the cache.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+fn distance(a: Point<f64, Global>, b: Point<f64, Global>) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SurfaceUnderCache;
+    use smithay::utils::{IsAlive, Rectangle};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Target(bool);
+
+    impl IsAlive for Target {
+        fn alive(&self) -> bool {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_surface_cache() {
+        let bounds = Some(Rectangle::new((0., 0.).into(), (12., 100.).into()));
+        let mut cache = SurfaceUnderCache::default();
+        assert_eq!(cache.lookup((10., 10.).into(), "DP-1", 1, 2.0), None);
+        cache.store(
+            (10., 10.).into(),
+            "DP-1".into(),
+            1,
+            Some((Target(true), (0., 0.).into())),
+            bounds,
+        );
+
+        // small repeated motions hit
+        for step in [0.5, 1.0, 1.5] {
+            let under = cache.lookup((10. + step, 10.).into(), "DP-1", 1, 2.0);
+            assert_eq!(under, Some(Some((Target(true), (0., 0.).into()))));
+        }
+        assert_eq!(cache.hits, 3);
+
+        // moving past the threshold or onto another output misses
+        assert_eq!(cache.lookup((10., 13.).into(), "DP-1", 1, 2.0), None);
+        assert_eq!(cache.lookup((10., 10.).into(), "DP-2", 1, 2.0), None);
+        // as does leaving the target within the threshold
+        assert_eq!(cache.lookup((12., 10.).into(), "DP-1", 1, 2.0), None);
+        // or any change to the scene, like a window being raised
+        assert_eq!(cache.lookup((10., 10.).into(), "DP-1", 2, 2.0), None);
+
+        // a commit of another surface keeps the entry, one of the target drops it
+        cache.invalidate_if(|target| !target.0);
+        assert!(cache.lookup((10., 10.).into(), "DP-1", 1, 2.0).is_some());
+        cache.invalidate_if(|target| target.0);
+        assert_eq!(cache.lookup((10., 10.).into(), "DP-1", 1, 2.0), None);
+
+        // the cached surface going away misses
+        cache.store(
+            (10., 10.).into(),
+            "DP-1".into(),
+            1,
+            Some((Target(false), (0., 0.).into())),
+            bounds,
+        );
+        assert_eq!(cache.lookup((10., 10.).into(), "DP-1", 1, 2.0), None);
+
+        // and so does a lookup that found nothing to bound it
+        cache.store((10., 10.).into(), "DP-1".into(), 1, None, None);
+        assert_eq!(cache.lookup((10., 10.).into(), "DP-1", 1, 2.0), None);
+        assert_eq!((cache.hits, cache.misses), (4, 8));
+    }
+}
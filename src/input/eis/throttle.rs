@@ -0,0 +1,51 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::time::{Duration, Instant};
+
+/// Rate-limits a repeated log message, counting the occurrences suppressed in
+/// between.
+#[derive(Debug, Default)]
+pub struct LogThrottle {
+    last: Option<Instant>,
+    suppressed: u64,
+}
+
+impl LogThrottle {
+    /// Returns `Some(suppressed)` if the message should be logged at `now`,
+    /// or `None` if it was logged less than `interval` ago.
+    pub fn check(&mut self, now: Instant, interval: Duration) -> Option<u64> {
+        match self.last {
+            Some(last) if now.saturating_duration_since(last) < interval => {
+                self.suppressed += 1;
+                None
+            }
+            _ => {
+                self.last = Some(now);
+                Some(std::mem::take(&mut self.suppressed))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogThrottle;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_log_throttle() {
+        let interval = Duration::from_secs(5);
+        let start = Instant::now();
+        let mut throttle = LogThrottle::default();
+
+        assert_eq!(throttle.check(start, interval), Some(0));
+        for i in 1..=10 {
+            assert_eq!(
+                throttle.check(start + Duration::from_millis(i), interval),
+                None
+            );
+        }
+        assert_eq!(throttle.check(start + interval, interval), Some(10));
+        assert_eq!(throttle.check(start + interval, interval), None);
+    }
+}
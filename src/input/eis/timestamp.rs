@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Smoothing of the spacing between a client's frame timestamps.
+
+/// Gap between frames, in milliseconds, after which motion counts as having
+/// paused and smoothing starts over.
+const MAX_FRAME_GAP_MS: i32 = 250;
+
+/// Regularizes jittery frame timestamps towards the client's average frame
+/// interval.
+///
+/// Each timestamp is predicted from the previous output and the average
+/// interval, then pulled towards the client's actual time, so the output
+/// stays within half a frame of the client clock without drifting. Outputs
+/// never go backwards.
+#[derive(Debug, Default)]
+pub struct TimestampSmoother {
+    last: Option<Last>,
+}
+
+#[derive(Debug)]
+struct Last {
+    time: u32,
+    output: u32,
+    /// Difference between the output and the client's time.
+    offset: f64,
+    interval: f64,
+}
+
+impl TimestampSmoother {
+    /// Smooth the frame time `time` in milliseconds. A `smoothing` of 0
+    /// passes timestamps through, values towards 1 regularize harder.
+    pub fn smooth(&mut self, time: u32, smoothing: f64) -> u32 {
+        let smoothing = if smoothing.is_finite() {
+            smoothing.clamp(0.0, 0.99)
+        } else {
+            0.0
+        };
+        let delta = self
+            .last
+            .as_ref()
+            .map(|last| time.wrapping_sub(last.time) as i32)
+            .filter(|delta| (1..=MAX_FRAME_GAP_MS).contains(delta));
+        let (Some(delta), Some(last)) = (delta, self.last.as_ref()) else {
+            self.last = Some(Last {
+                time,
+                output: time,
+                offset: 0.0,
+                interval: 0.0,
+            });
+            return time;
+        };
+
+        let delta = f64::from(delta);
+        let interval = if last.interval > 0.0 {
+            last.interval + (1.0 - smoothing) * (delta - last.interval)
+        } else {
+            delta
+        };
+        // correct jitter of up to half a frame, beyond that follow the client
+        let bound = interval / 2.0;
+        let offset = (smoothing * (last.offset + interval - delta)).clamp(-bound, bound);
+        let mut output = time.wrapping_add_signed(offset.round() as i32);
+        // keep the order of the frames
+        if (output.wrapping_sub(last.output) as i32) < 0 {
+            output = last.output;
+        }
+
+        self.last = Some(Last {
+            time,
+            output,
+            offset,
+            interval,
+        });
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimestampSmoother;
+
+    #[test]
+    fn test_timestamp_smoothing() {
+        // frames every 16ms on average, arriving 8 and 24ms apart
+        let times = (0..100u32)
+            .scan(1000u32, |time, i| {
+                *time += if i % 2 == 0 { 8 } else { 24 };
+                Some(*time)
+            })
+            .collect::<Vec<_>>();
+
+        let mut smoother = TimestampSmoother::default();
+        let smoothed = times
+            .iter()
+            .map(|time| smoother.smooth(*time, 0.9))
+            .collect::<Vec<_>>();
+        for pair in smoothed[50..].windows(2) {
+            let spacing = pair[1] - pair[0];
+            assert!((15..=17).contains(&spacing), "spacing {spacing}");
+        }
+        // staying within half of the longest frame of the client's clock
+        for (time, smoothed) in times.iter().zip(&smoothed) {
+            assert!(time.abs_diff(*smoothed) <= 12);
+        }
+
+        // disabled, timestamps pass through
+        let mut smoother = TimestampSmoother::default();
+        for time in &times {
+            assert_eq!(smoother.smooth(*time, 0.0), *time);
+        }
+
+        // a pause starts over rather than smoothing across it
+        let mut smoother = TimestampSmoother::default();
+        smoother.smooth(1000, 0.9);
+        smoother.smooth(1016, 0.9);
+        assert_eq!(smoother.smooth(5000, 0.9), 5000);
+    }
+}
@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Translation of client touch ids into compositor-wide touch slots, and
+//! batching of touch frames up to the client's frame boundary.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use super::SessionId;
+
+/// First slot handed out to EIS clients, keeping them clear of the small slot
+/// numbers used by physical touchscreens.
+const SLOT_BASE: u32 = 1 << 16;
+
+/// Maps each session's touch ids onto distinct touch slots, so concurrent
+/// sessions reusing the same ids don't collide in the seat's touch state.
+#[derive(Debug, Default)]
+pub struct TouchSlots {
+    slots: HashMap<(SessionId, u32), Slot>,
+}
+
+#[derive(Debug)]
+struct Slot {
+    slot: u32,
+    /// Last down or motion event for the touch.
+    last_seen: Instant,
+}
+
+impl TouchSlots {
+    /// Return the slot for `touch_id`, allocating the lowest free one if the
+    /// touch is new.
+    pub fn allocate(&mut self, session: SessionId, touch_id: u32, now: Instant) -> u32 {
+        if let Some(slot) = self.refresh(session, touch_id, now) {
+            return slot;
+        }
+        let slot = (SLOT_BASE..)
+            .find(|slot| !self.slots.values().any(|used| used.slot == *slot))
+            .unwrap();
+        self.slots.insert(
+            (session, touch_id),
+            Slot {
+                slot,
+                last_seen: now,
+            },
+        );
+        slot
+    }
+
+    /// Return the slot for an active touch, noting activity on it at `now`.
+    pub fn refresh(&mut self, session: SessionId, touch_id: u32, now: Instant) -> Option<u32> {
+        let slot = self.slots.get_mut(&(session, touch_id))?;
+        slot.last_seen = now;
+        Some(slot.slot)
+    }
+
+    pub fn release(&mut self, session: SessionId, touch_id: u32) -> Option<u32> {
+        self.slots
+            .remove(&(session, touch_id))
+            .map(|slot| slot.slot)
+    }
+
+    /// Free the slots of touches without activity for `timeout`, returning
+    /// them so they can be lifted.
+    pub fn expire(&mut self, now: Instant, timeout: Duration) -> Vec<u32> {
+        let mut expired = Vec::new();
+        self.slots.retain(|_, slot| {
+            if now.saturating_duration_since(slot.last_seen) >= timeout {
+                expired.push(slot.slot);
+                false
+            } else {
+                true
+            }
+        });
+        expired
+    }
+
+    /// When the longest-idle touch last saw activity.
+    pub fn oldest_activity(&self) -> Option<Instant> {
+        self.slots.values().map(|slot| slot.last_seen).min()
+    }
+
+    /// Free every slot held by `session`, returning them.
+    pub fn release_session(&mut self, session: SessionId) -> Vec<u32> {
+        let mut released = Vec::new();
+        self.slots.retain(|(owner, _), slot| {
+            if *owner == session {
+                released.push(slot.slot);
+                false
+            } else {
+                true
+            }
+        });
+        released
+    }
+}
+
+/// Seats that got touch events during the current EIS frame, so a single
+/// touch frame ends all touch points the client changed at once.
+#[derive(Debug, Default)]
+pub struct TouchFrame {
+    seats: Vec<String>,
+}
+
+impl TouchFrame {
+    /// Note touch events on `seat`. Returns whether the frame was empty
+    /// before.
+    pub fn touched(&mut self, seat: &str) -> bool {
+        let first = self.seats.is_empty();
+        if !self.seats.iter().any(|touched| touched == seat) {
+            self.seats.push(seat.to_string());
+        }
+        first
+    }
+
+    /// Take the seats needing a touch frame.
+    pub fn take(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.seats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TouchFrame, TouchSlots};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_touch_frame() {
+        let mut frame = TouchFrame::default();
+        // two fingers down in one EIS frame get one touch frame
+        assert!(frame.touched("seat0"));
+        assert!(!frame.touched("seat0"));
+        assert_eq!(frame.take(), vec!["seat0".to_string()]);
+        assert!(frame.take().is_empty());
+
+        // a frame for each seat touched
+        frame.touched("seat0");
+        frame.touched("seat1");
+        assert_eq!(frame.take().len(), 2);
+    }
+
+    #[test]
+    fn test_touch_slot_namespacing() {
+        let now = Instant::now();
+        let mut slots = TouchSlots::default();
+        let first = slots.allocate(1, 0, now);
+        let second = slots.allocate(2, 0, now);
+        assert_ne!(first, second);
+        assert_eq!(slots.allocate(1, 0, now), first);
+
+        // lifting one session's touch leaves the other moving
+        assert_eq!(slots.release(1, 0), Some(first));
+        assert_eq!(slots.refresh(1, 0, now), None);
+        assert_eq!(slots.refresh(2, 0, now), Some(second));
+
+        // freed slots are reused
+        assert_eq!(slots.allocate(3, 5, now), first);
+        assert_eq!(slots.release_session(2), vec![second]);
+        assert_eq!(slots.release(2, 0), None);
+    }
+
+    #[test]
+    fn test_touch_timeout() {
+        let timeout = Duration::from_millis(500);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut slots = TouchSlots::default();
+        let stuck = slots.allocate(1, 0, at(0));
+        let moving = slots.allocate(1, 1, at(0));
+
+        // motion keeps a touch alive
+        slots.refresh(1, 1, at(400));
+        assert!(slots.expire(at(499), timeout).is_empty());
+        assert_eq!(slots.expire(at(500), timeout), vec![stuck]);
+        assert_eq!(slots.refresh(1, 0, at(500)), None);
+
+        assert_eq!(slots.oldest_activity(), Some(at(400)));
+        assert_eq!(slots.expire(at(900), timeout), vec![moving]);
+        assert_eq!(slots.oldest_activity(), None);
+    }
+}
@@ -165,6 +165,9 @@ pub fn run(hooks: crate::hooks::Hooks) -> Result<(), Box<dyn Error>> {
         // shall we shut down?
         if state.common.should_stop {
             info!("Shutting down");
+            if let Some(eis_state) = state.common.eis_state.as_mut() {
+                eis_state.shutdown();
+            }
             state.common.event_loop_signal.stop();
             state.common.event_loop_signal.wakeup();
             return;
@@ -1508,7 +1508,10 @@ impl Common {
             with_states(surface, |states| {
                 surface_primary_scanout_output(surface, states).is_some()
             })
-        });
+        }) || self
+            .eis_state
+            .as_ref()
+            .is_some_and(|eis_state| eis_state.inhibits_idle());
         self.idle_notifier_state.set_is_inhibited(is_inhibited);
     }
 
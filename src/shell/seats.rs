@@ -75,6 +75,11 @@ impl Seats {
         self.last_active.as_ref().expect("No seat?")
     }
 
+    /// The last active seat, or `None` while there are no seats.
+    pub fn try_last_active(&self) -> Option<&Seat<State>> {
+        self.last_active.as_ref()
+    }
+
     pub fn update_last_active(&mut self, seat: &Seat<State>) {
         self.last_active = Some(seat.clone());
     }
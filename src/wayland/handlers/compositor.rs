@@ -266,6 +266,11 @@ impl CompositorHandler for State {
         // and refresh smithays internal state
         self.common.on_commit(surface);
 
+        // the commit may change what injected remote input would hit
+        if let Some(eis_state) = self.common.eis_state.as_mut() {
+            eis_state.surface_committed(surface);
+        }
+
         // handle initial configure events and map windows if necessary
         let mapped = self.send_initial_configure_and_map(surface);
 